@@ -0,0 +1,454 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use pcap::Device;
+use crate::{ErrorNetworkAnalyser, Filter, NetworkAnalyser, ReportFormat, validate_bpf_expression};
+use crate::report_sink::with_extension;
+use crate::reassembly::{DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP};
+
+/// Default number of seconds between two updates of the report
+pub const DEFAULT_TIME_INTERVAL: usize = 5;
+/// Default name of the report file
+pub const DEFAULT_REPORT_FILE: &str = "report.txt";
+
+/// Builder of a NetworkAnalyser, to configure it without the interactive prompts of 'init()'.
+/// All the fields have the same default values of 'NetworkAnalyser::new()', except the final report file,
+/// which by default is named adding "final_" before the name of the report file (in the same directory).
+/// The configuration is checked by 'build()', that returns all the errors found in a single ErrorNetworkAnalyser::ErrorConfig.
+/// - *interfaces*: names of the network interfaces to sniff (none: the first one found)
+/// - *capture_file*: if set, the packets are read from this pcap/pcapng file instead of being sniffed
+/// - *time_interval*: number of seconds between two updates of the report
+/// - *filename*, *final_filename*: paths of the report and of the final report (.txt, .csv, .json, .ndjson). Each report format replaces their extension with its own
+/// - *report_formats*: formats in which the reports are written (none: the format given by the extension of the report file)
+/// - *database*: if set, SQLite database (.db, .sqlite) where the conversations of every time interval are stored (feature "sqlite")
+/// - *filter*: filter of the packets shown in the report
+/// - *bpf_expression*: raw BPF expression (pcap-filter syntax) applied to the capture in addition to the filter
+/// - *savefile*, *savefile_only_filtered*, *savefile_max_size*, *savefile_rotation*: pcap savefile where the packets are saved, whether only the packets accepted by the filter are saved, size (bytes) and time (seconds) of rotation
+/// - *bidirectional*: whether the packets A->B and B->A are aggregated in the same conversation
+/// - *vlan_conversations*: whether the same endpoints talking on different VLANs form different conversations
+/// - *reassembly_flow_cap*, *reassembly_total_cap*: maximum memory (bytes) for the reassembly of a TCP stream and of all of them
+/// - *duration*: if set, the sniffing ends after this number of seconds (of capture time, when reading a capture file)
+/// - *packet_count*: if set, the sniffing ends after this number of packets
+/// - *metrics_address*: if set, address (ip:port) on which the metrics are served in the Prometheus text format, on '/metrics'
+#[derive(Debug, Clone)]
+pub struct NetworkAnalyserBuilder {
+    interfaces: Vec<String>,
+    capture_file: Option<String>,
+    time_interval: usize,
+    filename: String,
+    final_filename: Option<String>,
+    report_formats: Option<Vec<ReportFormat>>,
+    database: Option<String>,
+    filter: Filter,
+    bpf_expression: Option<String>,
+    savefile: Option<String>,
+    savefile_only_filtered: bool,
+    savefile_max_size: Option<u64>,
+    savefile_rotation: Option<u64>,
+    bidirectional: bool,
+    vlan_conversations: bool,
+    reassembly_flow_cap: usize,
+    reassembly_total_cap: usize,
+    duration: Option<u64>,
+    packet_count: Option<usize>,
+    metrics_address: Option<String>,
+}
+
+impl Default for NetworkAnalyserBuilder {
+    fn default() -> Self {
+        return NetworkAnalyserBuilder::new();
+    }
+}
+
+impl NetworkAnalyserBuilder {
+    /// Create a new builder with the default configuration
+    pub fn new() -> Self {
+        return NetworkAnalyserBuilder {
+            interfaces: vec![],
+            capture_file: None,
+            time_interval: DEFAULT_TIME_INTERVAL,
+            filename: DEFAULT_REPORT_FILE.to_string(),
+            final_filename: None,
+            report_formats: None,
+            database: None,
+            filter: Filter::new(),
+            bpf_expression: None,
+            savefile: None,
+            savefile_only_filtered: true,
+            savefile_max_size: None,
+            savefile_rotation: None,
+            bidirectional: false,
+            vlan_conversations: false,
+            reassembly_flow_cap: DEFAULT_FLOW_CAP,
+            reassembly_total_cap: DEFAULT_TOTAL_CAP,
+            duration: None,
+            packet_count: None,
+            metrics_address: None,
+        };
+    }
+
+    /// Add a network interface to sniff, given its name
+    pub fn interface(mut self, name: &str) -> Self {
+        self.interfaces.push(name.to_string());
+        return self;
+    }
+    /// Read the packets from a capture file (.pcap, .pcapng) instead of sniffing the network interfaces
+    pub fn capture_file(mut self, path: &str) -> Self {
+        self.capture_file = Some(path.to_string());
+        return self;
+    }
+    /// Set the number of seconds between two updates of the report
+    pub fn time_interval(mut self, secs: usize) -> Self {
+        self.time_interval = secs;
+        return self;
+    }
+    /// Set the path of the report file (.txt, .csv, .json, .ndjson)
+    pub fn report_file(mut self, path: &str) -> Self {
+        self.filename = path.to_string();
+        return self;
+    }
+    /// Set the path of the final report file (.txt, .csv, .json, .ndjson)
+    pub fn final_report_file(mut self, path: &str) -> Self {
+        self.final_filename = Some(path.to_string());
+        return self;
+    }
+    /// Set the formats in which the reports are written (each of them in files with its extension)
+    pub fn report_formats(mut self, formats: &[ReportFormat]) -> Self {
+        self.report_formats = Some(formats.to_vec());
+        return self;
+    }
+    /// Store the conversations of every time interval in a SQLite database, appended to the ones of the previous sessions.
+    /// It requires the feature "sqlite"
+    pub fn database(mut self, path: &str) -> Self {
+        self.database = Some(path.to_string());
+        return self;
+    }
+    /// Set the filter of the packets shown in the report
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        return self;
+    }
+    /// Set a raw BPF expression (pcap-filter syntax), applied to the capture in addition to the filter
+    pub fn bpf_expression(mut self, expression: &str) -> Self {
+        self.bpf_expression = Some(expression.to_string());
+        return self;
+    }
+    /// Save the packets in a pcap savefile: all of them, or only the ones accepted by the filter
+    pub fn savefile(mut self, path: &str, only_filtered: bool) -> Self {
+        self.savefile = Some(path.to_string());
+        self.savefile_only_filtered = only_filtered;
+        return self;
+    }
+    /// Rotate the savefile when it would exceed this size (bytes)
+    pub fn savefile_max_size(mut self, bytes: u64) -> Self {
+        self.savefile_max_size = Some(bytes);
+        return self;
+    }
+    /// Rotate the savefile every 'secs' seconds of capture time
+    pub fn savefile_rotation(mut self, secs: u64) -> Self {
+        self.savefile_rotation = Some(secs);
+        return self;
+    }
+    /// Set whether the packets A->B and B->A are aggregated in the same conversation
+    pub fn bidirectional(mut self, bidirectional: bool) -> Self {
+        self.bidirectional = bidirectional;
+        return self;
+    }
+    /// Set whether the same endpoints talking on different VLANs form different conversations
+    pub fn vlan_conversations(mut self, vlan_conversations: bool) -> Self {
+        self.vlan_conversations = vlan_conversations;
+        return self;
+    }
+    /// Set the maximum memory (bytes) for the reassembly of a single TCP stream and of all of them
+    pub fn reassembly_memory(mut self, flow_cap: usize, total_cap: usize) -> Self {
+        self.reassembly_flow_cap = flow_cap;
+        self.reassembly_total_cap = total_cap;
+        return self;
+    }
+    /// End the sniffing after 'secs' seconds (of capture time, when reading a capture file)
+    pub fn duration(mut self, secs: u64) -> Self {
+        self.duration = Some(secs);
+        return self;
+    }
+    /// End the sniffing after 'count' packets
+    pub fn packet_count(mut self, count: usize) -> Self {
+        self.packet_count = Some(count);
+        return self;
+    }
+    /// Serve the metrics of the sniffing on 'http://<address>/metrics' (e.g. "127.0.0.1:9100"), in the Prometheus text format
+    pub fn metrics_address(mut self, address: &str) -> Self {
+        self.metrics_address = Some(address.to_string());
+        return self;
+    }
+
+    /// It checks the configuration and creates the NetworkAnalyser.
+    /// If the configuration is not valid it returns an ErrorNetworkAnalyser::ErrorConfig listing all the errors found (one for each line).
+    pub fn build(self) -> Result<NetworkAnalyser, ErrorNetworkAnalyser> {
+        let mut errors: Vec<String> = vec![];
+
+        // Source of the packets
+        let mut devices = vec![];
+        match &self.capture_file {
+            Some(path) => {
+                if !self.interfaces.is_empty() {
+                    errors.push("Cannot sniff network interfaces while reading a capture file".to_string());
+                }
+                if !Path::new(path).is_file() {
+                    errors.push(format!("No such capture file: '{}'", path));
+                }
+            }
+            None => match Device::list() {
+                Ok(list) => {
+                    if self.interfaces.is_empty() {
+                        match list.into_iter().next() {
+                            Some(device) => devices.push(device),
+                            None => errors.push("No network interface found".to_string()),
+                        }
+                    } else {
+                        for name in &self.interfaces {
+                            if devices.iter().any(|device: &Device| &device.name == name) {
+                                continue;
+                            }
+                            match list.iter().find(|device| &device.name == name) {
+                                Some(device) => devices.push(device.clone()),
+                                None => errors.push(format!("No such network interface: '{}'", name)),
+                            }
+                        }
+                    }
+                }
+                Err(err) => errors.push(format!("Cannot list the network interfaces: {}", err)),
+            }
+        }
+
+        if self.time_interval == 0 {
+            errors.push("The time interval must not be zero".to_string());
+        }
+
+        // Report files
+        let final_filename = match &self.final_filename {
+            Some(path) => path.clone(),
+            None => get_final_file_name(&self.filename),
+        };
+        let report_extensions = ["txt", "csv", "json", "ndjson"];
+        for (path, description) in [(&self.filename, "report"), (&final_filename, "final report")] {
+            if let Err(err) = validate_output_file(path, &report_extensions) {
+                errors.push(format!("Invalid {} file: {}", description, err));
+            }
+        }
+        let report_formats = match &self.report_formats {
+            Some(formats) => {
+                let mut unique_formats = vec![];
+                for format in formats {
+                    if !unique_formats.contains(format) {
+                        unique_formats.push(*format);
+                    }
+                }
+                unique_formats
+            }
+            None => {
+                let extension = Path::new(&self.filename).extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+                vec![ReportFormat::from_extension(extension).unwrap_or(ReportFormat::Table)]
+            }
+        };
+        if report_formats.is_empty() {
+            errors.push("At least a report format must be selected".to_string());
+        }
+        if report_formats.iter().any(|format| with_extension(&self.filename, format.extension()) == with_extension(&final_filename, format.extension())) {
+            errors.push("The report and the final report must be written in different files".to_string());
+        }
+
+        // Filter
+        if let Some(vlan_id) = self.filter.get_vlan_id() {
+            if vlan_id > 4095 {
+                errors.push(format!("Invalid VLAN ID in the filter: {} (it must be between 0 and 4095)", vlan_id));
+            }
+        }
+        if let Some(expression) = &self.bpf_expression {
+            if let Err(err) = validate_bpf_expression(expression) {
+                errors.push(format!("Invalid BPF expression '{}': {}", expression, err));
+            }
+        }
+
+        // Database
+        if let Some(database) = &self.database {
+            if !cfg!(feature = "sqlite") {
+                errors.push("The database is not supported: the network analyser was built without the feature \"sqlite\"".to_string());
+            } else if let Err(err) = validate_output_file(database, &["db", "sqlite"]) {
+                errors.push(format!("Invalid database: {}", err));
+            }
+        }
+
+        // Savefile
+        if let Some(savefile) = &self.savefile {
+            if let Err(err) = validate_output_file(savefile, &["pcap"]) {
+                errors.push(format!("Invalid savefile: {}", err));
+            }
+        }
+        if self.savefile_max_size == Some(0) || self.savefile_rotation == Some(0) {
+            errors.push("The rotation size and time of the savefile must not be zero".to_string());
+        }
+        if self.savefile.is_none() && (self.savefile_max_size.is_some() || self.savefile_rotation.is_some()) {
+            errors.push("The rotation of the savefile is set, but the savefile is not".to_string());
+        }
+
+        // Reassembly memory
+        if self.reassembly_flow_cap == 0 || self.reassembly_total_cap == 0 {
+            errors.push("The memory for the TCP reassembly must not be zero".to_string());
+        } else if self.reassembly_flow_cap > self.reassembly_total_cap {
+            errors.push("The memory for the reassembly of a TCP stream must not exceed the one for all the streams".to_string());
+        }
+
+        // Limits
+        if self.duration == Some(0) || self.packet_count == Some(0) {
+            errors.push("The duration and the packet count must not be zero".to_string());
+        }
+
+        // Metrics endpoint
+        let metrics_address = match &self.metrics_address {
+            Some(address) => match address.parse::<SocketAddr>() {
+                Ok(address) => Some(address),
+                Err(_) => {
+                    errors.push(format!("Invalid address of the metrics endpoint: '{}' (e.g. 127.0.0.1:9100)", address));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if !errors.is_empty() {
+            return Err(ErrorNetworkAnalyser::ErrorConfig(errors.join("\n")));
+        }
+
+        let mut network_analyser = NetworkAnalyser::with_interfaces(devices);
+        network_analyser.capture_file = self.capture_file;
+        network_analyser.time_interval = self.time_interval;
+        network_analyser.filename = self.filename;
+        network_analyser.final_filename = final_filename;
+        network_analyser.report_formats = report_formats;
+        network_analyser.database = self.database;
+        network_analyser.filter = self.filter;
+        network_analyser.bpf_expression = self.bpf_expression;
+        network_analyser.savefile = self.savefile;
+        network_analyser.savefile_only_filtered = self.savefile_only_filtered;
+        network_analyser.savefile_max_size = self.savefile_max_size;
+        network_analyser.savefile_rotation = self.savefile_rotation;
+        network_analyser.bidirectional = self.bidirectional;
+        network_analyser.vlan_conversations = self.vlan_conversations;
+        network_analyser.reassembly_flow_cap = self.reassembly_flow_cap;
+        network_analyser.reassembly_total_cap = self.reassembly_total_cap;
+        network_analyser.duration = self.duration;
+        network_analyser.packet_count = self.packet_count;
+        network_analyser.metrics_address = metrics_address;
+
+        return Ok(network_analyser);
+    }
+}
+
+/// It returns the default name of the final report: "final_" is added before the name of the report file (in the same directory)
+fn get_final_file_name(filename: &str) -> String {
+    return match filename.rfind(['/', '\\']) {
+        Some(pos) => format!("{}final_{}", &filename[..=pos], &filename[pos + 1..]),
+        None => format!("final_{}", filename),
+    };
+}
+
+/// It checks that the output file has one of the given extensions and that its directory exists.
+fn validate_output_file(path: &str, extensions: &[&str]) -> Result<(), String> {
+    let path_ref = Path::new(path);
+    let extension = path_ref.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    if !extensions.contains(&extension) || path_ref.file_stem().is_none() {
+        return Err(format!("'{}' is not a .{} file", path, extensions.join(", .")));
+    }
+    if let Some(directory) = path_ref.parent() {
+        if !directory.as_os_str().is_empty() && !directory.is_dir() {
+            return Err(format!("the directory of '{}' does not exist", path));
+        }
+    }
+    if path_ref.is_dir() {
+        return Err(format!("'{}' is a directory", path));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use super::*;
+
+    /// It creates an empty capture file in the temporary directory, returning its path
+    fn capture_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("traffic_analyser_{}_{}.pcap", std::process::id(), name));
+        fs::write(&path, []).unwrap();
+        return path;
+    }
+
+    #[test]
+    fn build_lists_all_the_errors() {
+        let mut filter = Filter::new();
+        filter.set_vlan_id(5000);
+        let result = NetworkAnalyserBuilder::new()
+            .capture_file("no_such_capture.pcap")
+            .interface("eth0")
+            .time_interval(0)
+            .report_file("report.pdf")
+            .filter(filter)
+            .savefile_rotation(60)
+            .packet_count(0)
+            .metrics_address("localhost")
+            .build();
+        let errors = match result {
+            Err(ErrorNetworkAnalyser::ErrorConfig(errors)) => errors,
+            _ => panic!("the configuration must not be valid"),
+        };
+        let errors: Vec<&str> = errors.lines().collect();
+        assert_eq!(errors, [
+            "Cannot sniff network interfaces while reading a capture file",
+            "No such capture file: 'no_such_capture.pcap'",
+            "The time interval must not be zero",
+            "Invalid report file: 'report.pdf' is not a .txt, .csv, .json, .ndjson file",
+            "Invalid final report file: 'final_report.pdf' is not a .txt, .csv, .json, .ndjson file",
+            "Invalid VLAN ID in the filter: 5000 (it must be between 0 and 4095)",
+            "The rotation of the savefile is set, but the savefile is not",
+            "The duration and the packet count must not be zero",
+            "Invalid address of the metrics endpoint: 'localhost' (e.g. 127.0.0.1:9100)",
+        ]);
+    }
+
+    #[test]
+    fn build_reads_a_capture_file() {
+        let path = capture_file("builder");
+        let report = std::env::temp_dir().join("report.csv");
+        let result = NetworkAnalyserBuilder::new()
+            .capture_file(path.to_str().unwrap())
+            .report_file(report.to_str().unwrap())
+            .build();
+        fs::remove_file(&path).unwrap();
+        let network_analyser = match result {
+            Ok(network_analyser) => network_analyser,
+            Err(err) => panic!("the configuration must be valid: {}", err),
+        };
+        assert!(network_analyser.interfaces.is_empty());
+        assert_eq!(network_analyser.final_filename, std::env::temp_dir().join("final_report.csv").to_str().unwrap());
+        assert_eq!(network_analyser.report_formats, [ReportFormat::Csv]);
+    }
+
+    #[test]
+    fn final_file_name_is_in_the_same_directory() {
+        assert_eq!(get_final_file_name("report.txt"), "final_report.txt");
+        assert_eq!(get_final_file_name("reports/today/report.txt"), "reports/today/final_report.txt");
+        assert_eq!(get_final_file_name("C:\\reports\\report.txt"), "C:\\reports\\final_report.txt");
+        assert_eq!(get_final_file_name("/report.txt"), "/final_report.txt");
+    }
+
+    #[test]
+    fn output_file_needs_a_valid_extension_and_directory() {
+        assert!(validate_output_file("report.txt", &["txt", "csv"]).is_ok());
+        assert!(validate_output_file("report.csv", &["txt", "csv"]).is_ok());
+        let directory = std::env::temp_dir();
+        assert!(validate_output_file(directory.join("capture.pcap").to_str().unwrap(), &["pcap"]).is_ok());
+
+        assert_eq!(validate_output_file("report.pdf", &["txt", "csv"]), Err("'report.pdf' is not a .txt, .csv file".to_string()));
+        assert!(validate_output_file("report", &["txt"]).is_err());
+        assert!(validate_output_file("no_such_directory/report.txt", &["txt"]).is_err_and(|err| err.contains("directory")));
+    }
+}
@@ -0,0 +1,346 @@
+use std::fs;
+use std::net::IpAddr;
+use serde::Deserialize;
+use crate::{ErrorNetworkAnalyser, Filter, FilterExpression, NetworkAnalyser, NetworkAnalyserBuilder, ReportFormat};
+use crate::filter_expression::parse_protocol;
+use crate::reassembly::{DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP};
+
+/// Configuration of a NetworkAnalyser, read from a TOML file. All the fields are optional: the missing ones keep the
+/// default values of 'NetworkAnalyserBuilder'. The fields can be overwritten before calling 'build()' (e.g. by command line options).
+/// Example:
+/// ```toml
+/// interfaces = ["eth0", "eth1"]
+/// interval = 10
+/// report = "reports/report.txt"
+/// formats = ["table", "json"]
+/// bpf = "not port 22"
+/// duration = 3600
+/// bidirectional = true
+///
+/// [filter]
+/// protocol = "tcp"
+/// expression = "net 10.0.0.0/8"
+///
+/// [savefile]
+/// path = "capture.pcap"
+/// rotation = 600
+/// ```
+/// - *interfaces*: names of the network interfaces to sniff
+/// - *capture_file*: capture file (.pcap, .pcapng) read instead of sniffing the network interfaces
+/// - *interval*: number of seconds between two updates of the report
+/// - *report*, *final_report*: paths of the report and of the final report
+/// - *formats*: formats in which the reports are written ("table", "csv", "json", "ndjson")
+/// - *database*: SQLite database where the conversations of every time interval are stored (feature "sqlite")
+/// - *filter*: filter of the packets shown in the report
+/// - *bpf*: raw BPF expression (pcap-filter syntax) applied to the capture in addition to the filter
+/// - *savefile*: pcap savefile where the packets are saved
+/// - *duration*, *packet_count*: the sniffing ends after this number of seconds or of packets
+/// - *bidirectional*: whether the packets A->B and B->A are aggregated in the same conversation
+/// - *vlan_conversations*: whether the same endpoints talking on different VLANs form different conversations
+/// - *reassembly*: maximum memory for the TCP reassembly
+/// - *metrics*: address (ip:port) on which the metrics are served for Prometheus, on '/metrics'
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub interfaces: Option<Vec<String>>,
+    pub capture_file: Option<String>,
+    pub interval: Option<usize>,
+    pub report: Option<String>,
+    pub final_report: Option<String>,
+    pub formats: Option<Vec<String>>,
+    pub database: Option<String>,
+    pub filter: Option<FilterConfig>,
+    pub bpf: Option<String>,
+    pub savefile: Option<SavefileConfig>,
+    pub duration: Option<u64>,
+    pub packet_count: Option<usize>,
+    pub bidirectional: Option<bool>,
+    pub vlan_conversations: Option<bool>,
+    pub reassembly: Option<ReassemblyConfig>,
+    pub metrics: Option<String>,
+}
+
+/// Section [filter] of the configuration file. The fields are the same of the interactive filter.
+/// - *src_ip*, *dst_ip*: source and destination ip address
+/// - *src_port*, *dst_port*: source and destination port
+/// - *protocol*: protocol name, with the same keywords of the filter expressions (e.g. "tcp", "dns")
+/// - *vlan*: VLAN ID
+/// - *expression*: filter expression (e.g. "(net 10.0.0.0/8 and not port 22) or proto dns")
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterConfig {
+    pub src_ip: Option<String>,
+    pub dst_ip: Option<String>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub protocol: Option<String>,
+    pub vlan: Option<u16>,
+    pub expression: Option<String>,
+}
+
+/// Section [savefile] of the configuration file.
+/// - *path*: path of the savefile (.pcap)
+/// - *all_packets*: whether all the packets are saved, not only the ones accepted by the filter
+/// - *max_size*, *rotation*: size (bytes) and time (seconds) of rotation of the savefile
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavefileConfig {
+    pub path: String,
+    #[serde(default)]
+    pub all_packets: bool,
+    pub max_size: Option<u64>,
+    pub rotation: Option<u64>,
+}
+
+/// Section [reassembly] of the configuration file.
+/// - *flow_cap*, *total_cap*: maximum memory (bytes) for the reassembly of a TCP stream and of all of them
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReassemblyConfig {
+    pub flow_cap: Option<usize>,
+    pub total_cap: Option<usize>,
+}
+
+impl Config {
+    /// It reads the configuration from a TOML file.
+    /// It returns an ErrorNetworkAnalyser::ErrorConfig if the file cannot be read or it is not a valid configuration.
+    pub fn from_file(path: &str) -> Result<Config, ErrorNetworkAnalyser> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| ErrorNetworkAnalyser::ErrorConfig(format!("Cannot read the configuration file '{}': {}", path, err)))?;
+        return content.parse::<Config>()
+            .map_err(|err| ErrorNetworkAnalyser::ErrorConfig(format!("Invalid configuration file '{}': {}", path, err)));
+    }
+
+    /// It checks the configuration and creates the NetworkAnalyser.
+    /// If the configuration is not valid it returns an ErrorNetworkAnalyser::ErrorConfig listing all the errors found (one for each line):
+    /// the ones of the values of the configuration and the ones found by 'NetworkAnalyserBuilder::build()'.
+    pub fn build(self) -> Result<NetworkAnalyser, ErrorNetworkAnalyser> {
+        let mut errors: Vec<String> = vec![];
+        let mut builder = NetworkAnalyserBuilder::new();
+
+        for interface in self.interfaces.iter().flatten() {
+            builder = builder.interface(interface);
+        }
+        if let Some(path) = &self.capture_file {
+            builder = builder.capture_file(path);
+        }
+        if let Some(interval) = self.interval {
+            builder = builder.time_interval(interval);
+        }
+        if let Some(report) = &self.report {
+            builder = builder.report_file(report);
+        }
+        if let Some(final_report) = &self.final_report {
+            builder = builder.final_report_file(final_report);
+        }
+        if let Some(formats) = &self.formats {
+            let n_errors = errors.len();
+            let mut report_formats = vec![];
+            for format in formats {
+                match format.parse::<ReportFormat>() {
+                    Ok(format) => report_formats.push(format),
+                    Err(err) => errors.push(format!("Invalid report format: {}", err)),
+                }
+            }
+            // The formats are checked by the builder only if all of them are known
+            if errors.len() == n_errors {
+                builder = builder.report_formats(&report_formats);
+            }
+        }
+        if let Some(database) = &self.database {
+            builder = builder.database(database);
+        }
+        if let Some(filter) = &self.filter {
+            builder = builder.filter(filter.to_filter(&mut errors));
+        }
+        if let Some(bpf) = &self.bpf {
+            builder = builder.bpf_expression(bpf);
+        }
+        if let Some(savefile) = &self.savefile {
+            builder = builder.savefile(&savefile.path, !savefile.all_packets);
+            if let Some(max_size) = savefile.max_size {
+                builder = builder.savefile_max_size(max_size);
+            }
+            if let Some(rotation) = savefile.rotation {
+                builder = builder.savefile_rotation(rotation);
+            }
+        }
+        if let Some(duration) = self.duration {
+            builder = builder.duration(duration);
+        }
+        if let Some(count) = self.packet_count {
+            builder = builder.packet_count(count);
+        }
+        if let Some(bidirectional) = self.bidirectional {
+            builder = builder.bidirectional(bidirectional);
+        }
+        if let Some(vlan_conversations) = self.vlan_conversations {
+            builder = builder.vlan_conversations(vlan_conversations);
+        }
+        if let Some(reassembly) = &self.reassembly {
+            builder = builder.reassembly_memory(reassembly.flow_cap.unwrap_or(DEFAULT_FLOW_CAP),
+                                                reassembly.total_cap.unwrap_or(DEFAULT_TOTAL_CAP));
+        }
+        if let Some(address) = &self.metrics {
+            builder = builder.metrics_address(address);
+        }
+
+        return match builder.build() {
+            Ok(network_analyser) if errors.is_empty() => Ok(network_analyser),
+            Ok(_) => Err(ErrorNetworkAnalyser::ErrorConfig(errors.join("\n"))),
+            Err(ErrorNetworkAnalyser::ErrorConfig(builder_errors)) => {
+                errors.push(builder_errors);
+                Err(ErrorNetworkAnalyser::ErrorConfig(errors.join("\n")))
+            }
+            Err(err) => Err(err),
+        };
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = toml::de::Error;
+
+    /// It parses the configuration from the content of a TOML file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return toml::from_str(s);
+    }
+}
+
+impl FilterConfig {
+    /// It creates the Filter, adding to 'errors' the values that are not valid
+    fn to_filter(&self, errors: &mut Vec<String>) -> Filter {
+        let mut filter = Filter::new();
+        if let Some(ip) = &self.src_ip {
+            match ip.parse::<IpAddr>() {
+                Ok(addr) => filter.set_ip_srg(addr),
+                Err(_) => errors.push(format!("Invalid source ip address in the filter: '{}'", ip)),
+            }
+        }
+        if let Some(ip) = &self.dst_ip {
+            match ip.parse::<IpAddr>() {
+                Ok(addr) => filter.set_ip_dest(addr),
+                Err(_) => errors.push(format!("Invalid destination ip address in the filter: '{}'", ip)),
+            }
+        }
+        if let Some(port) = self.src_port {
+            filter.set_prt_srg(port);
+        }
+        if let Some(port) = self.dst_port {
+            filter.set_prt_dest(port);
+        }
+        if let Some(protocol) = &self.protocol {
+            match parse_protocol(protocol) {
+                Some(protocol) => filter.set_protocol(protocol),
+                None => errors.push(format!("Unknown protocol in the filter: '{}'", protocol)),
+            }
+        }
+        if let Some(vlan_id) = self.vlan {
+            filter.set_vlan_id(vlan_id);
+        }
+        if let Some(expression) = &self.expression {
+            match expression.parse::<FilterExpression>() {
+                Ok(expression) => filter.set_expression(expression),
+                Err(err) => errors.push(format!("Invalid filter expression '{}': {}", expression, err)),
+            }
+        }
+        return filter;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+
+    /// It creates an empty capture file in the temporary directory, returning its path
+    fn capture_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("traffic_analyser_{}_{}.pcap", std::process::id(), name));
+        fs::write(&path, []).unwrap();
+        return path;
+    }
+
+    /// It returns the errors of a configuration that is not valid
+    fn build_errors(config: Config) -> Vec<String> {
+        return match config.build() {
+            Err(ErrorNetworkAnalyser::ErrorConfig(errors)) => errors.lines().map(String::from).collect(),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("the configuration must not be valid"),
+        };
+    }
+
+    #[test]
+    fn configuration_sections_are_parsed() {
+        let config: Config = r#"
+            interfaces = ["eth0", "eth1"]
+            interval = 10
+            formats = ["table", "json"]
+
+            [filter]
+            protocol = "tcp"
+            src_port = 443
+
+            [savefile]
+            path = "capture.pcap"
+            rotation = 600
+
+            [reassembly]
+            flow_cap = 1024
+        "#.parse().unwrap();
+        assert_eq!(config.interfaces, Some(vec!["eth0".to_string(), "eth1".to_string()]));
+        assert_eq!(config.interval, Some(10));
+        assert_eq!(config.formats, Some(vec!["table".to_string(), "json".to_string()]));
+        let filter = config.filter.unwrap();
+        assert_eq!((filter.protocol.as_deref(), filter.src_port, filter.dst_port), (Some("tcp"), Some(443), None));
+        let savefile = config.savefile.unwrap();
+        assert_eq!((savefile.path.as_str(), savefile.rotation, savefile.max_size), ("capture.pcap", Some(600), None));
+        assert!(!savefile.all_packets);
+        let reassembly = config.reassembly.unwrap();
+        assert_eq!((reassembly.flow_cap, reassembly.total_cap), (Some(1024), None));
+        assert!(config.report.is_none() && config.duration.is_none());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!("intervall = 10".parse::<Config>().is_err());
+        assert!("[filter]\nport = 80".parse::<Config>().is_err());
+        assert!("[savefile]\npath = \"capture.pcap\"\nall = true".parse::<Config>().is_err());
+        assert!("[reassembly]\ncap = 1024".parse::<Config>().is_err());
+        // The path of the savefile is required
+        assert!("[savefile]\nrotation = 600".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn build_adds_the_errors_of_the_builder() {
+        let config: Config = r#"
+            capture_file = "no_such_capture.pcap"
+            interval = 0
+            formats = ["table", "xml"]
+
+            [filter]
+            src_ip = "10.0.0.256"
+        "#.parse().unwrap();
+        assert_eq!(build_errors(config), [
+            "Invalid report format: unknown report format 'xml' (expected one of: table, csv, json, ndjson)",
+            "Invalid source ip address in the filter: '10.0.0.256'",
+            "No such capture file: 'no_such_capture.pcap'",
+            "The time interval must not be zero",
+        ]);
+    }
+
+    #[test]
+    fn build_fails_on_the_errors_of_the_configuration_only() {
+        let path = capture_file("config");
+        let config = Config {
+            capture_file: Some(path.to_str().unwrap().to_string()),
+            filter: Some(FilterConfig { protocol: Some("tcpp".to_string()), ..FilterConfig::default() }),
+            ..Config::default()
+        };
+        let errors = build_errors(config.clone());
+        assert_eq!(errors, ["Unknown protocol in the filter: 'tcpp'"]);
+
+        let config = Config { filter: None, ..config };
+        let result = config.build();
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+use crate::events::{Conversation, FinalSummary, IntervalSummary};
+use crate::Protocol;
+use crate::report_sink::ReportSink;
+
+/// Columns describing a conversation, in the CSV reports
+const CONVERSATION_COLUMNS: [&str; 33] = [
+    "ip_srg", "prt_srg", "ip_dest", "prt_dest", "protocol", "interface", "outer_vlan", "inner_vlan",
+    "tot_bytes", "tot_packets", "starting_time", "ending_time", "duration",
+    "fwd_bytes", "fwd_packets", "bwd_bytes", "bwd_packets", "initiator_ip", "initiator_prt", "ipv6_extensions",
+    "tcp", "syn_packets", "fin_packets", "rst_packets", "retransmissions", "out_of_order", "dup_acks", "zero_windows", "tcp_end",
+    "handshake_rtt", "rtt_min", "rtt_avg", "rtt_max",
+];
+/// Column of the report containing the second (since the beginning of the sniffing) on which the time interval began
+const INTERVAL_START_COLUMN: &str = "interval_start";
+
+/// CsvSink object. It writes the reports as comma separated values (RFC 4180), one row for each conversation:
+/// the rows of every time interval are appended to the report file, with the start of their time interval in the first column,
+/// while the final report file is written at the end of the sniffing.
+/// The header is the same whatever the options of the sniffing, and the values are raw numbers: bytes, packets, times in seconds
+/// (since the beginning of the sniffing, with fractional part). The values not available are empty.
+/// - *file*: report file, created (truncated) when the sink is created
+/// - *final_filename*: name of the final report file (.csv)
+pub struct CsvSink {
+    file: BufWriter<File>,
+    final_filename: String,
+}
+
+impl CsvSink {
+    /// Create a new CsvSink object instance, creating the report file and writing its header.
+    /// It returns an error if the report file cannot be created.
+    /// - *filename*: name of the report file (.csv)
+    /// - *final_filename*: name of the final report file (.csv)
+    pub fn new(filename: String, final_filename: String) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(&filename)?);
+        let mut header = vec![INTERVAL_START_COLUMN.to_string()];
+        header.extend(CONVERSATION_COLUMNS.iter().map(|column| column.to_string()));
+        write_record(&mut file, &header)?;
+        file.flush()?;
+        return Ok(CsvSink { file, final_filename });
+    }
+}
+
+impl ReportSink for CsvSink {
+    fn write_interval(&mut self, summary: &IntervalSummary) -> io::Result<()> {
+        for conversation in summary.get_conversations() {
+            let mut record = vec![summary.get_interval_start().to_string()];
+            record.extend(conversation_record(conversation));
+            write_record(&mut self.file, &record)?;
+        }
+        return self.file.flush();
+    }
+
+    fn write_final(&mut self, summary: &FinalSummary) -> io::Result<()> {
+        println!("> Writing final report in {} ...", self.final_filename);
+        let mut final_file = BufWriter::new(File::create(&self.final_filename)?);
+        write_record(&mut final_file, &CONVERSATION_COLUMNS.iter().map(|column| column.to_string()).collect::<Vec<String>>())?;
+        for conversation in summary.get_conversations() {
+            write_record(&mut final_file, &conversation_record(conversation))?;
+        }
+        return final_file.flush();
+    }
+}
+
+/// It returns the values of a conversation, in the order of CONVERSATION_COLUMNS
+fn conversation_record(conversation: &Conversation) -> Vec<String> {
+    let key = conversation.get_key();
+    let stats = conversation.get_stats();
+    let [outer_vlan, inner_vlan] = key.get_vlan_ids();
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    let secs = |time: Option<Duration>| optional(time.map(|time| format!("{:.6}", time.as_secs_f64())));
+    let tcp_count = |count: usize| if stats.is_tcp() { count.to_string() } else { String::new() };
+    return vec![
+        key.get_ip_srg().to_string(),
+        key.get_prt_srg().to_string(),
+        key.get_ip_dest().to_string(),
+        key.get_prt_dest().to_string(),
+        match key.get_protocol() {
+            Protocol::None => String::new(),
+            protocol => protocol.to_string(),
+        },
+        conversation.get_interface().unwrap_or_default().to_string(),
+        if outer_vlan == 0 { String::new() } else { outer_vlan.to_string() },
+        if inner_vlan == 0 { String::new() } else { inner_vlan.to_string() },
+        stats.get_tot_bytes().to_string(),
+        stats.get_tot_packets().to_string(),
+        secs(stats.get_starting_time()),
+        secs(stats.get_ending_time()),
+        secs(stats.get_duration()),
+        stats.get_fwd_bytes().to_string(),
+        stats.get_fwd_packets().to_string(),
+        stats.get_bwd_bytes().to_string(),
+        stats.get_bwd_packets().to_string(),
+        optional(stats.get_initiator().map(|(ip, _)| ip.to_string())),
+        optional(stats.get_initiator().map(|(_, prt)| prt.to_string())),
+        stats.get_ipv6_extensions().to_string(),
+        stats.is_tcp().to_string(),
+        tcp_count(stats.get_syn_packets()),
+        tcp_count(stats.get_fin_packets()),
+        tcp_count(stats.get_rst_packets()),
+        tcp_count(stats.get_retransmissions()),
+        tcp_count(stats.get_out_of_order()),
+        tcp_count(stats.get_dup_acks()),
+        tcp_count(stats.get_zero_windows()),
+        optional(conversation.get_tcp_state().map(|state| state.connection_end().to_string())),
+        secs(stats.get_handshake_rtt()),
+        secs(stats.get_rtt_min()),
+        secs(stats.get_rtt_avg()),
+        secs(stats.get_rtt_max()),
+    ];
+}
+
+/// It writes a record, terminated by CRLF. The fields containing commas, double quotes or line breaks are enclosed
+/// in double quotes, and their double quotes are doubled (RFC 4180).
+fn write_record<W: Write>(file: &mut W, fields: &[String]) -> io::Result<()> {
+    let fields: Vec<String> = fields.iter()
+        .map(|field| {
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    return write!(file, "{}\r\n", fields.join(","));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use super::*;
+    use crate::ConversationKey;
+    use crate::packet_handle::ConversationStats;
+
+    /// It returns the record written for the fields
+    fn record(fields: &[&str]) -> String {
+        let mut output = vec![];
+        write_record(&mut output, &fields.iter().map(|field| field.to_string()).collect::<Vec<String>>()).unwrap();
+        return String::from_utf8(output).unwrap();
+    }
+
+    #[test]
+    fn plain_fields_are_not_quoted() {
+        assert_eq!(record(&["10.0.0.1", "443", "", "TLS"]), "10.0.0.1,443,,TLS\r\n");
+    }
+
+    #[test]
+    fn fields_with_special_characters_are_quoted() {
+        assert_eq!(record(&["a,b", "c"]), "\"a,b\",c\r\n");
+        assert_eq!(record(&["say \"hi\""]), "\"say \"\"hi\"\"\"\r\n");
+        assert_eq!(record(&["line\nbreak", "carriage\rreturn", "crlf\r\n"]), "\"line\nbreak\",\"carriage\rreturn\",\"crlf\r\n\"\r\n");
+    }
+
+    #[test]
+    fn records_are_terminated_by_crlf() {
+        assert_eq!(record(&[]), "\r\n");
+        assert_eq!(record(&["a"]) + &record(&["b"]), "a\r\nb\r\n");
+    }
+
+    #[test]
+    fn conversation_record_matches_the_columns() {
+        let key = ConversationKey::new_key(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                                           40000, 443, Protocol::Tcp);
+        let stats = ConversationStats::new(100, Duration::from_millis(1500), Duration::from_secs(3), 2);
+        let record = conversation_record(&Conversation::new(key, stats, None, None));
+        assert_eq!(record.len(), CONVERSATION_COLUMNS.len());
+        assert_eq!(record[0], "10.0.0.1");
+        assert_eq!(record[3], "443");
+        assert_eq!(record[5], "");
+        assert_eq!(record[10], "1.500000");
+    }
+}
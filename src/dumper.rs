@@ -0,0 +1,138 @@
+use std::time::{Duration, SystemTime};
+use pcap::{Capture, Dead, Linktype, Packet, Precision, Savefile};
+use crate::ErrorNetworkAnalyser;
+
+/// Size in bytes of the global header of a pcap savefile
+const PCAP_FILE_HEADER_LEN: u64 = 24;
+/// Size in bytes of the header of each packet record in a pcap savefile
+const PCAP_RECORD_HEADER_LEN: u64 = 16;
+
+/// Dumper object. It saves the raw packets got by the 'Sniffer' in a pcap savefile, eventually rotating it.
+/// - *filename*: name of the savefile (.pcap). The rotated files are named adding the index of the file before the extension (e.g. "capture_1.pcap")
+/// - *only_filtered*: if true only the packets accepted by the 'Filter' are saved, otherwise all the packets
+/// - *max_size*: if set, the savefile is rotated when it would exceed this size (bytes)
+/// - *rotation_interval*: if set, the savefile is rotated when the packets are more recent than this time since the first packet of the file
+/// - *capture*: fake capture handle (same link type and timestamp precision of the sniffed one) used to open the savefiles
+/// - *savefile*: savefile currently written
+/// - *n_file*: index of the savefile currently written
+/// - *file_size*: bytes written in the current savefile
+/// - *file_start*: timestamp of the first packet written in the current savefile
+pub struct Dumper {
+    filename: String,
+    only_filtered: bool,
+    max_size: Option<u64>,
+    rotation_interval: Option<Duration>,
+    capture: Capture<Dead>,
+    savefile: Option<Savefile>,
+    n_file: usize,
+    file_size: u64,
+    file_start: Option<SystemTime>,
+}
+
+impl Dumper {
+    /// Create a new Dumper object instance and open the first savefile.
+    /// It returns an ErrorNetworkAnalyser if the savefile cannot be created.
+    /// - *filename*: name of the savefile (.pcap)
+    /// - *linktype*: link type of the capture whose packets will be saved
+    /// - *precision*: timestamp precision of the capture whose packets will be saved (the headers of the packets are written as they are)
+    /// - *only_filtered*: if true only the packets accepted by the 'Filter' are saved, otherwise all the packets
+    /// - *max_size*: if set, the savefile is rotated when it would exceed this size (bytes)
+    /// - *rotation_interval*: if set, the savefile is rotated every 'rotation_interval' of capture time
+    pub fn new(filename: String,
+               linktype: Linktype,
+               precision: Precision,
+               only_filtered: bool,
+               max_size: Option<u64>,
+               rotation_interval: Option<Duration>,
+    ) -> Result<Self, ErrorNetworkAnalyser> {
+        let capture = match Capture::dead_with_precision(linktype, precision) {
+            Ok(cap) => cap,
+            Err(err) => return Err(ErrorNetworkAnalyser::ErrorNa(err.to_string()))
+        };
+
+        let mut dumper = Dumper {
+            filename,
+            only_filtered,
+            max_size,
+            rotation_interval,
+            capture,
+            savefile: None,
+            n_file: 0,
+            file_size: 0,
+            file_start: None,
+        };
+
+        let name = dumper.get_current_filename();
+        dumper.savefile = match dumper.capture.savefile(&name) {
+            Ok(savefile) => Some(savefile),
+            Err(err) => return Err(ErrorNetworkAnalyser::ErrorNa(format!("Error: cannot create the savefile '{}': {}", name, err)))
+        };
+        dumper.file_size = PCAP_FILE_HEADER_LEN;
+
+        return Ok(dumper);
+    }
+
+    /// It returns whether only the packets accepted by the 'Filter' need to be saved
+    pub fn get_only_filtered(&self) -> bool {
+        return self.only_filtered;
+    }
+
+    /// It writes the packet in the savefile, rotating it first if needed.
+    /// - *packet*: raw packet got from the capture
+    /// - *timestamp*: capture timestamp of the packet
+    pub fn dump(&mut self, packet: &Packet, timestamp: SystemTime) {
+        let record_len = PCAP_RECORD_HEADER_LEN + packet.header.caplen as u64;
+
+        // Check if the savefile needs to be rotated (a file always contains at least a packet)
+        if self.file_start.is_some() {
+            let too_big = match self.max_size {
+                Some(max_size) => self.file_size + record_len > max_size,
+                None => false
+            };
+            let too_old = match self.rotation_interval {
+                Some(interval) => timestamp.duration_since(self.file_start.unwrap()).unwrap_or(Duration::ZERO) >= interval,
+                None => false
+            };
+            if too_big || too_old {
+                self.rotate();
+            }
+        }
+
+        if let Some(savefile) = self.savefile.as_mut() {
+            savefile.write(packet);
+            self.file_size += record_len;
+            if self.file_start.is_none() {
+                self.file_start = Some(timestamp);
+            }
+        }
+    }
+
+    /// It closes the current savefile and opens the next one.
+    /// If the new savefile cannot be created the packets stop being saved.
+    fn rotate(&mut self) {
+        // Close the current savefile
+        self.savefile = None;
+
+        self.n_file += 1;
+        self.file_size = PCAP_FILE_HEADER_LEN;
+        self.file_start = None;
+
+        let name = self.get_current_filename();
+        match self.capture.savefile(&name) {
+            Ok(savefile) => self.savefile = Some(savefile),
+            Err(err) => println!("> [Error]: cannot create the savefile '{}', packets will not be saved anymore: {}", name, err)
+        }
+    }
+
+    /// It returns the name of the savefile currently written.
+    /// The first one is 'filename', the following ones have the index of the file before the extension.
+    fn get_current_filename(&self) -> String {
+        if self.n_file == 0 {
+            return self.filename.clone();
+        }
+        return match self.filename.rfind('.') {
+            Some(pos) => format!("{}_{}{}", &self.filename[..pos], self.n_file, &self.filename[pos..]),
+            None => format!("{}_{}", self.filename, self.n_file)
+        };
+    }
+}
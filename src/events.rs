@@ -0,0 +1,273 @@
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::time::SystemTime;
+use pnet::packet::ethernet::EtherType;
+use crate::packet_handle::{ConversationKey, ConversationStats, MacConversationKey, MacConversationStats, PacketInfo};
+use crate::tcp_tracker::TcpState;
+
+/// Event sent by the 'Reporter' to the subscribers of the NetworkAnalyser (see 'NetworkAnalyser::subscribe()').
+/// - *Packet*: a packet has been parsed. *accepted* tells whether it passed the filter (only the accepted packets are part of the summaries)
+/// - *Interval*: a time interval is over. It is sent when the report is updated
+/// - *Final*: the sniffing is over. It is the last event sent
+#[derive(Debug, Clone)]
+pub enum AnalyserEvent {
+    Packet { info: PacketInfo, accepted: bool },
+    Interval(IntervalSummary),
+    Final(FinalSummary),
+}
+
+/// Maximum number of events waiting in the channel of a subscriber: when it is full the new 'Packet' events are dropped
+pub const SUBSCRIBER_CAPACITY: usize = 4096;
+
+/// Subscriber to the events of the NetworkAnalyser. The 'Packet' events are sent without blocking the Reporter:
+/// the ones that do not fit in the channel (because the subscriber is too slow) are dropped and counted.
+/// The summaries are never dropped: the Reporter waits until they fit in the channel.
+/// - *sender*: sender end of the bounded channel of the subscriber
+/// - *packets*: whether the subscriber gets the 'Packet' events, or only the summaries
+/// - *dropped_events*: number of 'Packet' events dropped since the last summary sent to the subscriber
+pub(crate) struct Subscriber {
+    sender: SyncSender<AnalyserEvent>,
+    packets: bool,
+    dropped_events: usize,
+}
+
+impl Subscriber {
+    /// Create a new Subscriber object instance
+    pub(crate) fn new(sender: SyncSender<AnalyserEvent>, packets: bool) -> Self {
+        return Subscriber { sender, packets, dropped_events: 0 };
+    }
+
+    /// It returns whether the subscriber gets the 'Packet' events
+    pub(crate) fn wants_packets(&self) -> bool {
+        return self.packets;
+    }
+
+    /// It sends the event to the subscriber, if interested: a packet is dropped if the channel is full,
+    /// while a summary waits for a free slot and carries the number of packets dropped before it.
+    /// It returns false if the subscriber dropped its receiver.
+    pub(crate) fn send(&mut self, event: &AnalyserEvent) -> bool {
+        let mut event = match event {
+            AnalyserEvent::Packet { .. } if !self.packets => return true,
+            AnalyserEvent::Packet { .. } => {
+                return match self.sender.try_send(event.clone()) {
+                    Ok(()) => true,
+                    Err(TrySendError::Full(_)) => {
+                        self.dropped_events += 1;
+                        true
+                    }
+                    Err(TrySendError::Disconnected(_)) => false,
+                };
+            }
+            event => event.clone(),
+        };
+        match &mut event {
+            AnalyserEvent::Interval(summary) => summary.dropped_events = self.dropped_events,
+            AnalyserEvent::Final(summary) => summary.dropped_events = self.dropped_events,
+            AnalyserEvent::Packet { .. } => {}
+        }
+        if self.sender.send(event).is_err() {
+            return false;
+        }
+        self.dropped_events = 0;
+        return true;
+    }
+}
+
+/// A conversation of a summary.
+/// - *key*, *stats*: key and statistics of the conversation (the times are relative to the 'initial_time' of the summary)
+/// - *interface*: name of the network interface the conversation was sniffed on (None when reading a capture file)
+/// - *tcp_state*: state of the TCP connection the conversation belongs to, when the summary was created (None if it is not a TCP conversation)
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    key: ConversationKey,
+    stats: ConversationStats,
+    interface: Option<String>,
+    tcp_state: Option<TcpState>,
+}
+
+impl Conversation {
+    /// Create a new Conversation object instance
+    pub(crate) fn new(key: ConversationKey, stats: ConversationStats, interface: Option<String>, tcp_state: Option<TcpState>) -> Self {
+        return Conversation { key, stats, interface, tcp_state };
+    }
+    /// Get the key of the conversation
+    pub fn get_key(&self) -> &ConversationKey { return &self.key }
+    /// Get the statistics of the conversation
+    pub fn get_stats(&self) -> &ConversationStats { return &self.stats }
+    /// Get the name of the network interface the conversation was sniffed on
+    pub fn get_interface(&self) -> Option<&str> { return self.interface.as_deref() }
+    /// Get the state of the TCP connection the conversation belongs to
+    pub fn get_tcp_state(&self) -> Option<TcpState> { return self.tcp_state }
+}
+
+/// Frames not carrying an IP packet with the same EtherType.
+/// - *ethertype*: EtherType of the frames (None if the link layer does not carry it)
+/// - *frames*, *bytes*: number of frames and their total size in bytes
+#[derive(Debug, Clone, Copy)]
+pub struct NonIpCount {
+    ethertype: Option<EtherType>,
+    frames: usize,
+    bytes: usize,
+}
+
+impl NonIpCount {
+    /// Create a new NonIpCount object instance
+    pub(crate) fn new(ethertype: Option<EtherType>, frames: usize, bytes: usize) -> Self {
+        return NonIpCount { ethertype, frames, bytes };
+    }
+    /// Get the EtherType of the frames
+    pub fn get_ethertype(&self) -> Option<EtherType> { return self.ethertype }
+    /// Get the number of frames
+    pub fn get_frames(&self) -> usize { return self.frames }
+    /// Get the total size in bytes of the frames
+    pub fn get_bytes(&self) -> usize { return self.bytes }
+}
+
+/// Summary of a time interval: the same content of an update of the report.
+/// - *initial_time*: time 0 of the sniffing (when reading a capture file, the timestamp of its first packet)
+/// - *interval_start*: second (since the initial time) on which the time interval began
+/// - *conversations*: conversations happened in the time interval, sorted by starting time
+/// - *reassembled*, *discarded*: number of fragmented datagrams reassembled and discarded (incomplete) in the time interval
+/// - *non_ip*: frames accepted by the filter not carrying an IP packet, sorted by number of frames (descending)
+/// - *dropped_events*: packet events dropped since the previous summary because the channel of the subscriber was full (0 for the report sinks)
+#[derive(Debug, Clone)]
+pub struct IntervalSummary {
+    initial_time: Option<SystemTime>,
+    interval_start: u64,
+    conversations: Vec<Conversation>,
+    reassembled: usize,
+    discarded: usize,
+    non_ip: Vec<NonIpCount>,
+    dropped_events: usize,
+}
+
+impl IntervalSummary {
+    /// Create a new IntervalSummary object instance
+    pub(crate) fn new(initial_time: Option<SystemTime>, interval_start: u64, conversations: Vec<Conversation>, reassembled: usize, discarded: usize, non_ip: Vec<NonIpCount>) -> Self {
+        return IntervalSummary { initial_time, interval_start, conversations, reassembled, discarded, non_ip, dropped_events: 0 };
+    }
+    /// Get the time 0 of the sniffing
+    pub fn get_initial_time(&self) -> Option<SystemTime> { return self.initial_time }
+    /// Get the second (since the initial time) on which the time interval began
+    pub fn get_interval_start(&self) -> u64 { return self.interval_start }
+    /// Get the conversations happened in the time interval
+    pub fn get_conversations(&self) -> &[Conversation] { return &self.conversations }
+    /// Get the number of fragmented datagrams reassembled in the time interval
+    pub fn get_reassembled(&self) -> usize { return self.reassembled }
+    /// Get the number of fragmented datagrams discarded in the time interval
+    pub fn get_discarded(&self) -> usize { return self.discarded }
+    /// Get the frames not carrying an IP packet, by EtherType
+    pub fn get_non_ip(&self) -> &[NonIpCount] { return &self.non_ip }
+    /// Get the number of packet events dropped since the previous summary because the channel of the subscriber was full
+    pub fn get_dropped_events(&self) -> usize { return self.dropped_events }
+}
+
+/// Summary of the whole sniffing: the same content of the final report.
+/// - *initial_time*: time 0 of the sniffing (None if no packet was read from the capture file)
+/// - *packets*: number of packets accepted by the filter
+/// - *conversations*: all the conversations happened, sorted by starting time
+/// - *reassembled*, *discarded*: number of fragmented datagrams reassembled and discarded (incomplete)
+/// - *non_ip*: frames accepted by the filter not carrying an IP packet, sorted by number of frames (descending)
+/// - *mac_conversations*: layer 2 conversations (between two MAC addresses), sorted by bytes (descending)
+/// - *dropped_events*: packet events dropped since the previous summary because the channel of the subscriber was full (0 for the report sinks)
+#[derive(Debug, Clone)]
+pub struct FinalSummary {
+    initial_time: Option<SystemTime>,
+    packets: usize,
+    conversations: Vec<Conversation>,
+    reassembled: usize,
+    discarded: usize,
+    non_ip: Vec<NonIpCount>,
+    mac_conversations: Vec<(MacConversationKey, MacConversationStats)>,
+    dropped_events: usize,
+}
+
+impl FinalSummary {
+    /// Create a new FinalSummary object instance
+    pub(crate) fn new(initial_time: Option<SystemTime>,
+                      packets: usize,
+                      conversations: Vec<Conversation>,
+                      reassembled: usize,
+                      discarded: usize,
+                      non_ip: Vec<NonIpCount>,
+                      mac_conversations: Vec<(MacConversationKey, MacConversationStats)>,
+    ) -> Self {
+        return FinalSummary { initial_time, packets, conversations, reassembled, discarded, non_ip, mac_conversations, dropped_events: 0 };
+    }
+    /// Get the time 0 of the sniffing
+    pub fn get_initial_time(&self) -> Option<SystemTime> { return self.initial_time }
+    /// Get the number of packets accepted by the filter
+    pub fn get_packets(&self) -> usize { return self.packets }
+    /// Get all the conversations happened
+    pub fn get_conversations(&self) -> &[Conversation] { return &self.conversations }
+    /// Get the number of fragmented datagrams reassembled
+    pub fn get_reassembled(&self) -> usize { return self.reassembled }
+    /// Get the number of fragmented datagrams discarded
+    pub fn get_discarded(&self) -> usize { return self.discarded }
+    /// Get the frames not carrying an IP packet, by EtherType
+    pub fn get_non_ip(&self) -> &[NonIpCount] { return &self.non_ip }
+    /// Get the layer 2 conversations
+    pub fn get_mac_conversations(&self) -> &[(MacConversationKey, MacConversationStats)] { return &self.mac_conversations }
+    /// Get the number of packet events dropped since the previous summary because the channel of the subscriber was full
+    pub fn get_dropped_events(&self) -> usize { return self.dropped_events }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+    use std::thread;
+    use super::*;
+
+    fn packet_event() -> AnalyserEvent {
+        return AnalyserEvent::Packet { info: PacketInfo::new(), accepted: true };
+    }
+
+    fn interval_event() -> AnalyserEvent {
+        return AnalyserEvent::Interval(IntervalSummary::new(None, 0, vec![], 0, 0, vec![]));
+    }
+
+    #[test]
+    fn subscriber_without_packets_gets_only_the_summaries() {
+        let (sender, receiver) = sync_channel(4);
+        let mut subscriber = Subscriber::new(sender, false);
+        assert!(subscriber.send(&packet_event()));
+        assert!(subscriber.send(&interval_event()));
+        assert!(matches!(receiver.try_recv(), Ok(AnalyserEvent::Interval(_))));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn full_channel_drops_the_packets_but_not_the_summaries() {
+        let (sender, receiver) = sync_channel(2);
+        let mut subscriber = Subscriber::new(sender, true);
+        for _ in 0..5 {
+            assert!(subscriber.send(&packet_event()));
+        }
+        // The summary waits until the subscriber makes room for it
+        let sending = thread::spawn(move || {
+            assert!(subscriber.send(&interval_event()));
+            subscriber
+        });
+        let events: Vec<AnalyserEvent> = receiver.iter().take(3).collect();
+        let mut subscriber = sending.join().unwrap();
+        assert!(matches!(events[..2], [AnalyserEvent::Packet { .. }, AnalyserEvent::Packet { .. }]));
+        match &events[2] {
+            // The 3 packets that did not fit have been dropped
+            AnalyserEvent::Interval(summary) => assert_eq!(summary.get_dropped_events(), 3),
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        assert!(subscriber.send(&interval_event()));
+        match receiver.try_recv() {
+            Ok(AnalyserEvent::Interval(summary)) => assert_eq!(summary.get_dropped_events(), 0),
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn subscriber_is_removed_when_it_drops_the_receiver() {
+        let (sender, receiver) = sync_channel(2);
+        let mut subscriber = Subscriber::new(sender, true);
+        drop(receiver);
+        assert!(!subscriber.send(&interval_event()));
+    }
+}
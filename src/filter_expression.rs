@@ -0,0 +1,545 @@
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+use crate::packet_handle::{transport_tolerant_bpf, PacketInfo, Protocol};
+
+/* -------- Direction enum ---------*/
+/// Which address/port of the packet a primitive of the expression refers to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    Src,
+    Dst,
+    Any,
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Direction::Src => write!(f, "src "),
+            Direction::Dst => write!(f, "dst "),
+            Direction::Any => write!(f, ""),
+        }
+    }
+}
+
+/* -------- Filter expression AST ---------*/
+/// Filter expression, parsed from a string like "(net 10.0.0.0/8 and not port 22) or proto dns".
+/// Grammar (the keywords 'and', 'or', 'not' can also be written as '&&', '||', '!'):
+///  - *expression*: term [or term]*
+///  - *term*: factor [and factor]*
+///  - *factor*: not factor | ( expression ) | primitive
+///  - *primitive*: [src|dst] host IP | [src|dst] net IP/PREFIX | [src|dst] port N[-M] | [src|dst] portrange N-M | proto NAME | NAME | vlan ID
+///
+/// Primitives without direction match both the source and the destination of the packet. 'vlan ID' matches the frames with an 802.1Q tag (outer or inner) with that VLAN ID.
+/// The protocols (tcp, udp, dns, tls, http, https, arp, icmp, icmp6, ip, ip6, ether) match the packets carrying them at any layer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FilterExpression {
+    And(Box<FilterExpression>, Box<FilterExpression>),
+    Or(Box<FilterExpression>, Box<FilterExpression>),
+    Not(Box<FilterExpression>),
+    Host(Direction, IpAddr),
+    Net(Direction, IpAddr, u8),
+    Port(Direction, u16, u16),
+    Proto(Protocol),
+    Vlan(u16),
+}
+
+impl FromStr for FilterExpression {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(input);
+        if tokens.is_empty() {
+            return Err("Empty filter expression".to_string());
+        }
+
+        let mut parser = Parser { tokens, pos: 0 };
+        let expression = parser.parse_expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected '{}' in the filter expression", token));
+        }
+        return Ok(expression);
+    }
+}
+
+impl Display for FilterExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterExpression::And(left, right) => write!(f, "({} and {})", left, right),
+            FilterExpression::Or(left, right) => write!(f, "({} or {})", left, right),
+            FilterExpression::Not(expression) => write!(f, "not {}", expression),
+            FilterExpression::Host(dir, ip) => write!(f, "{}host {}", dir, ip),
+            FilterExpression::Net(dir, ip, prefix) => write!(f, "{}net {}/{}", dir, ip, prefix),
+            FilterExpression::Port(dir, start, end) => {
+                if start == end { write!(f, "{}port {}", dir, start) } else { write!(f, "{}port {}-{}", dir, start, end) }
+            }
+            FilterExpression::Proto(protocol) => write!(f, "proto {}", protocol_keyword(*protocol)),
+            FilterExpression::Vlan(vlan_id) => write!(f, "vlan {}", vlan_id),
+        }
+    }
+}
+
+impl FilterExpression {
+    /// It evaluates the expression on the information extracted from a packet.
+    pub fn matches(&self, packet_info: &PacketInfo) -> bool {
+        return match self {
+            FilterExpression::And(left, right) => left.matches(packet_info) && right.matches(packet_info),
+            FilterExpression::Or(left, right) => left.matches(packet_info) || right.matches(packet_info),
+            FilterExpression::Not(expression) => !expression.matches(packet_info),
+            FilterExpression::Host(dir, ip) => {
+                match_direction(*dir, packet_info.get_ip_sorgente(), packet_info.get_ip_destinazione(),
+                                |addr| addr == Some(*ip))
+            }
+            FilterExpression::Net(dir, net, prefix) => {
+                match_direction(*dir, packet_info.get_ip_sorgente(), packet_info.get_ip_destinazione(),
+                                |addr| match addr {
+                                    Some(addr) => in_subnet(addr, *net, *prefix),
+                                    None => false
+                                })
+            }
+            FilterExpression::Port(dir, start, end) => {
+                match_direction(*dir, packet_info.get_porta_sorgente(), packet_info.get_porta_destinazione(),
+                                |prt| *start <= prt && prt <= *end)
+            }
+            FilterExpression::Proto(protocol) => packet_info.carries(*protocol),
+            FilterExpression::Vlan(vlan_id) => packet_info.get_vlan_ids().contains(vlan_id),
+        };
+    }
+
+    /// It compiles the expression in a BPF expression accepting a superset of the packets matched by it.
+    /// It returns None if the expression cannot be (even partially) evaluated by the kernel.
+    pub fn to_bpf(&self) -> Option<String> {
+        return self.to_bpf_exact().map(|(bpf, _)| bpf);
+    }
+
+    /// It compiles the expression in BPF. The boolean returned is true if the BPF expression matches exactly the same packets,
+    /// false if it matches a superset of them (e.g. 'proto dns' becomes 'udp or tcp'), which cannot be negated.
+    fn to_bpf_exact(&self) -> Option<(String, bool)> {
+        return match self {
+            FilterExpression::And(left, right) => {
+                match (left.to_bpf_exact(), right.to_bpf_exact()) {
+                    (Some((l, l_exact)), Some((r, r_exact))) => Some((format!("({} and {})", l, r), l_exact && r_exact)),
+                    // A superset of one of the two operands is a superset of the 'and'
+                    (Some((l, _)), None) => Some((l, false)),
+                    (None, Some((r, _))) => Some((r, false)),
+                    (None, None) => None,
+                }
+            }
+            FilterExpression::Or(left, right) => {
+                match (left.to_bpf_exact(), right.to_bpf_exact()) {
+                    (Some((l, l_exact)), Some((r, r_exact))) => Some((format!("({} or {})", l, r), l_exact && r_exact)),
+                    _ => None,
+                }
+            }
+            FilterExpression::Not(expression) => {
+                match expression.to_bpf_exact() {
+                    Some((bpf, true)) => Some((format!("not {}", bpf), true)),
+                    _ => None,
+                }
+            }
+            FilterExpression::Host(dir, ip) => Some((format!("{}host {}", dir, ip), true)),
+            FilterExpression::Net(dir, ip, prefix) => Some((format!("{}net {}/{}", dir, ip, prefix), true)),
+            // The ports are known by the kernel only on the first fragment and without IPv6 extension headers: the other packets
+            // are let through to be checked after the reassembly (and the packets without ports are reported with port 0)
+            FilterExpression::Port(dir, start, end) => {
+                let bpf = if start == end { format!("{}port {}", dir, start) } else { format!("{}portrange {}-{}", dir, start, end) };
+                Some((transport_tolerant_bpf(&bpf), false))
+            }
+            // Only the link and network layer protocols are matched exactly: the transport and application layer ones are let through in the same cases of the ports
+            FilterExpression::Proto(protocol) => {
+                let exact = matches!(protocol, Protocol::Arp | Protocol::IpV4 | Protocol::IpV6);
+                protocol.to_bpf().map(|bpf| (bpf, exact))
+            }
+            // The BPF 'vlan' primitive shifts the offsets of the following ones: the VLAN ID is checked in user space
+            FilterExpression::Vlan(_) => None,
+        };
+    }
+}
+
+/// It applies the check to the source and/or the destination value, based on the direction.
+fn match_direction<T, F: Fn(T) -> bool>(dir: Direction, src: T, dst: T, check: F) -> bool {
+    return match dir {
+        Direction::Src => check(src),
+        Direction::Dst => check(dst),
+        Direction::Any => check(src) || check(dst),
+    };
+}
+
+/// It checks whether the address belongs to the subnet net/prefix.
+fn in_subnet(addr: IpAddr, net: IpAddr, prefix: u8) -> bool {
+    return match (addr, net) {
+        (IpAddr::V4(addr), IpAddr::V4(net)) => {
+            let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix as u32) };
+            u32::from(addr) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(addr), IpAddr::V6(net)) => {
+            let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix as u32) };
+            u128::from(addr) & mask == u128::from(net) & mask
+        }
+        _ => false
+    };
+}
+
+/// Keyword used in the expressions for each protocol
+fn protocol_keyword(protocol: Protocol) -> &'static str {
+    return match protocol {
+        Protocol::Ethernet => "ether",
+        Protocol::Arp => "arp",
+        Protocol::IpV4 => "ip",
+        Protocol::IpV6 => "ip6",
+        Protocol::Udp => "udp",
+        Protocol::Tcp => "tcp",
+        Protocol::IcmpV4 => "icmp",
+        Protocol::IcmpV6 => "icmp6",
+        Protocol::Dns => "dns",
+        Protocol::Tls => "tls",
+        Protocol::Http => "http",
+        Protocol::Https => "https",
+        Protocol::None => "none",
+    };
+}
+
+/// It returns the protocol identified by the keyword (case insensitive)
+pub(crate) fn parse_protocol(keyword: &str) -> Option<Protocol> {
+    return match keyword.to_lowercase().as_str() {
+        "ether" | "ethernet" => Some(Protocol::Ethernet),
+        "arp" => Some(Protocol::Arp),
+        "ip" | "ipv4" => Some(Protocol::IpV4),
+        "ip6" | "ipv6" => Some(Protocol::IpV6),
+        "udp" => Some(Protocol::Udp),
+        "tcp" => Some(Protocol::Tcp),
+        "icmp" | "icmpv4" => Some(Protocol::IcmpV4),
+        "icmp6" | "icmpv6" => Some(Protocol::IcmpV6),
+        "dns" => Some(Protocol::Dns),
+        "tls" => Some(Protocol::Tls),
+        "http" => Some(Protocol::Http),
+        "https" => Some(Protocol::Https),
+        _ => None,
+    };
+}
+
+/// It splits the expression in tokens: words, parenthesis and '!'
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+
+    for c in input.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' || c == '!' {
+            if !word.is_empty() {
+                tokens.push(word.clone());
+                word.clear();
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+
+    return tokens;
+}
+
+/// Recursive descent parser of the filter expressions
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    /// It returns the current token, without consuming it
+    fn peek(&self) -> Option<&str> {
+        return self.tokens.get(self.pos).map(|t| t.as_str());
+    }
+
+    /// It consumes and returns the current token
+    fn next(&mut self) -> Result<String, String> {
+        return match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => Err("Unexpected end of the filter expression".to_string())
+        };
+    }
+
+    /// expression: term [or term]*
+    fn parse_expression(&mut self) -> Result<FilterExpression, String> {
+        let mut left = self.parse_term()?;
+        while let Some("or") | Some("||") = self.peek() {
+            self.pos += 1;
+            let right = self.parse_term()?;
+            left = FilterExpression::Or(Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    /// term: factor [and factor]*
+    fn parse_term(&mut self) -> Result<FilterExpression, String> {
+        let mut left = self.parse_factor()?;
+        while let Some("and") | Some("&&") = self.peek() {
+            self.pos += 1;
+            let right = self.parse_factor()?;
+            left = FilterExpression::And(Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    /// factor: not factor | ( expression ) | primitive
+    fn parse_factor(&mut self) -> Result<FilterExpression, String> {
+        let token = self.next()?;
+        return match token.as_str() {
+            "not" | "!" => Ok(FilterExpression::Not(Box::new(self.parse_factor()?))),
+            "(" => {
+                let expression = self.parse_expression()?;
+                match self.next()?.as_str() {
+                    ")" => Ok(expression),
+                    other => Err(format!("Expected ')' instead of '{}'", other))
+                }
+            }
+            _ => self.parse_primitive(token),
+        };
+    }
+
+    /// primitive: [src|dst] host IP | [src|dst] net IP/PREFIX | [src|dst] port N[-M] | [src|dst] portrange N-M | proto NAME | NAME | vlan ID
+    fn parse_primitive(&mut self, token: String) -> Result<FilterExpression, String> {
+        let (dir, keyword) = match token.as_str() {
+            "src" => (Direction::Src, self.next()?),
+            "dst" => (Direction::Dst, self.next()?),
+            _ => (Direction::Any, token),
+        };
+
+        return match keyword.as_str() {
+            "host" => {
+                let value = self.next()?;
+                match value.parse::<IpAddr>() {
+                    Ok(ip) => Ok(FilterExpression::Host(dir, ip)),
+                    Err(_) => Err(format!("'{}' is not an IPV4 or IPV6 address", value))
+                }
+            }
+            "net" => {
+                let value = self.next()?;
+                let (ip, prefix) = parse_subnet(&value)?;
+                Ok(FilterExpression::Net(dir, ip, prefix))
+            }
+            "port" | "portrange" => {
+                let value = self.next()?;
+                let (start, end) = parse_port_range(&value)?;
+                Ok(FilterExpression::Port(dir, start, end))
+            }
+            _ if dir != Direction::Any => Err(format!("Expected 'host', 'net', 'port' or 'portrange' after '{}' instead of '{}'", if dir == Direction::Src { "src" } else { "dst" }, keyword)),
+            "proto" => {
+                let value = self.next()?;
+                match parse_protocol(&value) {
+                    Some(protocol) => Ok(FilterExpression::Proto(protocol)),
+                    None => Err(format!("Unknown protocol '{}'", value))
+                }
+            }
+            "vlan" => {
+                let value = self.next()?;
+                match value.parse::<u16>() {
+                    Ok(vlan_id) if vlan_id < 4096 => Ok(FilterExpression::Vlan(vlan_id)),
+                    _ => Err(format!("'{}' is not a VLAN ID (0-4095)", value))
+                }
+            }
+            _ => {
+                match parse_protocol(&keyword) {
+                    Some(protocol) => Ok(FilterExpression::Proto(protocol)),
+                    None => Err(format!("Unknown primitive '{}' in the filter expression", keyword))
+                }
+            }
+        };
+    }
+}
+
+/// It parses a subnet in CIDR notation (e.g. 10.0.0.0/8). A single address is considered a subnet with full prefix.
+/// The host bits of the address are cleared (10.1.2.3/8 becomes 10.0.0.0/8).
+fn parse_subnet(value: &str) -> Result<(IpAddr, u8), String> {
+    let (ip_str, prefix_str) = match value.split_once('/') {
+        Some((ip, prefix)) => (ip, Some(prefix)),
+        None => (value, None)
+    };
+
+    let ip = match ip_str.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => return Err(format!("'{}' is not an IPV4 or IPV6 address", ip_str))
+    };
+    let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+
+    let prefix = match prefix_str {
+        Some(prefix) => match prefix.parse::<u8>() {
+            Ok(prefix) if prefix <= max_prefix => prefix,
+            _ => return Err(format!("'{}' is not a valid prefix length for {}", prefix, ip))
+        },
+        None => max_prefix
+    };
+
+    let net = match ip {
+        IpAddr::V4(ip) => {
+            let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix as u32) };
+            IpAddr::V4((u32::from(ip) & mask).into())
+        }
+        IpAddr::V6(ip) => {
+            let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix as u32) };
+            IpAddr::V6((u128::from(ip) & mask).into())
+        }
+    };
+
+    return Ok((net, prefix));
+}
+
+/// It parses a port (e.g. 22) or a range of ports (e.g. 1000-2000)
+fn parse_port_range(value: &str) -> Result<(u16, u16), String> {
+    let parse_port = |prt: &str| match prt.parse::<u16>() {
+        Ok(prt) => Ok(prt),
+        Err(_) => Err(format!("'{}' is not a valid port", prt))
+    };
+
+    return match value.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_port(start)?, parse_port(end)?);
+            if start > end {
+                return Err(format!("Invalid port range '{}'", value));
+            }
+            Ok((start, end))
+        }
+        None => {
+            let prt = parse_port(value)?;
+            Ok((prt, prt))
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use super::*;
+
+    fn parse(input: &str) -> FilterExpression {
+        return input.parse::<FilterExpression>().unwrap();
+    }
+
+    fn parse_err(input: &str) -> String {
+        return input.parse::<FilterExpression>().unwrap_err();
+    }
+
+    fn host(a: u8, b: u8, c: u8, d: u8) -> FilterExpression {
+        return FilterExpression::Host(Direction::Any, IpAddr::V4(Ipv4Addr::new(a, b, c, d)));
+    }
+
+    #[test]
+    fn tokenize_splits_parenthesis_and_bang() {
+        assert_eq!(tokenize("!(host 1.2.3.4||port 53)"), vec!["!", "(", "host", "1.2.3.4||port", "53", ")"]);
+        assert_eq!(tokenize("  not\t(tcp)  "), vec!["not", "(", "tcp", ")"]);
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn parse_not_or_and_vlan() {
+        let expected = FilterExpression::And(
+            Box::new(FilterExpression::Not(Box::new(FilterExpression::Or(
+                Box::new(host(1, 2, 3, 4)),
+                Box::new(FilterExpression::Port(Direction::Any, 53, 53)),
+            )))),
+            Box::new(FilterExpression::Vlan(10)),
+        );
+        assert_eq!(parse("not (host 1.2.3.4 or port 53) and vlan 10"), expected);
+        assert_eq!(parse("! (host 1.2.3.4 || port 53) && vlan 10"), expected);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expected = FilterExpression::Or(
+            Box::new(host(1, 1, 1, 1)),
+            Box::new(FilterExpression::And(Box::new(host(2, 2, 2, 2)), Box::new(FilterExpression::Proto(Protocol::Tcp)))),
+        );
+        assert_eq!(parse("host 1.1.1.1 or host 2.2.2.2 and tcp"), expected);
+        // 'not' applies only to the following factor
+        assert_eq!(parse("not tcp and udp"), FilterExpression::And(
+            Box::new(FilterExpression::Not(Box::new(FilterExpression::Proto(Protocol::Tcp)))),
+            Box::new(FilterExpression::Proto(Protocol::Udp)),
+        ));
+    }
+
+    #[test]
+    fn parse_primitives() {
+        assert_eq!(parse("src net 10.1.2.3/8"), FilterExpression::Net(Direction::Src, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8));
+        assert_eq!(parse("dst portrange 1000-2000"), FilterExpression::Port(Direction::Dst, 1000, 2000));
+        assert_eq!(parse("port 1000-2000"), FilterExpression::Port(Direction::Any, 1000, 2000));
+        assert_eq!(parse("proto DNS"), FilterExpression::Proto(Protocol::Dns));
+        assert_eq!(parse("icmp6"), FilterExpression::Proto(Protocol::IcmpV6));
+        assert_eq!(parse("host ::1"), FilterExpression::Host(Direction::Any, "::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for input in ["not (host 1.2.3.4 or port 53) and vlan 10", "src net 10.0.0.0/8 or dst port 1-1023", "proto https and not tcp"] {
+            let expression = parse(input);
+            assert_eq!(parse(&expression.to_string()), expression);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse_err(""), "Empty filter expression");
+        assert_eq!(parse_err("host"), "Unexpected end of the filter expression");
+        assert_eq!(parse_err("(host 1.2.3.4"), "Unexpected end of the filter expression");
+        assert_eq!(parse_err("(host 1.2.3.4 tcp)"), "Expected ')' instead of 'tcp'");
+        assert_eq!(parse_err("host 1.2.3.4)"), "Unexpected ')' in the filter expression");
+        assert_eq!(parse_err("host 1.2.3"), "'1.2.3' is not an IPV4 or IPV6 address");
+        assert_eq!(parse_err("src tcp"), "Expected 'host', 'net', 'port' or 'portrange' after 'src' instead of 'tcp'");
+        assert_eq!(parse_err("net 10.0.0.0/33"), "'33' is not a valid prefix length for 10.0.0.0");
+        assert_eq!(parse_err("port 20-10"), "Invalid port range '20-10'");
+        assert_eq!(parse_err("port 70000"), "'70000' is not a valid port");
+        assert_eq!(parse_err("vlan 4096"), "'4096' is not a VLAN ID (0-4095)");
+        assert_eq!(parse_err("proto foo"), "Unknown protocol 'foo'");
+        assert_eq!(parse_err("foo"), "Unknown primitive 'foo' in the filter expression");
+    }
+
+    #[test]
+    fn bpf_of_exact_primitives() {
+        assert_eq!(parse("src host 1.2.3.4").to_bpf_exact(), Some(("src host 1.2.3.4".to_string(), true)));
+        assert_eq!(parse("not net 10.0.0.0/8").to_bpf_exact(), Some(("not net 10.0.0.0/8".to_string(), true)));
+        assert_eq!(parse("arp or ip6").to_bpf_exact(), Some(("(arp or ip6)".to_string(), true)));
+    }
+
+    #[test]
+    fn bpf_of_transport_primitives_is_not_exact() {
+        assert_eq!(parse("port 53").to_bpf_exact(), Some((transport_tolerant_bpf("port 53"), false)));
+        assert_eq!(parse("dst port 1-1023").to_bpf_exact(), Some((transport_tolerant_bpf("dst portrange 1-1023"), false)));
+        assert_eq!(parse("tcp").to_bpf_exact(), Some((transport_tolerant_bpf("tcp"), false)));
+        assert_eq!(parse("proto dns").to_bpf_exact(), Some((transport_tolerant_bpf("udp or tcp"), false)));
+        // A superset cannot be negated
+        assert_eq!(parse("not port 22").to_bpf_exact(), None);
+        assert_eq!(parse("not udp").to_bpf(), None);
+    }
+
+    #[test]
+    fn bpf_of_composite_expressions() {
+        // The VLAN ID is checked in user space: the 'and' keeps a superset, the 'or' cannot be pushed down
+        assert_eq!(parse("host 1.2.3.4 and vlan 10").to_bpf_exact(), Some(("host 1.2.3.4".to_string(), false)));
+        assert_eq!(parse("host 1.2.3.4 or vlan 10").to_bpf_exact(), None);
+        assert_eq!(parse("not (host 1.2.3.4 or port 53) and vlan 10").to_bpf_exact(), None);
+        assert_eq!(parse("not host 1.2.3.4 and port 53").to_bpf_exact(),
+                   Some((format!("(not host 1.2.3.4 and {})", transport_tolerant_bpf("port 53")), false)));
+    }
+
+    #[test]
+    fn matches_packet_info() {
+        let mut packet_info = PacketInfo::new();
+        packet_info.set_ip_sorgente(IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3)));
+        packet_info.set_ip_destinazione(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)));
+        packet_info.set_porta_sorgente(40000);
+        packet_info.set_porta_destinazione(53);
+        packet_info.set_vlan_id(10);
+
+        assert!(parse("src net 10.0.0.0/8 and dst port 53").matches(&packet_info));
+        assert!(parse("port 53 and vlan 10").matches(&packet_info));
+        assert!(!parse("not (host 1.2.3.4 or port 53) and vlan 10").matches(&packet_info));
+        assert!(!parse("dst net 10.0.0.0/8").matches(&packet_info));
+        assert!(parse("src portrange 30000-50000 or host 1.2.3.4").matches(&packet_info));
+    }
+}
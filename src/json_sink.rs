@@ -0,0 +1,308 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde_json::{json, Value};
+use crate::events::{Conversation, FinalSummary, IntervalSummary, NonIpCount};
+use crate::packet_handle::format_ethertype;
+use crate::{Filter, Protocol};
+use crate::report_sink::{ReportSink, SessionInfo};
+
+/// JsonSink object. It writes the reports as JSON documents: the report file contains the array of the time intervals,
+/// kept valid after every update, while the final report file contains the final summary and is written at the end of the sniffing.
+/// - *file*: report file, created (truncated) when the sink is created
+/// - *final_filename*: name of the final report file (.json)
+/// - *n_intervals*: number of time intervals written in the report file
+/// - *session*: parameters of the sniffing session (the active filter is written with each time interval and in the final report)
+pub struct JsonSink {
+    file: File,
+    final_filename: String,
+    n_intervals: usize,
+    session: Option<SessionInfo>,
+}
+
+impl JsonSink {
+    /// Create a new JsonSink object instance, creating the report file with an empty array.
+    /// It returns an error if the report file cannot be created.
+    /// - *filename*: name of the report file (.json)
+    /// - *final_filename*: name of the final report file (.json)
+    pub fn new(filename: String, final_filename: String) -> io::Result<Self> {
+        let mut file = File::create(&filename)?;
+        file.write_all(b"[\n]\n")?;
+        return Ok(JsonSink { file, final_filename, n_intervals: 0, session: None });
+    }
+}
+
+impl ReportSink for JsonSink {
+    fn begin(&mut self, session: &SessionInfo) -> io::Result<()> {
+        self.session = Some(session.clone());
+        return Ok(());
+    }
+
+    fn write_interval(&mut self, summary: &IntervalSummary) -> io::Result<()> {
+        // Overwrite the closing bracket of the array, then close it again after the new time interval
+        if self.n_intervals == 0 {
+            self.file.seek(SeekFrom::End(-2))?;
+        } else {
+            self.file.seek(SeekFrom::End(-3))?;
+            self.file.write_all(b",\n")?;
+        }
+        serde_json::to_writer(&mut self.file, &interval_to_json(summary, self.session.as_ref()))?;
+        self.file.write_all(b"\n]\n")?;
+        self.n_intervals += 1;
+        return self.file.flush();
+    }
+
+    fn write_final(&mut self, summary: &FinalSummary) -> io::Result<()> {
+        println!("> Writing final report in {} ...", self.final_filename);
+        let mut final_file = BufWriter::new(File::create(&self.final_filename)?);
+        serde_json::to_writer_pretty(&mut final_file, &final_to_json(summary, self.session.as_ref()))?;
+        final_file.write_all(b"\n")?;
+        return final_file.flush();
+    }
+}
+
+/// NdjsonSink object. It writes the reports as newline delimited JSON: every time interval is appended to the report file as a line,
+/// while the final report file contains the final summary in a single line and is written at the end of the sniffing.
+/// - *file*: report file, created (truncated) when the sink is created
+/// - *final_filename*: name of the final report file (.ndjson)
+/// - *session*: parameters of the sniffing session (the active filter is written in each line)
+pub struct NdjsonSink {
+    file: BufWriter<File>,
+    final_filename: String,
+    session: Option<SessionInfo>,
+}
+
+impl NdjsonSink {
+    /// Create a new NdjsonSink object instance, creating the report file.
+    /// It returns an error if the report file cannot be created.
+    /// - *filename*: name of the report file (.ndjson)
+    /// - *final_filename*: name of the final report file (.ndjson)
+    pub fn new(filename: String, final_filename: String) -> io::Result<Self> {
+        return Ok(NdjsonSink { file: BufWriter::new(File::create(&filename)?), final_filename, session: None });
+    }
+}
+
+impl ReportSink for NdjsonSink {
+    fn begin(&mut self, session: &SessionInfo) -> io::Result<()> {
+        self.session = Some(session.clone());
+        return Ok(());
+    }
+
+    fn write_interval(&mut self, summary: &IntervalSummary) -> io::Result<()> {
+        serde_json::to_writer(&mut self.file, &interval_to_json(summary, self.session.as_ref()))?;
+        self.file.write_all(b"\n")?;
+        return self.file.flush();
+    }
+
+    fn write_final(&mut self, summary: &FinalSummary) -> io::Result<()> {
+        println!("> Writing final report in {} ...", self.final_filename);
+        let mut final_file = BufWriter::new(File::create(&self.final_filename)?);
+        serde_json::to_writer(&mut final_file, &final_to_json(summary, self.session.as_ref()))?;
+        final_file.write_all(b"\n")?;
+        return final_file.flush();
+    }
+}
+
+/// It returns the JSON object describing a time interval.
+/// The times are absolute (seconds since the UNIX epoch, with fractional part), except 'interval_offset' (seconds since the initial time of the sniffing).
+fn interval_to_json(summary: &IntervalSummary, session: Option<&SessionInfo>) -> Value {
+    let initial_time = summary.get_initial_time();
+    return json!({
+        "interval_start": initial_time.map(|time| epoch_secs(time + Duration::from_secs(summary.get_interval_start()))),
+        "interval_offset": summary.get_interval_start(),
+        "time_interval": session.map(|session| session.get_time_interval()),
+        "filter": session.map(|session| filter_to_json(session.get_filter())),
+        "conversations": summary.get_conversations().iter().map(|conversation| conversation_to_json(conversation, initial_time)).collect::<Vec<Value>>(),
+        "fragmented_datagrams": { "reassembled": summary.get_reassembled(), "discarded": summary.get_discarded() },
+        "non_ip_frames": summary.get_non_ip().iter().map(non_ip_to_json).collect::<Vec<Value>>(),
+    });
+}
+
+/// It returns the JSON object describing the whole sniffing, with the parameters of the session.
+/// The times are absolute (seconds since the UNIX epoch, with fractional part).
+fn final_to_json(summary: &FinalSummary, session: Option<&SessionInfo>) -> Value {
+    let initial_time = summary.get_initial_time();
+    return json!({
+        "session": session.map(session_to_json),
+        "initial_time": initial_time.map(epoch_secs),
+        "packets": summary.get_packets(),
+        "conversations": summary.get_conversations().iter().map(|conversation| conversation_to_json(conversation, initial_time)).collect::<Vec<Value>>(),
+        "fragmented_datagrams": { "reassembled": summary.get_reassembled(), "discarded": summary.get_discarded() },
+        "non_ip_frames": summary.get_non_ip().iter().map(non_ip_to_json).collect::<Vec<Value>>(),
+        "ethernet_conversations": summary.get_mac_conversations().iter().map(|(key, stats)| json!({
+            "mac_srg": key.get_mac_srg().to_string(),
+            "mac_dest": key.get_mac_dest().to_string(),
+            "ethertypes": stats.get_ethertypes().iter().map(|ethertype| ethertype.0).collect::<Vec<u16>>(),
+            "ethertype_names": stats.get_ethertypes().iter().map(|ethertype| format_ethertype(*ethertype)).collect::<Vec<String>>(),
+            "tot_bytes": stats.get_tot_bytes(),
+            "tot_packets": stats.get_tot_packets(),
+        })).collect::<Vec<Value>>(),
+    });
+}
+
+/// It returns the JSON object describing the parameters of the sniffing session
+fn session_to_json(session: &SessionInfo) -> Value {
+    return json!({
+        "interfaces": session.get_interfaces(),
+        "capture_file": session.get_capture_file(),
+        "start_time": epoch_secs(session.get_start_time()),
+        "time_interval": session.get_time_interval(),
+        "bidirectional": session.is_bidirectional(),
+        "vlan_conversations": session.get_vlan_conversations(),
+        "filter": filter_to_json(session.get_filter()),
+    });
+}
+
+/// It returns the JSON object describing the filter: the fields not set are null
+fn filter_to_json(filter: &Filter) -> Value {
+    return json!({
+        "ip_srg": filter.get_ip_srg().map(|ip| ip.to_string()),
+        "ip_dest": filter.get_ip_dest().map(|ip| ip.to_string()),
+        "prt_srg": filter.get_prt_srg(),
+        "prt_dest": filter.get_prt_dest(),
+        "protocol": protocol_to_json(filter.get_protocol()),
+        "vlan_id": filter.get_vlan_id(),
+        "expression": filter.get_expression().map(|expression| expression.to_string()),
+    });
+}
+
+/// It returns the JSON object describing a conversation, with all the fields of its key and of its statistics.
+/// The starting and ending times are absolute (seconds since the UNIX epoch), the offsets are relative to the initial time of the sniffing,
+/// the durations and the round trip times are in seconds (null if not measured).
+/// The TCP counters are meaningful only if 'tcp' is true.
+fn conversation_to_json(conversation: &Conversation, initial_time: Option<SystemTime>) -> Value {
+    let key = conversation.get_key();
+    let stats = conversation.get_stats();
+    let starting_time = stats.get_starting_time().unwrap_or_default();
+    let ending_time = stats.get_ending_time().unwrap_or_default();
+    return json!({
+        "ip_srg": key.get_ip_srg().to_string(),
+        "prt_srg": key.get_prt_srg(),
+        "ip_dest": key.get_ip_dest().to_string(),
+        "prt_dest": key.get_prt_dest(),
+        "protocol": protocol_to_json(key.get_protocol()),
+        "interface": conversation.get_interface(),
+        "vlan_ids": key.get_vlan_ids(),
+        "tot_bytes": stats.get_tot_bytes(),
+        "tot_packets": stats.get_tot_packets(),
+        "starting_time": initial_time.map(|time| epoch_secs(time + starting_time)),
+        "ending_time": initial_time.map(|time| epoch_secs(time + ending_time)),
+        "start_offset": starting_time.as_secs_f64(),
+        "end_offset": ending_time.as_secs_f64(),
+        "duration": stats.get_duration().unwrap_or_default().as_secs_f64(),
+        "fwd_bytes": stats.get_fwd_bytes(),
+        "fwd_packets": stats.get_fwd_packets(),
+        "bwd_bytes": stats.get_bwd_bytes(),
+        "bwd_packets": stats.get_bwd_packets(),
+        "initiator": stats.get_initiator().map(|(ip, prt)| json!({ "ip": ip.to_string(), "prt": prt })),
+        "ipv6_extensions": stats.get_ipv6_extensions().to_vec().iter().map(|extension| extension.to_string()).collect::<Vec<String>>(),
+        "tcp": stats.is_tcp(),
+        "syn_packets": stats.get_syn_packets(),
+        "fin_packets": stats.get_fin_packets(),
+        "rst_packets": stats.get_rst_packets(),
+        "retransmissions": stats.get_retransmissions(),
+        "out_of_order": stats.get_out_of_order(),
+        "dup_acks": stats.get_dup_acks(),
+        "zero_windows": stats.get_zero_windows(),
+        "tcp_end": conversation.get_tcp_state().map(|state| state.connection_end()),
+        "handshake_rtt": stats.get_handshake_rtt().map(|rtt| rtt.as_secs_f64()),
+        "rtt_min": stats.get_rtt_min().map(|rtt| rtt.as_secs_f64()),
+        "rtt_avg": stats.get_rtt_avg().map(|rtt| rtt.as_secs_f64()),
+        "rtt_max": stats.get_rtt_max().map(|rtt| rtt.as_secs_f64()),
+    });
+}
+
+/// It returns the JSON value of a protocol: its name, or null if there is no protocol
+fn protocol_to_json(protocol: Protocol) -> Value {
+    return match protocol {
+        Protocol::None => Value::Null,
+        protocol => Value::String(protocol.to_string()),
+    };
+}
+
+/// It returns the JSON object describing the frames not carrying an IP packet with the same EtherType (null if not carried by the link layer)
+fn non_ip_to_json(count: &NonIpCount) -> Value {
+    return json!({
+        "ethertype": count.get_ethertype().map(|ethertype| ethertype.0),
+        "ethertype_name": count.get_ethertype().map(format_ethertype),
+        "frames": count.get_frames(),
+        "bytes": count.get_bytes(),
+    });
+}
+
+/// It returns the number of seconds (with fractional part) since the UNIX epoch
+fn epoch_secs(time: SystemTime) -> f64 {
+    return time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::path::PathBuf;
+    use super::*;
+    use crate::ConversationKey;
+    use crate::packet_handle::ConversationStats;
+
+    /// It returns a path in the temporary directory, unique for the test process
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("traffic_analyser_{}_{}", std::process::id(), name));
+    }
+
+    /// It returns the summary of a time interval with a single UDP conversation
+    fn interval(initial_time: SystemTime, interval_start: u64, tot_bytes: usize) -> IntervalSummary {
+        let key = ConversationKey::new_key(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                                           40000, 53, Protocol::Udp);
+        let stats = ConversationStats::new(tot_bytes, Duration::from_secs(interval_start), Duration::from_secs(interval_start + 1), 2);
+        let conversation = Conversation::new(key, stats, Some("eth0".to_string()), None);
+        return IntervalSummary::new(Some(initial_time), interval_start, vec![conversation], 0, 0, vec![]);
+    }
+
+    #[test]
+    fn json_report_is_a_valid_array_after_every_interval() {
+        let report = temp_path("report.json");
+        let final_report = temp_path("final_report.json");
+        let initial_time = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let session = SessionInfo::new(vec!["eth0".to_string()], None, Filter::new(), 5, false, false, initial_time);
+
+        let mut sink = JsonSink::new(report.to_string_lossy().into_owned(), final_report.to_string_lossy().into_owned()).unwrap();
+        sink.begin(&session).unwrap();
+        let parsed: Value = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+        assert_eq!(parsed, json!([]));
+
+        sink.write_interval(&interval(initial_time, 0, 100)).unwrap();
+        let parsed: Value = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 1);
+
+        sink.write_interval(&interval(initial_time, 5, 200)).unwrap();
+        let parsed: Value = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+        let intervals = parsed.as_array().unwrap();
+        assert_eq!(intervals.len(), 2);
+        assert_eq!(intervals[0]["interval_offset"], json!(0));
+        assert_eq!(intervals[1]["interval_offset"], json!(5));
+        assert_eq!(intervals[1]["interval_start"], json!(1_000_005.0));
+        assert_eq!(intervals[1]["time_interval"], json!(5));
+        let conversation = &intervals[1]["conversations"][0];
+        assert_eq!(conversation["ip_srg"], json!("10.0.0.1"));
+        assert_eq!(conversation["prt_dest"], json!(53));
+        assert_eq!(conversation["protocol"], json!("UDP"));
+        assert_eq!(conversation["interface"], json!("eth0"));
+        assert_eq!(conversation["tot_bytes"], json!(200));
+        assert_eq!(conversation["starting_time"], json!(1_000_005.0));
+
+        let conversations = vec![Conversation::new(*interval(initial_time, 0, 300).get_conversations()[0].get_key(),
+                                                   ConversationStats::new(300, Duration::ZERO, Duration::from_secs(6), 4),
+                                                   None, None)];
+        sink.write_final(&FinalSummary::new(Some(initial_time), 4, conversations, 0, 0, vec![], vec![])).unwrap();
+        let parsed: Value = serde_json::from_str(&fs::read_to_string(&final_report).unwrap()).unwrap();
+        assert_eq!(parsed["packets"], json!(4));
+        assert_eq!(parsed["initial_time"], json!(1_000_000.0));
+        assert_eq!(parsed["session"]["interfaces"], json!(["eth0"]));
+        assert_eq!(parsed["conversations"][0]["tot_bytes"], json!(300));
+        assert_eq!(parsed["conversations"][0]["duration"], json!(6.0));
+
+        fs::remove_file(report).unwrap();
+        fs::remove_file(final_report).unwrap();
+    }
+}
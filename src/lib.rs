@@ -1,22 +1,53 @@
+// The functions return their value with an explicit 'return' statement
+#![allow(clippy::needless_return)]
+
 mod packet_handle;
+mod filter_expression;
 mod sniffer;
 mod reporter;
-
-use pcap::{Active, Capture, Device};
+mod dumper;
+mod tcp_tracker;
+mod reassembly;
+mod builder;
+mod config;
+mod events;
+mod report_sink;
+mod table_sink;
+mod csv_sink;
+mod json_sink;
+mod metrics;
+#[cfg(feature = "sqlite")]
+mod sqlite_sink;
+
+use pcap::{Activated, Active, Capture, Device, Linktype, Offline, Precision};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::io;
 use std::io::Write;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::path::Path;
 use std::sync::{Arc, Condvar, Mutex};
-use std::sync::mpsc::{channel};
-use packet_handle::{Filter};
+use std::sync::atomic::AtomicUsize;
+use std::sync::mpsc::{channel, sync_channel, Receiver};
+pub use packet_handle::{ConversationKey, ConversationStats, Filter, Ipv6Extension, Ipv6Extensions, MacConversationKey, MacConversationStats, PacketInfo, Protocol};
+pub use filter_expression::{Direction, FilterExpression};
+pub use builder::{NetworkAnalyserBuilder, DEFAULT_REPORT_FILE, DEFAULT_TIME_INTERVAL};
+pub use config::{Config, FilterConfig, ReassemblyConfig, SavefileConfig};
+pub use events::{AnalyserEvent, Conversation, FinalSummary, IntervalSummary, NonIpCount, SUBSCRIBER_CAPACITY};
+pub use tcp_tracker::TcpState;
+pub use report_sink::{ReportFormat, ReportSink, SessionInfo, REPORT_FORMATS};
+#[cfg(feature = "sqlite")]
+pub use sqlite_sink::SqliteSink;
 use std::thread::{self, JoinHandle};
-use std::time::{SystemTime};
+use std::time::{Duration, SystemTime};
 use regex::Regex;
-use crate::packet_handle::{Protocol};
+use crate::dumper::Dumper;
+use crate::events::Subscriber;
+use crate::metrics::{Metrics, serve_metrics};
+use crate::reassembly::{DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP, Reassembler};
+use crate::report_sink::with_extension;
 use crate::reporter::Reporter;
-use crate::sniffer::Sniffer;
+use crate::sniffer::{Sniffer, SnifferOptions};
 
 
 #[derive(Debug)]
@@ -26,7 +57,8 @@ pub enum ErrorNetworkAnalyser {
     ErrorResume(String),
     ErrorPause(String),
     ErrorNa(String),
-    ErrorAbort(String)
+    ErrorAbort(String),
+    ErrorConfig(String)
 }
 
 impl Display for ErrorNetworkAnalyser {
@@ -36,7 +68,8 @@ impl Display for ErrorNetworkAnalyser {
             ErrorNetworkAnalyser::ErrorResume(msg) => write!(f, "{}", msg),
             ErrorNetworkAnalyser::ErrorPause(msg) => write!(f, "{}", msg),
             ErrorNetworkAnalyser::ErrorNa(msg) => write!(f, "{}", msg),
-            ErrorNetworkAnalyser::ErrorAbort(msg) => write!(f, "{}", msg)
+            ErrorNetworkAnalyser::ErrorAbort(msg) => write!(f, "{}", msg),
+            ErrorNetworkAnalyser::ErrorConfig(msg) => write!(f, "{}", msg)
         }
     }
 }
@@ -56,6 +89,12 @@ pub struct Status {
     cvar: Condvar,
 }
 
+impl Default for Status {
+    fn default() -> Self {
+        return Status::new();
+    }
+}
+
 impl Status {
     /// Function to create a new instance of the Status object
     pub fn new() -> Status {
@@ -64,56 +103,194 @@ impl Status {
             cvar: Condvar::new(),
         }
     }
+
+    /// It puts the application in 'Exit' state, waking up the threads waiting while paused
+    fn exit(&self) {
+        let mut status_value = self.mutex.lock().unwrap();
+        *status_value = StatusValue::Exit;
+        self.cvar.notify_all();
+    }
+}
+
+#[derive(Clone)]
+/// Handle used to stop the NetworkAnalyser from another thread (e.g. a signal handler), while 'wait()' is waiting for its end.
+pub struct StopHandle {
+    status: Arc<Status>,
 }
 
-/// NetworkAnalyser object. It manages all the sniffing process by creating two threads: the Sniffer and the Reporter.
+impl StopHandle {
+    /// It puts the application in 'Exit' state: the sniffing ends and the final report gets written
+    pub fn stop(&self) {
+        self.status.exit();
+    }
+}
+
+/// NetworkAnalyser object. It manages all the sniffing process by creating the threads: a Sniffer for each network interface and the Reporter.
+/// The packets are sniffed from the network interfaces or, if a capture file is set, read from a pcap/pcapng file (by a single Sniffer).
+/// All the Sniffers send the packets to the same Reporter, so that the report contains the conversations of all the interfaces.
+/// If a savefile is set, the packets are also saved in it ("tee" mode), eventually rotating it by size or time.
+/// The filter (and the raw BPF expression, if set) are compiled in a BPF program applied by the kernel, so that the packets not needed are not even copied
+/// (unless all the packets are saved in the savefile: then the filter is checked in user space).
+/// If bidirectional is set, the packets A->B and B->A are aggregated in the same conversation (client -> server).
+/// If vlan_conversations is set, the same endpoints talking on different VLANs form different conversations.
+/// The TCP streams are reassembled (with bounded memory) so that the application layer messages split across several segments are recognised.
+/// If a duration or a packet count is set, the process ends by itself when the limit is reached.
+/// The reports are written in each of the report formats (in files named as the report file, with the extension of the format), and in the sinks added by the user.
+/// If a database is set (feature "sqlite"), the conversations of every time interval are also stored in it, appended to the ones of the previous sessions.
+/// If a metrics address is set, the counters of the sniffing are served on 'http://<address>/metrics' in the Prometheus text format, until the end of the sniffing.
 /// The user can control the process by using the functions pause(), resume(), quit()
 pub struct NetworkAnalyser {
-    interface: Device,
+    interfaces: Vec<Device>,
+    capture_file: Option<String>,
     time_interval: usize,
     filename: String,
     final_filename: String,
+    report_formats: Vec<ReportFormat>,
+    sinks: Vec<Box<dyn ReportSink>>,
+    database: Option<String>,
     filter: Filter,
-    sniffer_handle: Option<JoinHandle<()>>,
+    bpf_expression: Option<String>,
+    savefile: Option<String>,
+    savefile_only_filtered: bool,
+    savefile_max_size: Option<u64>,
+    savefile_rotation: Option<u64>,
+    bidirectional: bool,
+    vlan_conversations: bool,
+    reassembly_flow_cap: usize,
+    reassembly_total_cap: usize,
+    duration: Option<u64>,
+    packet_count: Option<usize>,
+    metrics_address: Option<SocketAddr>,
+    sniffer_handles: Vec<JoinHandle<()>>,
     reporter_handle: Option<JoinHandle<()>>,
+    metrics_handle: Option<JoinHandle<()>>,
     status: Arc<Status>,
+    subscribers: Vec<Subscriber>,
 }
 
 impl Display for NetworkAnalyser {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let source = match &self.capture_file {
+            Some(path) => format!("Capture file: '{}'", path),
+            None => format!("Interfaces: {}", self.interfaces.iter()
+                .map(|interface| interface.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")),
+        };
+        let savefile = match &self.savefile {
+            Some(name) => format!("'{}' [{} packets, rotation size: {}, rotation time: {}]", name,
+                                  if self.savefile_only_filtered { "filtered" } else { "all" },
+                                  match self.savefile_max_size {
+                                      Some(size) => format!("{} bytes", size),
+                                      None => "-".to_string()
+                                  },
+                                  match self.savefile_rotation {
+                                      Some(secs) => format!("{} secs", secs),
+                                      None => "-".to_string()
+                                  }),
+            None => "None".to_string(),
+        };
         write!(f, "> NETWORK ANALYSER: \n\n\
-                   >> Interface: {}; \n\
+                   >> {}; \n\
                    >> Time Interval: {} secs; \n\
                    >> Filename: '{}'; \n\
                    >> Final Filename: '{}'; \n\
-                   >> Filter: {};\n", self.interface.name, self.time_interval,
-                                      self.filename, self.final_filename, self.filter)
+                   >> Report formats: {}; \n\
+                   >> Database: {}; \n\
+                   >> Filter: {};\n\
+                   >> BPF expression: {};\n\
+                   >> Savefile: {};\n\
+                   >> Bidirectional conversations: {};\n\
+                   >> Conversations by VLAN: {};\n\
+                   >> TCP reassembly memory: {} bytes per stream, {} bytes in total;\n\
+                   >> Limits: duration {}, packets {};\n\
+                   >> Metrics endpoint: {};\n", source, self.time_interval,
+                                      self.filename, self.final_filename,
+                                      self.report_formats.iter().map(|format| format.to_string()).collect::<Vec<String>>().join(", "),
+                                      self.database.as_deref().map(|path| format!("'{}'", path)).unwrap_or("None".to_string()),
+                                      self.filter,
+                                      self.bpf_expression.as_deref().unwrap_or("None"), savefile,
+                                      if self.bidirectional { "Yes" } else { "No" },
+                                      if self.vlan_conversations { "Yes" } else { "No" },
+                                      self.reassembly_flow_cap, self.reassembly_total_cap,
+                                      match self.duration {
+                                          Some(secs) => format!("{} secs", secs),
+                                          None => "-".to_string()
+                                      },
+                                      match self.packet_count {
+                                          Some(count) => count.to_string(),
+                                          None => "-".to_string()
+                                      },
+                                      match self.metrics_address {
+                                          Some(address) => format!("http://{}/metrics", address),
+                                          None => "None".to_string()
+                                      })
     }
 }
 
 impl NetworkAnalyser {
     /// Create a new instance of the NetworkAnalyser and set default values to its fields
-    /// - Network Interface: selected the first one.
+    /// - Network Interfaces: selected the first one.
+    /// - Capture file: none. Packets are sniffed from the network interface
     /// - Time interval: 5 sec
     /// - Report file name: "report.txt"
     /// - Final report file name: "final_report.txt"
     /// - Filter: empty. No filter
+    /// - BPF expression: none
+    /// - Savefile: none. The packets are not saved
+    /// - Database: none. The conversations are not stored in a database
+    /// - Bidirectional: false. The packets A->B and B->A belong to different conversations
+    /// - Conversations by VLAN: false. The VLAN tags are not part of the conversations
+    /// - TCP reassembly memory: 64 KiB per stream, 16 MiB in total
+    /// - Duration and packet count: none. The process ends only calling the 'quit()' function (or at the end of the capture file)
+    /// - Metrics address: none. The metrics are not served
+    // No Default implementation: creating a NetworkAnalyser looks up the network interfaces, which may panic
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let dft_interface = select_device_by_name(find_my_device_name(0));
-        let dft_time_interval = 5;
-        let dft_filename = "report.txt".to_string(); //default report file
-        let dft_final_filename = "final_report.txt".to_string(); //default final report file
+        return Self::with_interfaces(vec![dft_interface]);
+    }
+
+    /// Create a new instance of the NetworkAnalyser, to configure it without the interactive prompts of 'init()'.
+    /// The configuration is checked when the NetworkAnalyser is built.
+    pub fn builder() -> NetworkAnalyserBuilder {
+        return NetworkAnalyserBuilder::new();
+    }
+
+    /// Create a new instance of the NetworkAnalyser sniffing the given network interfaces, with the default values of the other fields
+    fn with_interfaces(interfaces: Vec<Device>) -> Self {
+        let dft_time_interval = DEFAULT_TIME_INTERVAL;
+        let dft_filename = DEFAULT_REPORT_FILE.to_string(); //default report file
+        let dft_final_filename = format!("final_{}", DEFAULT_REPORT_FILE); //default final report file
         let dft_filter = Filter::new();
 
         return Self {
-            interface: dft_interface,
+            interfaces,
+            capture_file: None,
             time_interval: dft_time_interval,
             filename: dft_filename,
             final_filename: dft_final_filename,
+            report_formats: vec![ReportFormat::Table],
+            sinks: vec![],
+            database: None,
             filter: dft_filter,
-            sniffer_handle: None,
+            bpf_expression: None,
+            savefile: None,
+            savefile_only_filtered: true,
+            savefile_max_size: None,
+            savefile_rotation: None,
+            bidirectional: false,
+            vlan_conversations: false,
+            reassembly_flow_cap: DEFAULT_FLOW_CAP,
+            reassembly_total_cap: DEFAULT_TOTAL_CAP,
+            duration: None,
+            packet_count: None,
+            metrics_address: None,
+            sniffer_handles: vec![],
             reporter_handle: None,
+            metrics_handle: None,
             status: Arc::new(Status::new()),
+            subscribers: vec![],
         };
     }
 
@@ -129,14 +306,40 @@ impl NetworkAnalyser {
         println!();
         println!("**************************************************************** INITIALIZATION  ***************************************************************");
 
-        // Get interface
-        self.interface = get_interface()?;
+        // Get the source of the packets: a capture file or a network interface
+        self.capture_file = get_capture_file()?;
+        if self.capture_file.is_none() {
+            self.interfaces = get_interfaces()?;
+        }
         self.time_interval = get_time_interval(self.time_interval)?;
-        self.filename = get_file_name(&*self.filename)?;
+        self.filename = get_file_name(&self.filename)?;
         self.final_filename = self.filename.clone();
         self.final_filename.insert_str(0, "final_");
+        // The format of the report is chosen by the extension of its file
+        let extension = Path::new(&self.filename).extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        self.report_formats = vec![ReportFormat::from_extension(extension).unwrap_or(ReportFormat::Table)];
 
         self.filter= get_filter()?;
+        self.bpf_expression = get_bpf_expression()?;
+
+        // Eventually save the packets in a savefile
+        if let Some((savefile, only_filtered)) = get_savefile()? {
+            self.savefile = Some(savefile);
+            self.savefile_only_filtered = only_filtered;
+            self.savefile_max_size = get_optional_value("> Rotate the savefile when it reaches this size in bytes:", ">> Max size (bytes): ")?;
+            self.savefile_rotation = get_optional_value("> Rotate the savefile every this number of seconds:", ">> Rotation time (s): ")?;
+        }
+
+        self.bidirectional = get_bidirectional()?;
+        self.vlan_conversations = get_vlan_conversations()?;
+
+        // Memory used to reassemble the TCP streams
+        if let Some(cap) = get_optional_value(&format!("> Maximum memory for the reassembly of a single TCP stream, in bytes (skip to keep the default: {}):", DEFAULT_FLOW_CAP), ">> Stream memory (bytes): ")? {
+            self.reassembly_flow_cap = cap as usize;
+        }
+        if let Some(cap) = get_optional_value(&format!("> Maximum memory for the reassembly of all the TCP streams, in bytes (skip to keep the default: {}):", DEFAULT_TOTAL_CAP), ">> Total memory (bytes): ")? {
+            self.reassembly_total_cap = cap as usize;
+        }
 
         println!();
         println!("************************************************************************************************************************************************");
@@ -151,63 +354,157 @@ impl NetworkAnalyser {
     /// It can return an ErrorNetworkAnalyser if an error occours during the process.
     /// Otherwise it returns void and it means that the process is running.
     pub fn start(&mut self) -> Result<(), ErrorNetworkAnalyser> {
-        let rcv_channel = activate_capture(&self.interface)?;
+        // Open a capture for each network interface (or the capture file),
+        // with the timestamp precision in effect (the capture files are always read in nanoseconds)
+        let captures: Vec<(Option<Device>, Capture<dyn Activated>, Precision)> = match &self.capture_file {
+            Some(path) => vec![(None, activate_offline_capture(path)?.into(), Precision::Nano)],
+            None => {
+                let mut captures = vec![];
+                for interface in &self.interfaces {
+                    let capture = activate_capture(interface)?;
+                    let precision = get_tstamp_precision(&capture);
+                    captures.push((Some(interface.clone()), capture.into(), precision));
+                }
+                captures
+            }
+        };
+
+        // Prepare all the captures before starting any Sniffer, so that an error does not leave some of them running
+        let mut sniffer_inputs = vec![];
+        for (interface, mut rcv_channel, precision) in captures {
+            // The filter is pushed down to the kernel (the BPF program depends on the link type of the capture)
+            let bpf_program = self.get_bpf_program(rcv_channel.get_datalink());
+            apply_bpf_program(&mut rcv_channel, bpf_program.as_deref())?;
+
+            // Eventually open the savefile where the packets will be saved (one for each interface, if there are more of them)
+            let dumper = match &self.savefile {
+                Some(savefile) => {
+                    let savefile = match &interface {
+                        Some(interface) if self.interfaces.len() > 1 => get_interface_savefile(savefile, &interface.name),
+                        _ => savefile.clone(),
+                    };
+                    Some(Dumper::new(savefile,
+                                     rcv_channel.get_datalink(),
+                                     precision,
+                                     self.savefile_only_filtered,
+                                     self.savefile_max_size,
+                                     self.savefile_rotation.map(Duration::from_secs))?)
+                }
+                None => None,
+            };
+            sniffer_inputs.push((interface, rcv_channel, precision, dumper));
+        }
+
+        // Create the report files of each format, then add the sinks of the user
+        let mut sinks = vec![];
+        for format in &self.report_formats {
+            sinks.push(format.create_sink(&self.filename, &self.final_filename)
+                .map_err(|err| ErrorNetworkAnalyser::ErrorNa(format!("> [Error]: cannot create the {} report: {}", format, err)))?);
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(database) = &self.database {
+            sinks.push(Box::new(SqliteSink::open(database)
+                .map_err(|err| ErrorNetworkAnalyser::ErrorNa(format!("> [Error]: cannot open the database '{}': {}", database, err)))?));
+        }
+        sinks.append(&mut self.sinks);
+
+        // Eventually listen on the address of the metrics endpoint
+        let metrics_listener = match self.metrics_address {
+            Some(address) => Some(TcpListener::bind(address)
+                .map_err(|err| ErrorNetworkAnalyser::ErrorNa(format!("> [Error]: cannot serve the metrics on {}: {}", address, err)))?),
+            None => None,
+        };
+        let metrics = metrics_listener.as_ref().map(|_| Arc::new(Metrics::new()));
 
         // Record initial time
         let time = SystemTime::now();
 
-        // Thread Sniffer
-        // - Create a channel shared by the sniffer and the reporter
+        // Create a channel shared by the sniffers and the reporter
         let (snd_sniffer, rcv_sniffer) = channel();
-        // - Clone the status, creating a copy of the pointer
-        let status_sniffer = self.status.clone();
-        // - Clone the network interface pointer. It will be used by the sniffer to get packets from the interface
-        let interface = self.interface.clone();
-        // - Clone the filter (needed by the sniffer to accordingly filter the packets)
-        let filter = self.filter.clone();
-        // - Clone the initial time
-        let time_sniffer = time.clone();
-
-        // Run the thread sniffer
-        self.sniffer_handle = Some(thread::spawn(move || {
-            let sniffer = Sniffer::new(interface, filter,snd_sniffer, rcv_channel, status_sniffer, time_sniffer);
-            sniffer.sniffing();
-        }));
+        // Packets still to be sniffed, shared by the sniffers (if the packet count is limited)
+        let remaining_packets = self.packet_count.map(|count| Arc::new(AtomicUsize::new(count)));
+
+        for (interface, rcv_channel, precision, dumper) in sniffer_inputs {
+            // Thread Sniffer
+            // - Clone the sender end of the channel
+            let snd_sniffer = snd_sniffer.clone();
+            // - Clone the status, creating a copy of the pointer
+            let status_sniffer = self.status.clone();
+            // - Clone the filter (needed by the sniffer to accordingly filter the packets)
+            let filter = self.filter.clone();
+            // - Options: the savefile, a new reassembler of the TCP streams, a clone of the limits of the sniffing and of the metrics (if served)
+            let options = SnifferOptions {
+                precision,
+                dumper,
+                reassembler: Reassembler::new(self.reassembly_flow_cap, self.reassembly_total_cap),
+                duration: self.duration.map(Duration::from_secs),
+                remaining_packets: remaining_packets.clone(),
+                metrics: metrics.clone(),
+            };
+
+            // Run the thread sniffer
+            self.sniffer_handles.push(thread::spawn(move || {
+                let sniffer = Sniffer::new(interface, filter, snd_sniffer, rcv_channel, status_sniffer, options);
+                sniffer.sniffing();
+            }));
+        }
 
         // Thread Reporter
+        // - Parameters of the session: clone the filter, the time interval, the names of the network interfaces sniffed
+        //   (none when reading a capture file) and the aggregation mode of the conversations
+        let interfaces = match self.capture_file {
+            Some(_) => vec![],
+            None => self.interfaces.iter().map(|interface| interface.name.clone()).collect(),
+        };
+        let session = SessionInfo::new(interfaces,
+                                       self.capture_file.clone(),
+                                       self.filter.clone(),
+                                       self.time_interval,
+                                       self.bidirectional,
+                                       self.vlan_conversations,
+                                       time);
         // - Clone the status, creating a copy of the pointer
         let status_reporter = self.status.clone();
-        // - Clone the name of the file for the report
-        let filename = self.filename.clone();
-        // - Clone the name of the file for the final report
-        let final_filename = self.final_filename.clone();
-        // - Clone the time interval
-        let time_interval = self.time_interval.clone();
-        // - Clone the initial time
-        let time_reporter = time.clone();
+        // - Clone the initial time (offline the time 0 is the time of the first packet recorded in the capture file)
+        let time_reporter = if self.capture_file.is_some() { None } else { Some(time) };
+        // - Move the channels of the subscribers to the events (their receivers get disconnected when the Reporter ends)
+        let subscribers = std::mem::take(&mut self.subscribers);
+        // - Clone the metrics (if served)
+        let metrics_reporter = metrics.clone();
         // Run the reporter thread
         self.reporter_handle = Some(thread::spawn(move || {
             let reporter = Reporter::new(
-                filename,
-                final_filename,
-                time_interval,
+                sinks,
+                session,
                 status_reporter,
                 rcv_sniffer,
-
                 time_reporter,
-                filter);
+                subscribers,
+                metrics_reporter);
             reporter.reporting();
 
         }));
 
-        println!("**** SNIFFING... ");
+        // Thread serving the metrics endpoint (it returns when the application goes in 'Exit' state)
+        if let (Some(listener), Some(metrics)) = (metrics_listener, metrics) {
+            let status_metrics = self.status.clone();
+            self.metrics_handle = Some(thread::spawn(move || {
+                serve_metrics(listener, metrics, status_metrics);
+            }));
+            println!("**** SERVING THE METRICS ON http://{}/metrics ", self.metrics_address.unwrap());
+        }
+
+        match &self.capture_file {
+            Some(path) => println!("**** READING {}... ", path),
+            None => println!("**** SNIFFING... "),
+        }
 
         return Ok(());
     }
     /// Pause the network analyser
     /// If the process is already in 'Pause' mode then it returns an error.
     pub fn pause(&mut self) -> Result<(), ErrorNetworkAnalyser> {
-        if self.sniffer_handle.is_none() || self.reporter_handle.is_none()
+        if self.sniffer_handles.is_empty() || self.reporter_handle.is_none()
         {
             return Err(ErrorNetworkAnalyser::ErrorPause("Error: first start the sniffing calling the 'start()' function".to_string()));
 
@@ -230,10 +527,10 @@ impl NetworkAnalyser {
 
             let mut status_value = self.status.mutex.lock().unwrap();
 
-            // If its in pause mode wakeup the reporter
+            // If its in pause mode wakeup the reporter (and the sniffer, if it is reading a capture file)
             if *status_value == StatusValue::Paused
             {
-                self.status.cvar.notify_one();
+                self.status.cvar.notify_all();
             }
             // Set exit status
             *status_value = StatusValue::Exit;
@@ -241,32 +538,111 @@ impl NetworkAnalyser {
 
         }
 
-        if let Some(sniffer_handle) = self.sniffer_handle.take() {
-            sniffer_handle.join().unwrap();
-        } else {
+        return self.join();
+    }
+
+    /// Wait until the process ends and the final report has been written: when all the packets of the capture file have been analysed,
+    /// when the duration or the packet count is reached, or when it is stopped through a StopHandle.
+    /// It returns an error if the process has not been started.
+    pub fn wait(&mut self) -> Result<(), ErrorNetworkAnalyser> {
+        return self.join();
+    }
+
+    /// Subscribe to the events of the analysis: it returns the receiver end of a channel on which the Reporter sends
+    /// each packet parsed (if asked), the summary of each time interval (when the report is updated) and, as last event, the final summary.
+    /// The channel holds up to SUBSCRIBER_CAPACITY events: the Reporter does not wait for a slow subscriber to send a packet, the packets that do not fit
+    /// are dropped and their number is given by the following summary ('get_dropped_events()'). The summaries are never dropped:
+    /// the Reporter waits for them to fit, so the subscriber must keep receiving the events until the final summary (or drop the receiver).
+    /// It must be called before 'start()': the subscriptions made later receive no event.
+    /// A subscriber that is no longer interested can drop the receiver.
+    /// - *packets*: whether to get an event for each packet parsed, or only the summaries
+    pub fn subscribe(&mut self, packets: bool) -> Receiver<AnalyserEvent> {
+        let (sender, receiver) = sync_channel(SUBSCRIBER_CAPACITY);
+        self.subscribers.push(Subscriber::new(sender, packets));
+        return receiver;
+    }
+
+    /// Add a sink to which the Reporter gives the reports, in addition to the ones of the report formats.
+    /// It must be called before 'start()'.
+    pub fn add_sink(&mut self, sink: Box<dyn ReportSink>) {
+        self.sinks.push(sink);
+    }
+
+    /// It returns a handle that can stop the process from another thread, e.g. while this one is in 'wait()'
+    pub fn stop_handle(&self) -> StopHandle {
+        return StopHandle { status: self.status.clone() };
+    }
+
+    /// Wait the end of the Sniffers and the Reporter threads.
+    fn join(&mut self) -> Result<(), ErrorNetworkAnalyser> {
+        if self.sniffer_handles.is_empty() {
             return Err(ErrorNetworkAnalyser::ErrorQuit("Error: first start the sniffing calling the 'start()' function".to_string()));
         }
+        for sniffer_handle in self.sniffer_handles.drain(..) {
+            sniffer_handle.join().unwrap();
+        }
 
         if let Some(reporter_handle) = self.reporter_handle.take() {
             reporter_handle.join().unwrap();
         } else {
             return Err(ErrorNetworkAnalyser::ErrorQuit("Error: first start the sniffing calling the 'start()' function".to_string()));
         }
+        if let Some(metrics_handle) = self.metrics_handle.take() {
+            metrics_handle.join().unwrap();
+        }
 
         println!();
         println!("************************************************************************************************************************************************");
         println!("********************************************************   THE END   ***************************************************************************");
         println!("************************************************************************************************************************************************");
         println!();
-        println!("* You can find the final report here: {}", self.final_filename);
+        for format in &self.report_formats {
+            println!("* You can find the final report here: {}", with_extension(&self.final_filename, format.extension()));
+        }
+        if let Some(database) = &self.database {
+            println!("* You can find the conversations of every time interval in the database: {}", database);
+        }
+        if let Some(savefile) = &self.savefile {
+            if self.capture_file.is_none() && self.interfaces.len() > 1 {
+                for interface in &self.interfaces {
+                    println!("* You can find the packets captured on {} here: {}", interface.name, get_interface_savefile(savefile, &interface.name));
+                }
+            } else {
+                println!("* You can find the captured packets here: {}", savefile);
+            }
+        }
 
         return Ok(());
     }
 
+    /// It returns the BPF program applied to the capture: the filter compiled in BPF and the raw BPF expression set by the user.
+    /// On Ethernet captures the filter also accepts the packets carried in VLAN tagged frames.
+    /// When all the packets are saved in the savefile (not only the ones accepted by the filter) the filter is checked only in user space,
+    /// since the packets dropped by the kernel would never reach the savefile: only the raw BPF expression is applied.
+    /// None if there is nothing to filter.
+    fn get_bpf_program(&self, linktype: Linktype) -> Option<String> {
+        let filter = if self.savefile.is_some() && !self.savefile_only_filtered { None } else { self.filter.to_bpf() };
+        let filter = match filter {
+            Some(filter) if linktype == Linktype::ETHERNET => Some(vlan_tolerant_bpf(&filter)),
+            filter => filter,
+        };
+        return match (filter, &self.bpf_expression) {
+            (Some(filter), Some(expression)) => Some(format!("({}) and ({})", filter, expression)),
+            (Some(filter), None) => Some(filter),
+            (None, Some(expression)) => Some(expression.clone()),
+            (None, None) => None,
+        };
+    }
+
+    /// Returns true if the packets are read from a capture file instead of being sniffed from a network interface
+    pub fn is_offline(&self) -> bool {
+        return self.capture_file.is_some();
+    }
+
     /// Resume the process. It wakes up the Reporter and the Sniffer and the process continues.
     /// It returns an error if the process is already running.
     pub fn resume(&mut self) -> Result<(), ErrorNetworkAnalyser> {
-        if self.sniffer_handle.is_none() || self.reporter_handle.is_none()
+        if self.sniffer_handles.is_empty() || self.reporter_handle.is_none()
         {
             return Err(ErrorNetworkAnalyser::ErrorResume("Error: first start the sniffing calling the 'start()' function".to_string()));
 
@@ -277,7 +653,7 @@ impl NetworkAnalyser {
         }
 
         *status_value = StatusValue::Running;
-        self.status.cvar.notify_one();
+        self.status.cvar.notify_all();
         println!("**** SNIFFING RESUMED...  ");
         println!("**** SNIFFING...  ");
         return Ok(());
@@ -286,20 +662,26 @@ impl NetworkAnalyser {
 
 
 
+/// It returns the names and the descriptions (if any) of all the network interfaces found
+/// if an error occurs, it returns an instance of ErrorNetworkAnalyser!
+pub fn list_interfaces() -> Result<Vec<(String, Option<String>)>, ErrorNetworkAnalyser> {
+    return match Device::list() {
+        Ok(interfaces) => Ok(interfaces.into_iter().map(|inter| (inter.name, inter.desc)).collect()),
+        Err(err) => Err(ErrorNetworkAnalyser::ErrorNa(err.to_string()))
+    };
+}
+
 /// Print the names and the descriptions of all the network interfaces found
 /// and returns the total number of interfaces found
 /// if an error occurs, it returns an instance of ErrorNetworkAnalyser!
 fn print_devices() -> Result<usize, ErrorNetworkAnalyser> {
 
-    let interfaces = match Device::list(){
-        Ok(_interfaces) => _interfaces,
-        Err(err) => return Err(ErrorNetworkAnalyser::ErrorNa(err.to_string()))
-    };
+    let interfaces = list_interfaces()?;
 
     let tot = interfaces.len();
 
-    for (i, inter) in interfaces.into_iter().enumerate() {
-        println!("> {}:   {:?} {:?}", i, inter.name, inter.desc.unwrap_or_else(|| "-".to_string()));
+    for (i, (name, desc)) in interfaces.into_iter().enumerate() {
+        println!("> {}:   {:?} {:?}", i, name, desc.unwrap_or_else(|| "-".to_string()));
     }
 
     return Ok(tot);
@@ -312,8 +694,7 @@ fn select_device_by_name(name: String) -> Device {
     let interfaces = Device::list().unwrap();
     let chosen_interface = interfaces
         .into_iter()
-        .filter(|inter| inter.name == name)
-        .next()
+        .find(|inter| inter.name == name)
         .unwrap_or_else(|| panic!("No such network interface: {}", name)); // this status should not be reachable
 
     return chosen_interface;
@@ -327,17 +708,68 @@ fn find_my_device_name(index: usize) -> String {
 
 
 
-/// It asks the user to select the Device she/he wants to sniff.
-/// If an error occurs it returns an ErrorNetworkAnalyser, otherwise it returns the Device
-fn get_interface() -> Result<Device, ErrorNetworkAnalyser>
+/// It asks the user whether she/he wants to analyse a capture file (.pcap/.pcapng) instead of sniffing a network interface.
+/// If an error occurs it returns an ErrorNetworkAnalyser, otherwise it returns the path of the file (None to sniff a network interface)
+fn get_capture_file() -> Result<Option<String>, ErrorNetworkAnalyser>
 {
-    println!("> Which of the following interfaces you want to sniff?");
+    println!("> Do you want to read the packets from a capture file (.pcap, .pcapng) instead of sniffing a network interface? [Y, N]");
+
+    let mut answer = String::new();
+    loop {
+        print!(">> Answer: ");
+        io::stdout().flush().expect("Error");
+        answer.clear();
+
+        match io::stdin().read_line(&mut answer) {
+            Ok(_) => {
+                match answer.trim() {
+                    "Y" | "y" => break,
+                    "" | "N" | "n" => return Ok(None),
+                    _ => println!("> [Error]: Please, write a correct answer"),
+                }
+            }
+            Err(err) => println!("> [Error]: {}", err)
+        }
+    }
+
+    println!("> Please, insert the path of the capture file. [Press X to exit.]");
+
+    let mut path = String::new();
+    loop {
+        print!(">> Capture file: ");
+        io::stdout().flush().expect("Error");
+        path.clear();
+
+        match io::stdin().read_line(&mut path) {
+            Ok(_) => {
+                let cmd = path.trim();
+                if cmd == "x" || cmd == "X"
+                {
+                    return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string()));
+                }
+
+                if Path::new(cmd).is_file() {
+                    return Ok(Some(cmd.to_string()));
+                } else {
+                    println!("> [Error]: No such file: {}. Try Again.", cmd);
+                }
+            }
+            Err(err) => println!("> [Error]: {}", err)
+        }
+    }
+}
+
+/// It asks the user to select the Devices she/he wants to sniff.
+/// If an error occurs it returns an ErrorNetworkAnalyser, otherwise it returns the Devices
+fn get_interfaces() -> Result<Vec<Device>, ErrorNetworkAnalyser>
+{
+    println!("> Which of the following interfaces you want to sniff? You can select more of them, separating the indexes with commas (e.g. 0, 2).");
     let tot_interfaces = print_devices()?;
 
     let mut my_index_str = String::new();
 
-    loop {
-        print!(">> Select an index: [Press X to exit.]  ");
+    'input: loop {
+        print!(">> Select the indexes: [Press X to exit.]  ");
         io::stdout().flush().expect("Error");
         my_index_str.clear();
 
@@ -349,23 +781,33 @@ fn get_interface() -> Result<Device, ErrorNetworkAnalyser>
                     return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string()));
                 }
 
-                match cmd.parse::<usize>() {
-                    Ok(my_index) => {
-                        if my_index < tot_interfaces {
-                            let dev_name = find_my_device_name(my_index);
-
-                            println!("> Ok, you selected:  {:?}", dev_name);
-                            let interface = select_device_by_name(dev_name);
-                            println!("> Setting the interface in promiscous mode... ");
-                            return Ok(interface);
-                        } else {
-                            println!("> [Error]: please select a valid number. Try Again.");
+                let mut indexes: Vec<usize> = vec![];
+                for index_str in cmd.split(',') {
+                    match index_str.trim().parse::<usize>() {
+                        Ok(my_index) => {
+                            if my_index >= tot_interfaces {
+                                println!("> [Error]: please select valid numbers. Try Again.");
+                                continue 'input;
+                            }
+                            if !indexes.contains(&my_index) {
+                                indexes.push(my_index);
+                            }
+                        }
+                        Err(err) => {
+                            println!("> [Error]: {}", err);
+                            continue 'input;
                         }
-                    }
-                    Err(err) => {
-                        println!("> [Error]: {}", err);
                     }
                 }
+
+                let mut interfaces = vec![];
+                for my_index in indexes {
+                    let dev_name = find_my_device_name(my_index);
+                    println!("> Ok, you selected:  {:?}", dev_name);
+                    interfaces.push(select_device_by_name(dev_name));
+                }
+                println!("> Setting the interfaces in promiscous mode... ");
+                return Ok(interfaces);
             }
             Err(err) => {
                 println!("> [Error]: {}", err);
@@ -391,7 +833,7 @@ fn get_time_interval(default: usize) -> Result<usize, ErrorNetworkAnalyser>
         match io::stdin().read_line(&mut time_interval_str) {
             Ok(_) => {
                 let cmd = time_interval_str.trim();
-                if cmd == "" {
+                if cmd.is_empty() {
                     return Ok(default);
                 }
                 else if cmd == "x" || cmd == "X"
@@ -417,24 +859,25 @@ fn get_time_interval(default: usize) -> Result<usize, ErrorNetworkAnalyser>
 }
 
 
-/// It asks the user to select the name of the file where the report needs to be written.
+/// It asks the user to select the name of the file where the report needs to be written. Its extension selects the format of the report.
 /// If an error occours it returns an ErrorNetworkAnalyser, otherwise it returns the filename
 fn get_file_name(default: &str)-> Result<String, ErrorNetworkAnalyser>
 {
-    println!("> Please, insert the name of the file where we will save the report in \".txt\" format. [Press X to exit.] [Enter to keep the default name: {}]", default);
+    println!("> Please, insert the name of the file where we will save the report: \".txt\" (tables), \".csv\", \".json\" or \".ndjson\" format. [Press X to exit.] [Enter to keep the default name: {}]", default);
 
 
     let mut filename = String::new();
+    let reg = Regex::new(r"^[\w,\s-]+\.(txt|csv|json|ndjson)$").unwrap();
 
     loop {
-        print!(">> File Name (.txt): ");
+        print!(">> File Name (.txt, .csv, .json, .ndjson): ");
         io::stdout().flush().expect("Error");
         filename.clear();
 
         match io::stdin().read_line(&mut filename) {
             Ok(_) => {
                 let cmd = filename.trim();
-                if cmd == "" {
+                if cmd.is_empty() {
                     return Ok(default.to_string());
                 }
                 else if cmd == "x" || cmd == "X"
@@ -442,12 +885,11 @@ fn get_file_name(default: &str)-> Result<String, ErrorNetworkAnalyser>
                     return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string()));
                 }
                 else {
-                    let reg = Regex::new(r"^[\w,\s-]+\.txt$").unwrap();
-                    if reg.is_match(&*cmd) {
+                    if reg.is_match(cmd) {
                         filename = cmd.to_string();
                         return Ok(filename);
                     } else {
-                        println!("> [Error] Please, write a correct filename in txt format! It must not contain :       \\ /:*?\"<>|");
+                        println!("> [Error] Please, write a correct filename in txt, csv, json or ndjson format! It must not contain :       \\ /:*?\"<>|");
                     }
                 }
             }
@@ -499,7 +941,7 @@ fn get_filter()-> Result<Filter, ErrorNetworkAnalyser>
             Ok(_) => {
                 ip_str = ip_str.trim().to_string();
 
-                if ip_str == ""  {
+                if ip_str.is_empty() {
                     break;
                 }
                 else if ip_str == "x" || ip_str == "X"
@@ -531,7 +973,7 @@ fn get_filter()-> Result<Filter, ErrorNetworkAnalyser>
             Ok(_) => {
                 ip_dst = ip_dst.trim().to_string();
 
-                if ip_dst == "" {
+                if ip_dst.is_empty() {
                     break;
                 }
                 else if ip_dst == "x" || ip_dst== "X"
@@ -563,7 +1005,7 @@ fn get_filter()-> Result<Filter, ErrorNetworkAnalyser>
             Ok(_) => {
                 prt_str = prt_str.trim().to_string();
 
-                if prt_str == "" {
+                if prt_str.is_empty() {
                     break;
                 }
                 else if prt_str == "x" || prt_str == "X"
@@ -597,7 +1039,7 @@ fn get_filter()-> Result<Filter, ErrorNetworkAnalyser>
             Ok(_) => {
                 prt_dst = prt_dst.trim().to_string();
 
-                if prt_dst == "" {
+                if prt_dst.is_empty() {
                     break;
 
                 }
@@ -651,7 +1093,7 @@ fn get_filter()-> Result<Filter, ErrorNetworkAnalyser>
             Ok(_) => {
                 cmd = cmd.trim().to_string();
 
-                if cmd == "" {
+                if cmd.is_empty() {
                     break;
                 }
                 else if prt_str == "x" || prt_str == "X"
@@ -675,18 +1117,275 @@ fn get_filter()-> Result<Filter, ErrorNetworkAnalyser>
         }
     }
 
+    println!("> Filter frames tagged with this VLAN ID: [Press ENTER to skip.] [Press X to exit.]");
+
+    let mut vlan_str = String::new();
+    loop {
+        print!(">> VLAN ID: ");
+        io::stdout().flush().expect("Error");
+        vlan_str.clear();
+
+        match io::stdin().read_line(&mut vlan_str) {
+            Ok(_) => {
+                let cmd = vlan_str.trim();
+
+                if cmd.is_empty() {
+                    break;
+                }
+                else if cmd == "x" || cmd == "X"
+                {
+                    return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string()));
+                }
+                match cmd.parse::<u16>() {
+                    Ok(val) if val < 4096 => {
+                        filter.set_vlan_id(val);
+                        break;
+                    }
+                    Ok(_) => println!(">[Error]: The VLAN ID must be between 0 and 4095"),
+                    Err(err) => println!(">[Error]: {}", err)
+                }
+            }
+            Err(err) => println!(">[Error]: {}", err)
+        }
+    }
+
+    println!("> Filter packets matching this expression, e.g. \"(net 10.0.0.0/8 and not port 22) or proto dns\": [Press ENTER to skip.] [Press X to exit.]");
+    println!("> Possible primitives: [src|dst] host IP, [src|dst] net IP/PREFIX, [src|dst] port N[-M], proto NAME, vlan ID; combined with and, or, not, ( )");
+
+    let mut expression = String::new();
+    loop {
+        print!(">> Expression: ");
+        io::stdout().flush().expect("Error");
+        expression.clear();
+
+        match io::stdin().read_line(&mut expression) {
+            Ok(_) => {
+                let cmd = expression.trim();
+
+                if cmd.is_empty() {
+                    break;
+                }
+                else if cmd == "x" || cmd == "X"
+                {
+                    return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string()));
+                }
+
+                match cmd.parse::<FilterExpression>() {
+                    Ok(val) => {
+                        filter.set_expression(val);
+                        break;
+                    }
+                    Err(err) => println!(">[Error]: {}", err)
+                }
+            }
+            Err(err) => println!(">[Error]: {}", err)
+        }
+    }
+
     return Ok(filter);
 
 }
 
 
+/// It asks the user a raw BPF expression (pcap-filter syntax) to be applied by the kernel in addition to the filter.
+/// If an error occours it returns an ErrorNetworkAnalyser, otherwise it returns the expression (None if skipped)
+fn get_bpf_expression() -> Result<Option<String>, ErrorNetworkAnalyser>
+{
+    println!("> Filter packets with this BPF expression (e.g. \"tcp and net 10.0.0.0/8\"): [Press ENTER to skip.] [Press X to exit.]");
+
+    let mut expression = String::new();
+    loop {
+        print!(">> BPF expression: ");
+        io::stdout().flush().expect("Error");
+        expression.clear();
+
+        match io::stdin().read_line(&mut expression) {
+            Ok(_) => {
+                let cmd = expression.trim();
+                if cmd.is_empty() {
+                    return Ok(None);
+                }
+                else if cmd == "x" || cmd == "X"
+                {
+                    return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string()));
+                }
+                match validate_bpf_expression(cmd) {
+                    Ok(_) => return Ok(Some(cmd.to_string())),
+                    Err(err) => println!("> [Error]: {}", err)
+                }
+            }
+            Err(err) => println!("> [Error]: {}", err)
+        }
+    }
+}
+
+/// It asks the user whether she/he wants to save the packets in a pcap savefile, while analysing them.
+/// If an error occours it returns an ErrorNetworkAnalyser, otherwise it returns the name of the savefile
+/// and whether only the packets accepted by the filter need to be saved (None if the packets must not be saved)
+fn get_savefile() -> Result<Option<(String, bool)>, ErrorNetworkAnalyser>
+{
+    println!("> Do you want to save the captured packets in a pcap file? [Y, N]");
+
+    let mut answer = String::new();
+    loop {
+        print!(">> Answer: ");
+        io::stdout().flush().expect("Error");
+        answer.clear();
+
+        match io::stdin().read_line(&mut answer) {
+            Ok(_) => {
+                match answer.trim() {
+                    "Y" | "y" => break,
+                    "" | "N" | "n" => return Ok(None),
+                    _ => println!("> [Error]: Please, write a correct answer"),
+                }
+            }
+            Err(err) => println!("> [Error]: {}", err)
+        }
+    }
+
+    let default = "capture.pcap";
+    println!("> Please, insert the name of the file where we will save the packets in \".pcap\" format. [Press X to exit.] [Enter to keep the default name: {}]", default);
+
+    let mut filename = String::new();
+    let savefile;
+    let reg = Regex::new(r"^[\w,\s-]+\.pcap$").unwrap();
+    loop {
+        print!(">> File Name (.pcap): ");
+        io::stdout().flush().expect("Error");
+        filename.clear();
 
+        match io::stdin().read_line(&mut filename) {
+            Ok(_) => {
+                let cmd = filename.trim();
+                if cmd.is_empty() {
+                    savefile = default.to_string();
+                    break;
+                }
+                else if cmd == "x" || cmd == "X"
+                {
+                    return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string()));
+                }
+                if reg.is_match(cmd) {
+                    savefile = cmd.to_string();
+                    break;
+                } else {
+                    println!("> [Error] Please, write a correct filename in pcap format! It must not contain :       \\ /:*?\"<>|");
+                }
+            }
+            Err(err) => println!("> [Error]: {}", err)
+        }
+    }
+
+    println!("> Do you want to save all the packets or only the ones accepted by the filter? [A, F] [Enter to keep the default: F]");
+    loop {
+        print!(">> Answer: ");
+        io::stdout().flush().expect("Error");
+        answer.clear();
+
+        match io::stdin().read_line(&mut answer) {
+            Ok(_) => {
+                match answer.trim() {
+                    "A" | "a" => return Ok(Some((savefile, false))),
+                    "" | "F" | "f" => return Ok(Some((savefile, true))),
+                    _ => println!("> [Error]: Please, write a correct answer"),
+                }
+            }
+            Err(err) => println!("> [Error]: {}", err)
+        }
+    }
+}
+
+/// It asks the user whether she/he wants to aggregate the packets A->B and B->A in the same conversation.
+/// If an error occours it returns an ErrorNetworkAnalyser, otherwise it returns the answer
+fn get_bidirectional() -> Result<bool, ErrorNetworkAnalyser>
+{
+    println!("> Do you want to aggregate both the directions of a conversation (client -> server)? [Y, N] [Enter to keep the default: N] [Press X to exit.]");
+
+    let mut answer = String::new();
+    loop {
+        print!(">> Answer: ");
+        io::stdout().flush().expect("Error");
+        answer.clear();
+
+        match io::stdin().read_line(&mut answer) {
+            Ok(_) => {
+                match answer.trim() {
+                    "Y" | "y" => return Ok(true),
+                    "" | "N" | "n" => return Ok(false),
+                    "X" | "x" => return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string())),
+                    _ => println!("> [Error]: Please, write a correct answer"),
+                }
+            }
+            Err(err) => println!("> [Error]: {}", err)
+        }
+    }
+}
+
+/// It asks the user whether she/he wants to distinguish the conversations by the VLAN of the frames.
+/// If an error occours it returns an ErrorNetworkAnalyser, otherwise it returns the answer
+fn get_vlan_conversations() -> Result<bool, ErrorNetworkAnalyser>
+{
+    println!("> Do you want to distinguish the conversations by VLAN (802.1Q tags)? [Y, N] [Enter to keep the default: N] [Press X to exit.]");
+
+    let mut answer = String::new();
+    loop {
+        print!(">> Answer: ");
+        io::stdout().flush().expect("Error");
+        answer.clear();
+
+        match io::stdin().read_line(&mut answer) {
+            Ok(_) => {
+                match answer.trim() {
+                    "Y" | "y" => return Ok(true),
+                    "" | "N" | "n" => return Ok(false),
+                    "X" | "x" => return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string())),
+                    _ => println!("> [Error]: Please, write a correct answer"),
+                }
+            }
+            Err(err) => println!("> [Error]: {}", err)
+        }
+    }
+}
+
+/// It asks the user an optional positive value (e.g. a rotation threshold of the savefile).
+/// If an error occours it returns an ErrorNetworkAnalyser, otherwise it returns the value inserted (None if skipped)
+fn get_optional_value(question: &str, prompt: &str) -> Result<Option<u64>, ErrorNetworkAnalyser>
+{
+    println!("{} [Press ENTER to skip.] [Press X to exit.]", question);
+
+    let mut value = String::new();
+    loop {
+        print!("{}", prompt);
+        io::stdout().flush().expect("Error");
+        value.clear();
+
+        match io::stdin().read_line(&mut value) {
+            Ok(_) => {
+                let cmd = value.trim();
+                if cmd.is_empty() {
+                    return Ok(None);
+                }
+                else if cmd == "x" || cmd == "X"
+                {
+                    return Err(ErrorNetworkAnalyser::ErrorAbort("> [Error]: User asked to abort.".to_string()));
+                }
+                match cmd.parse::<u64>() {
+                    Ok(0) => println!("> [Error]: The value must not be zero."),
+                    Ok(val) => return Ok(Some(val)),
+                    Err(err) => println!("> [Error]: {}", err)
+                }
+            }
+            Err(err) => println!("> [Error]: {}", err)
+        }
+    }
+}
 
 
 /// It returns true if ip_str is a valide ip address, false otherwise
 fn validate_ip_address(ip_str: String) -> Result<IpAddr, String> {
-    let vec_ip4: Vec<&str> = ip_str.split(".").map(|x| x).collect();
-    let vec_ip6: Vec<&str> = ip_str.split(":").map(|x| x).collect();
+    let vec_ip4: Vec<&str> = ip_str.split(".").collect();
+    let vec_ip6: Vec<&str> = ip_str.split(":").collect();
 
     if vec_ip4.len() == 4 {
         let mut correct = true;
@@ -727,16 +1426,57 @@ fn validate_ip_address(ip_str: String) -> Result<IpAddr, String> {
     return Err("It is not an IPV4 or IPV6 address".to_string());
 }
 
+/// It checks whether the given BPF expression (pcap-filter syntax) can be compiled.
+fn validate_bpf_expression(expression: &str) -> Result<(), String> {
+    let capture = match Capture::dead(Linktype::ETHERNET) {
+        Ok(cap) => cap,
+        Err(err) => return Err(err.to_string())
+    };
+    return match capture.compile(expression, true) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string())
+    };
+}
+
+/// It returns the name of the savefile of the packets sniffed on an interface, when more interfaces are sniffed:
+/// the name of the interface (without the characters not allowed in file names) is added before the extension (e.g. "capture_eth0.pcap").
+fn get_interface_savefile(savefile: &str, interface: &str) -> String {
+    let interface: String = interface.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    return match savefile.rsplit_once('.') {
+        Some((name, extension)) => format!("{}_{}.{}", name, interface, extension),
+        None => format!("{}_{}", savefile, interface),
+    };
+}
+
+/// It returns a BPF program accepting the packets matched by 'program' also when they are carried in VLAN (802.1Q) or QinQ tagged frames,
+/// since the BPF primitives do not skip the tags.
+fn vlan_tolerant_bpf(program: &str) -> String {
+    return format!("({0}) or (vlan and (({0}) or (vlan and ({0}))))", program);
+}
+
+/// It applies the BPF program (if any) to the capture, so that the packets are filtered by the kernel.
+fn apply_bpf_program<T: Activated + ?Sized>(capture: &mut Capture<T>, bpf_program: Option<&str>) -> Result<(), ErrorNetworkAnalyser> {
+    if let Some(program) = bpf_program {
+        if let Err(err) = capture.filter(program, true) {
+            return Err(ErrorNetworkAnalyser::ErrorNa(format!("Error: cannot apply the BPF filter '{}': {}", program, err)));
+        }
+    }
+    return Ok(());
+}
+
 /// Given a device, it returns the Channel from which the sniffer gets the level2 packets.
 /// also, it sets the interface in promiscous mode,
 /// the capture as an online and non blocking capture
+/// with packet timestamps in nanosecond precision (if supported by the device, see get_tstamp_precision())
 pub fn activate_capture(device: &Device) -> Result<Capture<Active>, ErrorNetworkAnalyser>{
 
     let mut cap = match Capture::from_device(device.clone()){
         Ok(cap) => cap,
         Err(err) => return Err(ErrorNetworkAnalyser::ErrorNa(err.to_string()))
     };
-    cap = cap.promisc(true);
+    cap = cap.promisc(true).precision(Precision::Nano);
 
     let act_cap = match cap.open() {
         Ok(act) => act,
@@ -750,3 +1490,37 @@ pub fn activate_capture(device: &Device) -> Result<Capture<Active>, ErrorNetwork
 
     return Ok(non_block);
 }
+
+/// It returns the timestamp precision in effect on a live capture: nanoseconds are requested, but not all the devices support them.
+/// pcap does not tell it, so it is read from the magic number of the header of a savefile opened on the capture (deleted right after).
+/// If the savefile cannot be written the requested precision is assumed.
+fn get_tstamp_precision(capture: &Capture<Active>) -> Precision {
+    // Magic number of the savefiles with nanosecond timestamps (written in the byte order of the host)
+    const NSEC_TCPDUMP_MAGIC: u32 = 0xa1b23c4d;
+
+    let path = std::env::temp_dir().join(format!("traffic_analyser_{}_precision.pcap", std::process::id()));
+    let magic = match capture.savefile(&path) {
+        // The header is written when the savefile gets closed
+        Ok(savefile) => {
+            drop(savefile);
+            std::fs::read(&path).ok().and_then(|header| header.get(..4).map(|magic| u32::from_ne_bytes([magic[0], magic[1], magic[2], magic[3]])))
+        }
+        Err(_) => None,
+    };
+    let _ = std::fs::remove_file(&path);
+    return match magic {
+        Some(magic) if magic != NSEC_TCPDUMP_MAGIC => Precision::Micro,
+        _ => Precision::Nano,
+    };
+}
+
+/// Given the path of a capture file (.pcap, .pcapng), it returns the Channel from which the sniffer gets the level2 packets
+/// recorded in the file. The timestamps of the records are read in nanosecond precision.
+pub fn activate_offline_capture(path: &str) -> Result<Capture<Offline>, ErrorNetworkAnalyser> {
+    let cap = match Capture::from_file_with_precision(path, Precision::Nano) {
+        Ok(cap) => cap,
+        Err(err) => return Err(ErrorNetworkAnalyser::ErrorNa(format!("Error: cannot open the capture file '{}': {}", path, err)))
+    };
+
+    return Ok(cap);
+}
@@ -1,9 +1,229 @@
+// The functions return their value with an explicit 'return' statement
+#![allow(clippy::needless_return)]
+
 extern crate core;
 use std::io;
 use std::io::Write;
-use traffic_analyser::{NetworkAnalyser};
+use std::process;
+use clap::{Args, Parser, Subcommand};
+use traffic_analyser::{Config, ErrorNetworkAnalyser, FilterConfig, list_interfaces, NetworkAnalyser, SavefileConfig};
+
+/// Network analyser: it sniffs the network interfaces (or reads a capture file) and periodically writes a report of the conversations observed.
+/// Without a subcommand the parameters are asked interactively.
+#[derive(Parser)]
+#[command(name = "traffic_analyser", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the network interfaces that can be sniffed
+    ListInterfaces,
+    /// Sniff the network interfaces until the duration or the packet count is reached, or until SIGINT/SIGTERM
+    Capture {
+        /// Network interface to sniff (repeat it to sniff more interfaces) [default: the first one found]
+        #[arg(short, long = "interface", value_name = "NAME")]
+        interfaces: Vec<String>,
+        #[command(flatten)]
+        options: AnalysisOptions,
+    },
+    /// Analyse the packets recorded in a capture file (.pcap, .pcapng)
+    Read {
+        /// Capture file to read
+        file: String,
+        #[command(flatten)]
+        options: AnalysisOptions,
+    },
+}
+
+/// Options shared by the 'capture' and 'read' subcommands.
+/// They override the values read from the configuration file.
+#[derive(Args)]
+struct AnalysisOptions {
+    /// Configuration file (TOML); the other options override its values
+    #[arg(long, value_name = "PATH")]
+    config: Option<String>,
+    /// Seconds between two updates of the report [default: 5]
+    #[arg(short = 't', long, value_name = "SECS")]
+    interval: Option<usize>,
+    /// Report file (.txt, .csv, .json, .ndjson) [default: report.txt]
+    #[arg(short = 'o', long, value_name = "PATH")]
+    report: Option<String>,
+    /// Final report file [default: the name of the report file with the "final_" prefix]
+    #[arg(long, value_name = "PATH")]
+    final_report: Option<String>,
+    /// Formats of the reports, written in files named as the report file with the extension of the format: table, csv, json, ndjson [default: the one of the extension of the report file]
+    #[arg(long = "format", value_name = "FORMAT", value_delimiter = ',')]
+    formats: Vec<String>,
+    /// SQLite database (.db, .sqlite) where the conversations of every time interval are stored, appended to the ones of the previous sessions (feature "sqlite")
+    #[arg(long, value_name = "PATH")]
+    database: Option<String>,
+    /// Filter expression, e.g. "(net 10.0.0.0/8 and not port 22) or proto dns"
+    #[arg(short, long, value_name = "EXPR")]
+    filter: Option<String>,
+    /// Raw BPF expression (pcap-filter syntax) applied in addition to the filter
+    #[arg(long, value_name = "EXPR")]
+    bpf: Option<String>,
+    /// Save the packets accepted by the filter in a pcap savefile
+    #[arg(short = 'w', long, value_name = "PATH")]
+    write: Option<String>,
+    /// Save all the packets in the savefile, not only the ones accepted by the filter
+    #[arg(long, requires = "write")]
+    write_all: bool,
+    /// Stop after this number of seconds (of capture time, when reading a capture file)
+    #[arg(short, long, value_name = "SECS")]
+    duration: Option<u64>,
+    /// Stop after this number of packets
+    #[arg(short = 'c', long, value_name = "N")]
+    count: Option<usize>,
+    /// Aggregate the packets A->B and B->A in the same conversation
+    #[arg(long, overrides_with = "no_bidirectional")]
+    bidirectional: bool,
+    /// Count the packets A->B and B->A in different conversations (e.g. overriding the configuration file)
+    #[arg(long, overrides_with = "bidirectional")]
+    no_bidirectional: bool,
+    /// Distinguish the conversations by VLAN
+    #[arg(long, overrides_with = "no_vlan_conversations")]
+    vlan_conversations: bool,
+    /// Do not distinguish the conversations by VLAN (e.g. overriding the configuration file)
+    #[arg(long, overrides_with = "vlan_conversations")]
+    no_vlan_conversations: bool,
+    /// Serve the metrics for Prometheus on http://ADDR/metrics, e.g. 127.0.0.1:9100
+    #[arg(long, value_name = "ADDR")]
+    metrics: Option<String>,
+}
+
+impl AnalysisOptions {
+    /// It reads the configuration file, if any, and overrides its values with the options set on the command line
+    fn configure(self) -> Result<Config, ErrorNetworkAnalyser> {
+        let mut config = match &self.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        if self.interval.is_some() {
+            config.interval = self.interval;
+        }
+        if self.report.is_some() {
+            config.report = self.report;
+        }
+        if self.final_report.is_some() {
+            config.final_report = self.final_report;
+        }
+        if !self.formats.is_empty() {
+            config.formats = Some(self.formats);
+        }
+        if self.database.is_some() {
+            config.database = self.database;
+        }
+        if self.filter.is_some() {
+            config.filter.get_or_insert_with(FilterConfig::default).expression = self.filter;
+        }
+        if self.bpf.is_some() {
+            config.bpf = self.bpf;
+        }
+        if let Some(path) = self.write {
+            match &mut config.savefile {
+                Some(savefile) => {
+                    savefile.path = path;
+                    // Without '--write-all' the choice of the configuration file is kept
+                    if self.write_all {
+                        savefile.all_packets = true;
+                    }
+                }
+                None => config.savefile = Some(SavefileConfig { path, all_packets: self.write_all, max_size: None, rotation: None }),
+            }
+        }
+        if self.duration.is_some() {
+            config.duration = self.duration;
+        }
+        if self.count.is_some() {
+            config.packet_count = self.count;
+        }
+        if self.bidirectional {
+            config.bidirectional = Some(true);
+        }
+        if self.no_bidirectional {
+            config.bidirectional = Some(false);
+        }
+        if self.vlan_conversations {
+            config.vlan_conversations = Some(true);
+        }
+        if self.no_vlan_conversations {
+            config.vlan_conversations = Some(false);
+        }
+        if self.metrics.is_some() {
+            config.metrics = self.metrics;
+        }
+        return Ok(config);
+    }
+}
 
 fn main() {
+    let cli = Cli::parse();
+
+    let config = match cli.command {
+        None => return interactive(),
+        Some(Command::ListInterfaces) => {
+            match list_interfaces() {
+                Ok(interfaces) => {
+                    for (name, desc) in interfaces {
+                        println!("{}\t{}", name, desc.unwrap_or_else(|| "-".to_string()));
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Capture { interfaces, options }) => {
+            options.configure().map(|mut config| {
+                config.capture_file = None;
+                if !interfaces.is_empty() {
+                    config.interfaces = Some(interfaces);
+                }
+                config
+            })
+        }
+        Some(Command::Read { file, options }) => {
+            options.configure().map(|mut config| {
+                config.interfaces = None;
+                config.capture_file = Some(file);
+                config
+            })
+        }
+    };
+
+    let mut na = match config.and_then(|config| config.build()) {
+        Ok(na) => na,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    // Stop the sniffing (writing the final report) on SIGINT/SIGTERM, also if received while the sniffing is starting
+    let stop_handle = na.stop_handle();
+    if let Err(e) = ctrlc::set_handler(move || stop_handle.stop()) {
+        eprintln!("> [Warning]: cannot handle the termination signals: {}", e);
+    }
+
+    if let Err(e) = na.start() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+
+    if let Err(e) = na.wait() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// Interactive execution: the parameters are asked on the console, then the user controls the sniffing with the commands P, R, X.
+fn interactive() {
 
     let mut na = NetworkAnalyser::new(); // Network Analyser with default values
 
@@ -23,6 +243,12 @@ fn main() {
             return;}
     }
 
+    // A capture file is analysed until its end
+    if na.is_offline() {
+        na.wait().unwrap_or_else(|err| println!("{}", err) );
+        return;
+    }
+
     // Handle user commands
     let mut cmd = String::new();
     loop {
@@ -44,4 +270,4 @@ fn main() {
             println!("> [Error]: Please Try again");
         }
     }
-}
\ No newline at end of file
+}
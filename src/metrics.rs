@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use crate::{Status, StatusValue};
+
+/// Metrics object. Counters of the sniffing, exposed in the Prometheus text format by the metrics endpoint.
+/// They are updated by the Reporter for each packet it gets (and by the Sniffers for the packets dropped by the kernel),
+/// and read by the thread serving the endpoint.
+/// - *counters*: packets and bytes by protocol and by network interface, dropped packets, conversations and TCP connections
+/// - *backlog*: number of packets sent by the Sniffers and not yet taken from the channel by the Reporter
+pub(crate) struct Metrics {
+    counters: Mutex<Counters>,
+    backlog: AtomicUsize,
+}
+
+/// Values of the counters of the Metrics. BTreeMaps keep the series sorted in the output.
+/// - *protocols*: packets and bytes got by the Reporter, by protocol
+/// - *interfaces*: packets and bytes got by the Reporter, by network interface (or capture file)
+/// - *accepted*: packets accepted by the filter
+/// - *dropped*: packets dropped by the kernel or by the network interface, by network interface (pcap statistics)
+/// - *interval_conversations*: conversations of the current time interval
+/// - *open_tcp_connections*: TCP connections tracked that have not ended
+#[derive(Default)]
+struct Counters {
+    protocols: BTreeMap<String, (u64, u64)>,
+    interfaces: BTreeMap<String, (u64, u64)>,
+    accepted: u64,
+    dropped: BTreeMap<String, u64>,
+    interval_conversations: usize,
+    open_tcp_connections: usize,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        return Metrics { counters: Mutex::new(Counters::default()), backlog: AtomicUsize::new(0) };
+    }
+
+    /// It counts a packet got by the Reporter
+    /// - *protocol*, *interface*: labels of the packet
+    /// - *bytes*: size of the packet
+    /// - *accepted*: whether the packet passed the filter
+    /// - *interval_conversations*: conversations of the current time interval, after the packet
+    /// - *open_tcp_connections*: TCP connections tracked that have not ended, after the packet
+    pub(crate) fn add_packet(&self, protocol: &str, interface: &str, bytes: usize, accepted: bool, interval_conversations: usize, open_tcp_connections: usize) {
+        let mut guard = self.counters.lock().unwrap();
+        let counters = &mut *guard;
+        for (map, label) in [(&mut counters.protocols, protocol), (&mut counters.interfaces, interface)] {
+            let count = match map.get_mut(label) {
+                Some(count) => count,
+                None => map.entry(label.to_string()).or_insert((0, 0)),
+            };
+            count.0 += 1;
+            count.1 += bytes as u64;
+        }
+        if accepted {
+            counters.accepted += 1;
+        }
+        counters.interval_conversations = interval_conversations;
+        counters.open_tcp_connections = open_tcp_connections;
+    }
+
+    /// It sets the number of conversations of the current time interval (e.g. to 0 when the report gets updated)
+    pub(crate) fn set_interval_conversations(&self, interval_conversations: usize) {
+        self.counters.lock().unwrap().interval_conversations = interval_conversations;
+    }
+
+    /// It sets the number of packets dropped on a network interface since the beginning of the sniffing
+    pub(crate) fn set_dropped(&self, interface: &str, dropped: u64) {
+        self.counters.lock().unwrap().dropped.insert(interface.to_string(), dropped);
+    }
+
+    /// It counts a packet sent by a Sniffer to the Reporter
+    pub(crate) fn packet_sent(&self) {
+        self.backlog.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// It counts a packet taken from the channel by the Reporter
+    pub(crate) fn packet_received(&self) {
+        self.backlog.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// It returns the metrics in the Prometheus text exposition format (version 0.0.4)
+    pub(crate) fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut text = String::new();
+
+        write_header(&mut text, "netanalyser_packets_total", "counter", "Packets received by the reporter, by protocol.");
+        for (protocol, (packets, _)) in &counters.protocols {
+            let _ = writeln!(text, "netanalyser_packets_total{{protocol=\"{}\"}} {}", escape_label(protocol), packets);
+        }
+        write_header(&mut text, "netanalyser_bytes_total", "counter", "Bytes received by the reporter, by protocol.");
+        for (protocol, (_, bytes)) in &counters.protocols {
+            let _ = writeln!(text, "netanalyser_bytes_total{{protocol=\"{}\"}} {}", escape_label(protocol), bytes);
+        }
+        write_header(&mut text, "netanalyser_interface_packets_total", "counter", "Packets received by the reporter, by network interface.");
+        for (interface, (packets, _)) in &counters.interfaces {
+            let _ = writeln!(text, "netanalyser_interface_packets_total{{interface=\"{}\"}} {}", escape_label(interface), packets);
+        }
+        write_header(&mut text, "netanalyser_interface_bytes_total", "counter", "Bytes received by the reporter, by network interface.");
+        for (interface, (_, bytes)) in &counters.interfaces {
+            let _ = writeln!(text, "netanalyser_interface_bytes_total{{interface=\"{}\"}} {}", escape_label(interface), bytes);
+        }
+        write_header(&mut text, "netanalyser_accepted_packets_total", "counter", "Packets accepted by the filter.");
+        let _ = writeln!(text, "netanalyser_accepted_packets_total {}", counters.accepted);
+        write_header(&mut text, "netanalyser_dropped_packets_total", "counter", "Packets dropped by the kernel or by the network interface, by network interface.");
+        for (interface, dropped) in &counters.dropped {
+            let _ = writeln!(text, "netanalyser_dropped_packets_total{{interface=\"{}\"}} {}", escape_label(interface), dropped);
+        }
+        write_header(&mut text, "netanalyser_interval_conversations", "gauge", "Conversations of the current time interval, reset when the report is updated.");
+        let _ = writeln!(text, "netanalyser_interval_conversations {}", counters.interval_conversations);
+        write_header(&mut text, "netanalyser_open_tcp_connections", "gauge", "TCP connections tracked that are neither closed nor reset (idle ones are forgotten after 5 minutes).");
+        let _ = writeln!(text, "netanalyser_open_tcp_connections {}", counters.open_tcp_connections);
+        write_header(&mut text, "netanalyser_channel_backlog", "gauge", "Packets sent by the sniffers and not yet handled by the reporter.");
+        let _ = writeln!(text, "netanalyser_channel_backlog {}", self.backlog.load(Ordering::Relaxed));
+        return text;
+    }
+}
+
+/// Maximum number of metrics requests handled at the same time
+const MAX_METRICS_HANDLERS: usize = 8;
+/// Time waited by the metrics endpoint when no request can be accepted, before checking the status again
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+
+/// It serves the metrics endpoint on the listener, answering 'GET /metrics' with the metrics and the other requests with '404 Not Found'.
+/// Each request is handled by its own thread, so that a slow client does not delay the other scrapes: up to MAX_METRICS_HANDLERS at the same time,
+/// the connections arriving while all of them are busy are closed without an answer. It returns when the application goes in 'Exit' state.
+pub(crate) fn serve_metrics(listener: TcpListener, metrics: Arc<Metrics>, status: Arc<Status>) {
+    // The listener does not block, so that the status can be checked while no scrape arrives
+    if let Err(err) = listener.set_nonblocking(true) {
+        println!("> [Error]: cannot serve the metrics: {}", err);
+        return;
+    }
+    let handlers = Arc::new(AtomicUsize::new(0));
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if handlers.load(Ordering::Acquire) >= MAX_METRICS_HANDLERS {
+                    println!("> [Error]: too many metrics requests at the same time, the connection is closed");
+                    drop(stream);
+                    continue;
+                }
+                handlers.fetch_add(1, Ordering::AcqRel);
+                let metrics = metrics.clone();
+                let handlers = handlers.clone();
+                thread::spawn(move || {
+                    if let Err(err) = handle_request(stream, &metrics) {
+                        println!("> [Error]: cannot answer the metrics request: {}", err);
+                    }
+                    handlers.fetch_sub(1, Ordering::AcqRel);
+                });
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if *status.mutex.lock().unwrap() == StatusValue::Exit {
+                    return;
+                }
+                thread::sleep(ACCEPT_POLL);
+            }
+            Err(err) => {
+                // E.g. too many open files: wait before trying again
+                println!("> [Error]: cannot accept the metrics request: {}", err);
+                if *status.mutex.lock().unwrap() == StatusValue::Exit {
+                    return;
+                }
+                thread::sleep(ACCEPT_POLL);
+            }
+        }
+    }
+}
+
+/// It reads the request line (and the headers) of an HTTP request and writes the response
+fn handle_request(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, up to the empty line
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next().map(|target| target.split('?').next().unwrap_or_default())) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "Not Found: the metrics are served on GET /metrics\n".to_string()),
+    };
+    let mut stream = reader.into_inner();
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, body.len(), body)?;
+    return stream.flush();
+}
+
+/// It writes the HELP and TYPE lines of a metric
+fn write_header(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+/// It escapes the backslashes, the double quotes and the line feeds of a label value
+fn escape_label(value: &str) -> String {
+    return value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::time::Instant;
+    use super::*;
+
+    #[test]
+    fn render_writes_the_counters() {
+        let metrics = Metrics::new();
+        metrics.add_packet("TCP", "eth0", 100, true, 1, 1);
+        metrics.add_packet("DNS", "eth0", 60, false, 1, 1);
+        metrics.set_interval_conversations(0);
+        let text = metrics.render();
+        assert!(text.contains("netanalyser_packets_total{protocol=\"TCP\"} 1\n"));
+        assert!(text.contains("netanalyser_interface_bytes_total{interface=\"eth0\"} 160\n"));
+        assert!(text.contains("netanalyser_accepted_packets_total 1\n"));
+        assert!(text.contains("# TYPE netanalyser_interval_conversations gauge\nnetanalyser_interval_conversations 0\n"));
+        assert!(text.contains("netanalyser_open_tcp_connections 1\n"));
+    }
+
+    #[test]
+    fn slow_client_does_not_delay_the_other_scrapes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let status = Arc::new(Status::new());
+        let server = {
+            let status = status.clone();
+            thread::spawn(move || serve_metrics(listener, Arc::new(Metrics::new()), status))
+        };
+
+        // A client that never sends its request
+        let _slow_client = TcpStream::connect(address).unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let start = Instant::now();
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        *status.mutex.lock().unwrap() = StatusValue::Exit;
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn requests_beyond_the_handlers_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let status = Arc::new(Status::new());
+        let server = {
+            let status = status.clone();
+            thread::spawn(move || serve_metrics(listener, Arc::new(Metrics::new()), status))
+        };
+
+        // Clients that never send their request keep all the handlers busy
+        let slow_clients: Vec<TcpStream> = (0..MAX_METRICS_HANDLERS).map(|_| TcpStream::connect(address).unwrap()).collect();
+        thread::sleep(Duration::from_millis(300));
+
+        let mut client = TcpStream::connect(address).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut response = String::new();
+        // Closed without an answer
+        assert_eq!(client.read_to_string(&mut response).unwrap(), 0);
+
+        // Once the slow clients are gone the requests are answered again
+        drop(slow_clients);
+        thread::sleep(Duration::from_millis(300));
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        *status.mutex.lock().unwrap() = StatusValue::Exit;
+        server.join().unwrap();
+    }
+}
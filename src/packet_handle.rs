@@ -2,22 +2,26 @@ extern crate pnet;
 
 use std::fmt::{Display, Formatter};
 use pnet::packet::arp::{ArpPacket};
-use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::ethernet::{EtherType, EtherTypes, EthernetPacket};
 use pnet::packet::icmp::{ IcmpPacket};
 use pnet::packet::icmpv6::Icmpv6Packet;
 use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
-use pnet::packet::ipv4::Ipv4Packet;
-use pnet::packet::ipv6::Ipv6Packet;
-use pnet::packet::tcp::TcpPacket;
+use pnet::packet::ipv4::{Ipv4Flags, Ipv4Packet};
+use pnet::packet::ipv6::{ExtensionPacket, FragmentPacket, Ipv6Packet};
+use pnet::packet::tcp::{TcpFlags, TcpPacket};
 use pnet::packet::udp::UdpPacket;
 use pnet::packet::Packet;
+use pnet::util::MacAddr;
 
 use std::net::{IpAddr};
-use std::time::{Duration};
+use std::time::{Duration, SystemTime};
 
 
 use std::str::FromStr;
-use pcap::Device;
+use pcap::Linktype;
+use crate::filter_expression::FilterExpression;
+use crate::tcp_tracker::TcpEvents;
+use crate::reassembly::{Fragment, Reassembler};
 
 /* -------- Protocol enum ---------*/
 /// All possible Protocols that can be handled by the applications.
@@ -79,39 +83,181 @@ impl Display for Protocol {
     }
 }
 
+impl Protocol {
+    /// It returns the BPF expression matching (a superset of) the packets carrying the protocol.
+    /// None if the protocol cannot be expressed in BPF.
+    /// Application layer protocols (DNS, TLS) are recognised only parsing the payload, so the kernel can only select their transport protocol.
+    /// The transport layer protocols are also matched on the packets whose transport header the kernel cannot see (see 'transport_tolerant_bpf()').
+    pub fn to_bpf(&self) -> Option<String> {
+        return match *self {
+            Protocol::Arp => Some("arp".to_string()),
+            Protocol::IpV4 => Some("ip".to_string()),
+            Protocol::IpV6 => Some("ip6".to_string()),
+            Protocol::Udp => Some(transport_tolerant_bpf("udp")),
+            Protocol::Tcp => Some(transport_tolerant_bpf("tcp")),
+            Protocol::IcmpV4 => Some("icmp".to_string()),
+            Protocol::IcmpV6 => Some(transport_tolerant_bpf("icmp6")),
+            Protocol::Dns => Some(transport_tolerant_bpf("udp or tcp")),
+            Protocol::Tls => Some(transport_tolerant_bpf("tcp")),
+            Protocol::Http => Some(transport_tolerant_bpf("tcp port 80")),
+            Protocol::Https => Some(transport_tolerant_bpf("tcp port 443")),
+            Protocol::Ethernet | Protocol::None => None,
+        };
+    }
+}
+
+/// It returns a BPF expression accepting the packets matched by a transport layer primitive (e.g. 'tcp', 'src port 53')
+/// also when the kernel cannot see their transport header, so that they still reach the reassembly in user space:
+/// the non-first fragments of the IPv4 datagrams, and the IPv6 packets with extension headers (the BPF primitives check only the next header of the fixed header).
+pub(crate) fn transport_tolerant_bpf(primitive: &str) -> String {
+    return format!("(({}) or (ip and ip[6:2] & 0x1fff != 0) or (ip6 and not ip6 proto 6 and not ip6 proto 17))", primitive);
+}
+
+/* -------- IPv6 Extension headers ---------*/
+/// IPv6 extension headers that can be walked to reach the transport layer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Ipv6Extension {
+    HopByHop,
+    Routing,
+    Fragment,
+    DestinationOptions,
+    Authentication,
+}
+
+impl Display for Ipv6Extension {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Ipv6Extension::HopByHop => write!(f, "Hop-by-Hop"),
+            Ipv6Extension::Routing => write!(f, "Routing"),
+            Ipv6Extension::Fragment => write!(f, "Fragment"),
+            Ipv6Extension::DestinationOptions => write!(f, "Destination Options"),
+            Ipv6Extension::Authentication => write!(f, "Authentication"),
+        }
+    }
+}
+
+/// Set of IPv6 extension headers (one bit for each Ipv6Extension)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Ipv6Extensions(u8);
+
+impl Ipv6Extensions {
+    /// All the extension headers, in the order in which they are shown
+    const ALL: [Ipv6Extension; 5] = [Ipv6Extension::HopByHop, Ipv6Extension::Routing, Ipv6Extension::Fragment,
+                                     Ipv6Extension::DestinationOptions, Ipv6Extension::Authentication];
+
+    /// Add an extension header to the set
+    pub fn insert(&mut self, extension: Ipv6Extension) {
+        self.0 |= 1 << extension as u8;
+    }
+    /// Add all the extension headers of another set
+    pub fn extend(&mut self, other: Ipv6Extensions) {
+        self.0 |= other.0;
+    }
+    /// It returns whether the set contains the extension header
+    pub fn contains(&self, extension: Ipv6Extension) -> bool {
+        return self.0 & (1 << extension as u8) != 0;
+    }
+    /// It returns whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        return self.0 == 0;
+    }
+    /// It returns the extension headers of the set, in the order in which they are shown
+    pub fn to_vec(&self) -> Vec<Ipv6Extension> {
+        return Ipv6Extensions::ALL.iter().copied().filter(|extension| self.contains(*extension)).collect();
+    }
+}
+
+impl Display for Ipv6Extensions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<String> = self.to_vec().iter()
+            .map(|extension| extension.to_string())
+            .collect();
+        write!(f, "{}", names.join(", "))
+    }
+}
+
 /* -------- Packet info structure ---------*/
 #[derive(Debug, Clone)]
 /// Object used to save relevant information of a sniffed packet.
+/// - *interface*: name of the network interface the packet was sniffed on (None if read from a capture file)
+/// - *mac_sorg*: MAC address of the source (None if the link layer does not carry it)
+/// - *mac_dest*: MAC address of the destination (None if the link layer does not carry it)
+/// - *ethertype*: EtherType of the packet carried by the frame, after the VLAN tags (None if the link layer does not carry it)
 /// - *ip_sorg*: Ip address of the source
 /// - *ip_dest*: Ip address of the destination
 /// - *prt_srg*: Source port
 /// - *prt_dst*: Destination port
-/// - *protocol*: Protocol carried by the packet
+/// - *protocol*: Protocol carried by the packet (the highest layer one)
+/// - *protocols*: All the protocols carried by the packet, one for each layer parsed
+/// - *vlan_ids*: VLAN IDs of the 802.1Q tags of the frame, from the outer one
 /// - *dim*: size in bytes of the packet
-/// - *arrival_time*: when the packet arrived
+/// - *ipv6_extensions*: IPv6 extension headers walked to reach the transport layer
+/// - *reassembled*: whether the packet completed a fragmented datagram (its transport layer is parsed from the reassembled datagram)
+/// - *discarded_datagrams*: number of fragmented datagrams discarded while handling the packet, because not completed in time
+/// - *tcp_flags*: flags of the TCP header (None if the packet is not a TCP one)
+/// - *tcp_sequence*, *tcp_acknowledgement*, *tcp_window*: sequence number, acknowledgement number and window size of the TCP header
+/// - *tcp_payload_len*: size in bytes of the TCP payload
+/// - *timestamp*: when the packet was captured, as reported by the pcap header
+/// - *arrival_time*: when the packet arrived (considering as time 0 the time on which the sniffing began)
 /// - *printed*: whether the packet needs to be printed on the report or if it is filtered out by the user.
 pub struct PacketInfo {
 
+    interface: Option<String>,
+    mac_sorg: Option<MacAddr>,
+    mac_dest: Option<MacAddr>,
+    ethertype: Option<EtherType>,
     ip_sorg: Option<IpAddr>,
     ip_dest: Option<IpAddr>,
     prt_sorg: u16,
     prt_dest: u16,
     protocol: Protocol,
+    protocols: Vec<Protocol>,
+    vlan_ids: Vec<u16>,
     dim: usize,
+    ipv6_extensions: Ipv6Extensions,
+    reassembled: bool,
+    discarded_datagrams: usize,
+    tcp_flags: Option<u16>,
+    tcp_sequence: u32,
+    tcp_acknowledgement: u32,
+    tcp_window: u16,
+    tcp_payload_len: usize,
+    timestamp: Option<SystemTime>,
     arrival_time: Option<Duration>,
     printed: bool,
 }
 
+impl Default for PacketInfo {
+    fn default() -> Self {
+        return PacketInfo::new();
+    }
+}
+
 impl PacketInfo {
     /// Create a new PacketInfo object instance
     pub fn new() -> Self {
         return PacketInfo {
+            interface: None,
+            mac_sorg: None,
+            mac_dest: None,
+            ethertype: None,
             ip_sorg: None,
             ip_dest: None,
             prt_sorg: 0,
             prt_dest: 0,
             protocol: Protocol::None,
+            protocols: vec![],
+            vlan_ids: vec![],
             dim: 0,
+            ipv6_extensions: Ipv6Extensions::default(),
+            reassembled: false,
+            discarded_datagrams: 0,
+            tcp_flags: None,
+            tcp_sequence: 0,
+            tcp_acknowledgement: 0,
+            tcp_window: 0,
+            tcp_payload_len: 0,
+            timestamp: None,
             arrival_time: None,
             printed: false,
         };
@@ -120,6 +266,10 @@ impl PacketInfo {
     /*
     Getter methods
      */
+    /// It returns the name of the network interface the packet was sniffed on (None if read from a capture file)
+    pub fn get_interface(&self) -> Option<&str> {
+        return self.interface.as_deref();
+    }
     /// It returns the size in bytes of the packet
     pub fn get_dim(&self) -> usize {
         return self.dim;
@@ -128,6 +278,16 @@ impl PacketInfo {
     pub fn get_time(&self) -> Option<Duration> {
         return self.arrival_time;
     }
+    /// It returns the capture timestamp of the packet (from the pcap header)
+    pub fn get_timestamp(&self) -> Option<SystemTime> {
+        return self.timestamp;
+    }
+    /// It returns the source MAC address
+    pub fn get_mac_sorgente(&self) -> Option<MacAddr> { return self.mac_sorg }
+    /// It returns the destination MAC address
+    pub fn get_mac_destinazione(&self) -> Option<MacAddr> { return self.mac_dest }
+    /// It returns the EtherType of the packet carried by the frame
+    pub fn get_ethertype(&self) -> Option<EtherType> { return self.ethertype }
     /// It returns the source ip address
     pub fn get_ip_sorgente(&self) -> Option<IpAddr> { return self.ip_sorg }
     /// It returns the destination ip address
@@ -144,6 +304,46 @@ impl PacketInfo {
     pub fn get_protocol(&self) -> Protocol {
         self.protocol
     }
+    /// It returns the VLAN IDs of the frame, from the outer one
+    pub fn get_vlan_ids(&self) -> &[u16] {
+        return &self.vlan_ids;
+    }
+    /// It returns whether the packet carries the protocol at any layer
+    pub fn carries(&self, protocol: Protocol) -> bool {
+        return self.protocols.contains(&protocol);
+    }
+    /// It returns the IPv6 extension headers carried by the packet
+    pub fn get_ipv6_extensions(&self) -> Ipv6Extensions {
+        return self.ipv6_extensions;
+    }
+    /// It returns whether the packet completed a fragmented datagram
+    pub fn get_reassembled(&self) -> bool {
+        return self.reassembled;
+    }
+    /// It returns the number of fragmented datagrams discarded while handling the packet
+    pub fn get_discarded_datagrams(&self) -> usize {
+        return self.discarded_datagrams;
+    }
+    /// It returns the flags of the TCP header (None if the packet is not a TCP one)
+    pub fn get_tcp_flags(&self) -> Option<u16> {
+        return self.tcp_flags;
+    }
+    /// It returns the sequence number of the TCP header
+    pub fn get_tcp_sequence(&self) -> u32 {
+        return self.tcp_sequence;
+    }
+    /// It returns the acknowledgement number of the TCP header
+    pub fn get_tcp_acknowledgement(&self) -> u32 {
+        return self.tcp_acknowledgement;
+    }
+    /// It returns the window size of the TCP header
+    pub fn get_tcp_window(&self) -> u16 {
+        return self.tcp_window;
+    }
+    /// It returns the size in bytes of the TCP payload
+    pub fn get_tcp_payload_len(&self) -> usize {
+        return self.tcp_payload_len;
+    }
     /// It returns whether the packets need to be printed or filtered out
     pub fn get_printed(&self) -> bool {
         return self.printed;
@@ -153,6 +353,22 @@ impl PacketInfo {
     /*
     Setter methods
      */
+    /// Set the name of the network interface the packet was sniffed on
+    pub fn set_interface(&mut self, interface: String) {
+        self.interface = Some(interface)
+    }
+    /// Set the source MAC address
+    pub fn set_mac_sorgente(&mut self, mac: MacAddr) {
+        self.mac_sorg = Some(mac)
+    }
+    /// Set the destination MAC address
+    pub fn set_mac_destinazione(&mut self, mac: MacAddr) {
+        self.mac_dest = Some(mac)
+    }
+    /// Set the EtherType of the packet carried by the frame
+    pub fn set_ethertype(&mut self, ethertype: EtherType) {
+        self.ethertype = Some(ethertype)
+    }
     /// Set the size of the packet
     pub fn set_dim(&mut self, dim: usize) {
         self.dim = dim
@@ -161,6 +377,10 @@ impl PacketInfo {
     pub fn set_time(&mut self, time: Duration) {
         self.arrival_time = Some(time)
     }
+    /// Set the capture timestamp of the packet (from the pcap header)
+    pub fn set_timestamp(&mut self, timestamp: SystemTime) {
+        self.timestamp = Some(timestamp)
+    }
     /// Set the source ip address
     pub fn set_ip_sorgente(&mut self, ip_sorg: IpAddr) {
         self.ip_sorg = Some(ip_sorg);
@@ -177,9 +397,39 @@ impl PacketInfo {
     pub fn set_porta_destinazione(&mut self, porta_dest: u16) {
         self.prt_dest = porta_dest;
     }
-    /// Set the protocol carried by the packet
+    /// Set the protocol carried by the packet (it also gets added to the protocols carried by the packet)
     pub fn set_protocol(&mut self, protocol: Protocol) {
-        self.protocol = protocol
+        self.protocol = protocol;
+        if !self.protocols.contains(&protocol) {
+            self.protocols.push(protocol);
+        }
+    }
+    /// Add the VLAN ID of a tag of the frame (the tags are added from the outer one)
+    pub fn set_vlan_id(&mut self, vlan_id: u16) {
+        self.vlan_ids.push(vlan_id);
+    }
+    /// Add an IPv6 extension header to the ones carried by the packet
+    pub fn set_ipv6_extension(&mut self, extension: Ipv6Extension) {
+        self.ipv6_extensions.insert(extension);
+    }
+    /// Set that the packet completed a fragmented datagram
+    pub fn set_reassembled(&mut self, value: bool) {
+        self.reassembled = value;
+    }
+    /// Set the number of fragmented datagrams discarded while handling the packet
+    pub fn set_discarded_datagrams(&mut self, discarded_datagrams: usize) {
+        self.discarded_datagrams = discarded_datagrams;
+    }
+    /// Set the flags of the TCP header
+    pub fn set_tcp_flags(&mut self, flags: u16) {
+        self.tcp_flags = Some(flags);
+    }
+    /// Set the sequence number, the acknowledgement number, the window size and the payload size of the TCP segment
+    pub fn set_tcp_segment(&mut self, sequence: u32, acknowledgement: u32, window: u16, payload_len: usize) {
+        self.tcp_sequence = sequence;
+        self.tcp_acknowledgement = acknowledgement;
+        self.tcp_window = window;
+        self.tcp_payload_len = payload_len;
     }
     /// Set that the packet needs to be printed (true)
     pub fn set_printed(&mut self, value: bool) {
@@ -190,15 +440,46 @@ impl PacketInfo {
 /* -------- Conversation Stats struct ---------*/
 #[derive(Debug, Copy, Clone)]
 /// Object used to save relevant information on Conversations between (IP_source, PORT_source) and (IP_destination, PORT_destination) using a given Protocol.
+/// The starting and ending times are derived from the capture timestamps of the first and the last packet of the conversation.
+/// The forward direction is the one from the source to the destination of the ConversationKey, the backward one is the opposite
+/// (it carries packets only if the conversations are aggregated in both directions).
 ///     - *tot_bytes*: total number of bytes exchanged
 ///     - *starting_time*: when the conversations started (considering as time 0 the time on which the sniffing began)
 ///     - *ending_time*: when the conversations ended (considering as time 0 the time on which the sniffing began)
 ///     - *tot_packets*: total number of packets exchanged
+///     - *fwd_bytes*, *fwd_packets*: bytes and packets sent in the forward direction
+///     - *bwd_bytes*, *bwd_packets*: bytes and packets sent in the backward direction
+///     - *initiator*: (ip address, port) of the endpoint that sent the first packet of the conversation
+///     - *ipv6_extensions*: IPv6 extension headers seen in the packets of the conversation
+///     - *tcp*: whether the conversation is carried by TCP
+///     - *syn_packets*, *fin_packets*, *rst_packets*: number of TCP packets with the SYN, FIN, RST flag set
+///     - *retransmissions*, *out_of_order*, *dup_acks*, *zero_windows*: number of TCP retransmissions, out-of-order segments, duplicate ACKs and zero-window advertisements
+///     - *handshake_rtt*: round trip time of the TCP handshake (SYN -> SYN-ACK -> ACK)
+///     - *rtt_min*, *rtt_max*, *rtt_sum*, *rtt_samples*: minimum, maximum, sum and number of the TCP round trip time samples (handshake and data/ACK pairs)
 pub struct ConversationStats {
     tot_bytes: usize,
     starting_time: Option<Duration>,
     ending_time: Option<Duration>,
     tot_packets: usize,
+    fwd_bytes: usize,
+    fwd_packets: usize,
+    bwd_bytes: usize,
+    bwd_packets: usize,
+    initiator: Option<(IpAddr, u16)>,
+    ipv6_extensions: Ipv6Extensions,
+    tcp: bool,
+    syn_packets: usize,
+    fin_packets: usize,
+    rst_packets: usize,
+    retransmissions: usize,
+    out_of_order: usize,
+    dup_acks: usize,
+    zero_windows: usize,
+    handshake_rtt: Option<Duration>,
+    rtt_min: Option<Duration>,
+    rtt_max: Option<Duration>,
+    rtt_sum: Duration,
+    rtt_samples: u32,
 }
 
 impl ConversationStats {
@@ -207,23 +488,90 @@ impl ConversationStats {
     ///     - *starting_time*: when the conversations started (considering as time 0 the time on which the sniffing began)
     ///     - *ending_time*: when the conversations ended (considering as time 0 the time on which the sniffing began)
     ///     - *tot_packets*: total number of packets exchanged
-
+    /// The bytes and the packets are considered as sent in the forward direction.
     pub fn new(tot_bytes: usize, start: Duration, end: Duration, tot_packets: usize) -> Self {
         return ConversationStats {
             tot_bytes,
             starting_time: Some(start),
             ending_time: Some(end),
             tot_packets,
+            fwd_bytes: tot_bytes,
+            fwd_packets: tot_packets,
+            bwd_bytes: 0,
+            bwd_packets: 0,
+            initiator: None,
+            ipv6_extensions: Ipv6Extensions::default(),
+            tcp: false,
+            syn_packets: 0,
+            fin_packets: 0,
+            rst_packets: 0,
+            retransmissions: 0,
+            out_of_order: 0,
+            dup_acks: 0,
+            zero_windows: 0,
+            handshake_rtt: None,
+            rtt_min: None,
+            rtt_max: None,
+            rtt_sum: Duration::ZERO,
+            rtt_samples: 0,
         };
     }
     /// Get the starting time of the conversation (considering as time 0 the time on which the sniffing began)
     pub fn get_starting_time(&self) -> Option<Duration> {return self.starting_time}
     /// Get the ending time of the conversation (considering as time 0 the time on which the sniffing began)
     pub fn get_ending_time(&self) -> Option<Duration> {return  self.ending_time}
+    /// Get the total duration of the conversation
+    pub fn get_duration(&self) -> Option<Duration> {
+        return match (self.starting_time, self.ending_time) {
+            (Some(start), Some(end)) => Some(end.saturating_sub(start)),
+            _ => None
+        };
+    }
     /// Get the total number of bytes exchanged during the conversation
     pub fn get_tot_bytes(&self) -> usize {return self.tot_bytes}
     /// Get the total number of packets exchanged during the conversation
     pub fn get_tot_packets(&self) -> usize {return self.tot_packets}
+    /// Get the number of bytes sent in the forward direction
+    pub fn get_fwd_bytes(&self) -> usize {return self.fwd_bytes}
+    /// Get the number of packets sent in the forward direction
+    pub fn get_fwd_packets(&self) -> usize {return self.fwd_packets}
+    /// Get the number of bytes sent in the backward direction
+    pub fn get_bwd_bytes(&self) -> usize {return self.bwd_bytes}
+    /// Get the number of packets sent in the backward direction
+    pub fn get_bwd_packets(&self) -> usize {return self.bwd_packets}
+    /// Get the (ip address, port) of the endpoint that started the conversation
+    pub fn get_initiator(&self) -> Option<(IpAddr, u16)> {return self.initiator}
+    /// Get the IPv6 extension headers seen in the conversation
+    pub fn get_ipv6_extensions(&self) -> Ipv6Extensions {return self.ipv6_extensions}
+    /// Get whether the conversation is carried by TCP
+    pub fn is_tcp(&self) -> bool {return self.tcp}
+    /// Get the number of TCP packets with the SYN flag set
+    pub fn get_syn_packets(&self) -> usize {return self.syn_packets}
+    /// Get the number of TCP packets with the FIN flag set
+    pub fn get_fin_packets(&self) -> usize {return self.fin_packets}
+    /// Get the number of TCP packets with the RST flag set
+    pub fn get_rst_packets(&self) -> usize {return self.rst_packets}
+    /// Get the number of TCP retransmissions
+    pub fn get_retransmissions(&self) -> usize {return self.retransmissions}
+    /// Get the number of TCP segments received out of order
+    pub fn get_out_of_order(&self) -> usize {return self.out_of_order}
+    /// Get the number of TCP duplicate ACKs
+    pub fn get_dup_acks(&self) -> usize {return self.dup_acks}
+    /// Get the number of TCP packets advertising a zero window
+    pub fn get_zero_windows(&self) -> usize {return self.zero_windows}
+    /// Get the round trip time of the TCP handshake
+    pub fn get_handshake_rtt(&self) -> Option<Duration> {return self.handshake_rtt}
+    /// Get the minimum TCP round trip time
+    pub fn get_rtt_min(&self) -> Option<Duration> {return self.rtt_min}
+    /// Get the average TCP round trip time
+    pub fn get_rtt_avg(&self) -> Option<Duration> {
+        return match self.rtt_samples {
+            0 => None,
+            n => Some(self.rtt_sum / n)
+        };
+    }
+    /// Get the maximum TCP round trip time
+    pub fn get_rtt_max(&self) -> Option<Duration> {return self.rtt_max}
 
     /// Set the ending time (considering as time 0 the time on which the sniffing began)
     pub fn set_ending_time(&mut self, end: Duration) {
@@ -237,6 +585,47 @@ impl ConversationStats {
     pub fn set_tot_packets(&mut self, to_add: usize) {
         self.tot_packets += to_add;
     }
+    /// Set the bytes and the packets sent in a direction (forward if 'forward' is true, backward otherwise)
+    pub fn set_direction_stats(&mut self, forward: bool, bytes_to_add: usize, packets_to_add: usize) {
+        if forward {
+            self.fwd_bytes += bytes_to_add;
+            self.fwd_packets += packets_to_add;
+        } else {
+            self.bwd_bytes += bytes_to_add;
+            self.bwd_packets += packets_to_add;
+        }
+    }
+    /// Set the (ip address, port) of the endpoint that started the conversation
+    pub fn set_initiator(&mut self, ip: IpAddr, prt: u16) {
+        self.initiator = Some((ip, prt));
+    }
+    /// Add the IPv6 extension headers of a packet of the conversation
+    pub fn set_ipv6_extensions(&mut self, extensions: Ipv6Extensions) {
+        self.ipv6_extensions.extend(extensions);
+    }
+    /// Count the SYN, FIN and RST flags of a TCP packet of the conversation
+    pub fn set_tcp_flags(&mut self, flags: u16) {
+        self.tcp = true;
+        if flags & TcpFlags::SYN != 0 { self.syn_packets += 1; }
+        if flags & TcpFlags::FIN != 0 { self.fin_packets += 1; }
+        if flags & TcpFlags::RST != 0 { self.rst_packets += 1; }
+    }
+    /// Count the events detected on a TCP packet of the conversation
+    pub fn set_tcp_events(&mut self, events: &TcpEvents) {
+        if events.is_retransmission() { self.retransmissions += 1; }
+        if events.is_out_of_order() { self.out_of_order += 1; }
+        if events.is_dup_ack() { self.dup_acks += 1; }
+        if events.is_zero_window() { self.zero_windows += 1; }
+        if let Some(handshake_rtt) = events.get_handshake_rtt() {
+            self.handshake_rtt = Some(handshake_rtt);
+        }
+        if let Some(rtt) = events.get_rtt() {
+            self.rtt_min = Some(self.rtt_min.map_or(rtt, |min| min.min(rtt)));
+            self.rtt_max = Some(self.rtt_max.map_or(rtt, |max| max.max(rtt)));
+            self.rtt_sum += rtt;
+            self.rtt_samples += 1;
+        }
+    }
 }
 
 /* -------- Conversation Key struct ---------*/
@@ -247,12 +636,16 @@ impl ConversationStats {
 ///   -  *prt_srg*: Source Port
 ///   -  *prt_dest*: Destination Port,
 ///   -  *protocol*: Protocol used in the conversation,
+///   -  *vlan_ids*: (outer, inner) VLAN IDs of the conversation, 0 if not tagged (or if the VLANs are not considered)
+///   -  *interface*: index of the network interface the conversation was sniffed on, among the sniffed ones (0 if only one is sniffed)
 pub struct ConversationKey {
     ip_srg: IpAddr,
     ip_dest: IpAddr,
     prt_srg: u16,
     prt_dest: u16,
     protocol: Protocol,
+    vlan_ids: [u16; 2],
+    interface: usize,
 }
 
 impl ConversationKey {
@@ -274,8 +667,22 @@ impl ConversationKey {
             prt_srg,
             prt_dest,
             protocol,
+            vlan_ids: [0, 0],
+            interface: 0,
         };
     }
+    /// It returns the same key, considering also the VLANs of the conversation (only the two outer tags)
+    pub fn with_vlan_ids(mut self, vlan_ids: &[u16]) -> Self {
+        for (key_vlan_id, vlan_id) in self.vlan_ids.iter_mut().zip(vlan_ids) {
+            *key_vlan_id = *vlan_id;
+        }
+        return self;
+    }
+    /// It returns the same key, considering also the index of the network interface of the conversation
+    pub fn with_interface(mut self, interface: usize) -> Self {
+        self.interface = interface;
+        return self;
+    }
     /// Get the source ip address
     pub fn get_ip_srg(&self) -> IpAddr{ return self.ip_srg}
     /// Get the destination ip address
@@ -286,9 +693,116 @@ impl ConversationKey {
     pub fn get_prt_dest(&self) -> u16{ return self.prt_dest}
     /// Get the protol
     pub fn get_protocol(&self) -> Protocol{ return self.protocol}
+    /// Get the (outer, inner) VLAN IDs, 0 if not tagged
+    pub fn get_vlan_ids(&self) -> [u16; 2]{ return self.vlan_ids}
+    /// Get the index of the network interface
+    pub fn get_interface(&self) -> usize{ return self.interface}
+
+    /// It returns the key identifying the conversation regardless of its direction, so that A->B and B->A packets share it.
+    /// The destination is the server: the endpoint with the lowest (non zero) port, or the lowest ip address if the ports are not significant.
+    pub fn normalized(&self) -> Self {
+        let swap = if self.prt_srg != 0 && self.prt_dest != 0 && self.prt_srg != self.prt_dest {
+            self.prt_srg < self.prt_dest
+        } else {
+            self.ip_srg < self.ip_dest
+        };
+
+        if swap {
+            return ConversationKey {
+                ip_srg: self.ip_dest,
+                ip_dest: self.ip_srg,
+                prt_srg: self.prt_dest,
+                prt_dest: self.prt_srg,
+                ..*self
+            };
+        }
+        return *self;
+    }
+}
+
+/* -------- MAC Conversation Key struct ---------*/
+#[derive(Debug, Eq, Hash, PartialEq, Copy, Clone)]
+/// Object identifying uniquely a layer 2 Conversation, between two MAC addresses.
+///   - *mac_srg*: MAC address of the source of the conversation
+///   - *mac_dest*: MAC address of the destination of the conversation
+pub struct MacConversationKey {
+    mac_srg: MacAddr,
+    mac_dest: MacAddr,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl MacConversationKey {
+    /// Create a new MacConversationKey object instance
+    ///   - *mac_srg*: MAC address of the source of the conversation
+    ///   - *mac_dest*: MAC address of the destination of the conversation
+    pub fn new_key(mac_srg: MacAddr, mac_dest: MacAddr) -> Self {
+        return MacConversationKey { mac_srg, mac_dest };
+    }
+    /// Get the source MAC address
+    pub fn get_mac_srg(&self) -> MacAddr{ return self.mac_srg}
+    /// Get the destination MAC address
+    pub fn get_mac_dest(&self) -> MacAddr{ return self.mac_dest}
+
+    /// It returns the key identifying the conversation regardless of its direction: the source is the lowest MAC address
+    pub fn normalized(&self) -> Self {
+        if self.mac_dest < self.mac_srg {
+            return MacConversationKey { mac_srg: self.mac_dest, mac_dest: self.mac_srg };
+        }
+        return *self;
+    }
+}
+
+/* -------- MAC Conversation Stats struct ---------*/
+#[derive(Debug, Clone)]
+/// Object used to save relevant information on layer 2 Conversations.
+///     - *tot_bytes*: total number of bytes exchanged
+///     - *tot_packets*: total number of frames exchanged
+///     - *ethertypes*: EtherTypes of the packets carried by the frames of the conversation, in order of appearance
+pub struct MacConversationStats {
+    tot_bytes: usize,
+    tot_packets: usize,
+    ethertypes: Vec<EtherType>,
+}
+
+impl Default for MacConversationStats {
+    fn default() -> Self {
+        return MacConversationStats::new();
+    }
+}
+
+impl MacConversationStats {
+    /// Create a new MacConversationStats object instance, without frames
+    pub fn new() -> Self {
+        return MacConversationStats { tot_bytes: 0, tot_packets: 0, ethertypes: vec![] };
+    }
+    /// Add a frame to the conversation
+    /// - *dim*: size in bytes of the frame
+    /// - *ethertype*: EtherType of the packet carried by the frame, if known
+    pub fn add_frame(&mut self, dim: usize, ethertype: Option<EtherType>) {
+        self.tot_bytes += dim;
+        self.tot_packets += 1;
+        if let Some(ethertype) = ethertype {
+            if !self.ethertypes.contains(&ethertype) {
+                self.ethertypes.push(ethertype);
+            }
+        }
+    }
+    /// Get the total number of bytes
+    pub fn get_tot_bytes(&self) -> usize {return self.tot_bytes}
+    /// Get the total number of frames
+    pub fn get_tot_packets(&self) -> usize {return self.tot_packets}
+    /// Get the EtherTypes of the packets carried by the frames
+    pub fn get_ethertypes(&self) -> &[EtherType] {return &self.ethertypes}
+}
+
+/// Name of an EtherType shown in the reports: its name if known, otherwise its hexadecimal value
+pub fn format_ethertype(ethertype: EtherType) -> String {
+    return match ethertype.to_string().as_str() {
+        "unknown" => format!("0x{:04x}", ethertype.0),
+        name => name.to_string(),
+    };
+}
+
+#[derive(Debug, Clone)]
 /// Filter object. It carries the information set by the user about which packet he/she is interested in seeing in the report
 /// All the fields of the filter object can be set to None (meaning 'Any' 'Not to be filtered based on this field').
 /// - *ip_srg*: a value different than None means that the user wants to see in the report only packets coming from *this* source ip address
@@ -296,19 +810,23 @@ impl ConversationKey {
 ///   -  *prt_srg*: a value different than None means that the user wants to see in the report only packets coming from *this* source port
 ///    - *prt_dest*: a value different than None means that the user wants to see in the report only packets coming from *this* destination port
 ///   -  *protocol*: a value different than None means that the user wants to see in the report only packets carrying *this* protocol
-
+///   -  *vlan_id*: a value different than None means that the user wants to see in the report only frames tagged with *this* VLAN ID (outer or inner tag)
+///   -  *expression*: a value different than None means that the user wants to see in the report only packets matching *this* filter expression
+///      (e.g. "(net 10.0.0.0/8 and not port 22) or proto dns"). It is evaluated in addition to the other fields.
 pub struct Filter {
     ip_srg: Option<IpAddr>,
     ip_dest: Option<IpAddr>,
     prt_srg: Option<u16>,
     prt_dest: Option<u16>,
     protocol: Protocol,
+    vlan_id: Option<u16>,
+    expression: Option<FilterExpression>,
 }
 
 impl Display for Filter {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
 
-        write!(f, "[ IP source: {}; IP dest: {}, Port source: {}, Port dest: {}, Protocol: {} ]",
+        write!(f, "[ IP source: {}; IP dest: {}, Port source: {}, Port dest: {}, Protocol: {}, VLAN: {}, Expression: {} ]",
                match self.ip_srg {
                    Some(ip) => ip.to_string(),
                    _ => "Any".to_string()
@@ -328,10 +846,24 @@ impl Display for Filter {
                match self.protocol {
                    Protocol::None => "Any".to_string(),
                    p => p.to_string()
+               },
+               match self.vlan_id {
+                   Some(vlan_id) => vlan_id.to_string(),
+                   _ => "Any".to_string()
+               },
+               match &self.expression {
+                   Some(expression) => expression.to_string(),
+                   _ => "Any".to_string()
                })
     }
 }
 
+impl Default for Filter {
+    fn default() -> Self {
+        return Filter::new();
+    }
+}
+
 impl Filter {
     /// Create a new Filter Object instance
    pub fn new() -> Self {
@@ -341,6 +873,8 @@ impl Filter {
             prt_srg: None,
             prt_dest: None,
             protocol: Protocol::None,
+            vlan_id: None,
+            expression: None,
         };
     }
     /// Set the source ip address on which filter out packets
@@ -364,6 +898,14 @@ impl Filter {
     pub fn set_protocol(&mut self, protocol: Protocol) {
         self.protocol = protocol;
     }
+    /// Set the VLAN ID on which filter out packets
+    pub fn set_vlan_id(&mut self, vlan_id: u16) {
+        self.vlan_id = Some(vlan_id);
+    }
+    /// Set the filter expression on which filter out packets
+    pub fn set_expression(&mut self, expression: FilterExpression) {
+        self.expression = Some(expression);
+    }
 
     /// Get the source ip address on which filter out packets
     pub fn get_ip_srg(&self) -> Option<IpAddr> { return self.ip_srg}
@@ -373,7 +915,76 @@ impl Filter {
     pub fn get_prt_srg(&self) -> Option<u16> { return self.prt_srg}
     /// Set the destination port on which filter out packets
     pub fn get_prt_dest(&self) -> Option<u16> { return self.prt_dest}
-    //pub fn get_protocol(&self) -> Protocol{ return self.protocol}
+    /// Get the protocol on which filter out packets
+    pub fn get_protocol(&self) -> Protocol{ return self.protocol}
+    /// Get the VLAN ID on which filter out packets
+    pub fn get_vlan_id(&self) -> Option<u16> { return self.vlan_id}
+    /// Get the filter expression on which filter out packets
+    pub fn get_expression(&self) -> Option<&FilterExpression> { return self.expression.as_ref()}
+
+    /// It compiles the filter in a BPF expression, so that the packets can be filtered by the kernel before being copied.
+    /// The expression accepts a superset of the packets accepted by the filter: the application layer protocols and the VLAN ID still need to be checked in user space.
+    /// It returns None if the filter is empty.
+    pub fn to_bpf(&self) -> Option<String> {
+        let mut primitives = vec![];
+
+        if let Some(protocol) = self.protocol.to_bpf() {
+            primitives.push(protocol);
+        }
+        if let Some(ip) = self.ip_srg {
+            primitives.push(format!("src host {}", ip));
+        }
+        if let Some(ip) = self.ip_dest {
+            primitives.push(format!("dst host {}", ip));
+        }
+        if let Some(prt) = self.prt_srg {
+            primitives.push(transport_tolerant_bpf(&format!("src port {}", prt)));
+        }
+        if let Some(prt) = self.prt_dest {
+            primitives.push(transport_tolerant_bpf(&format!("dst port {}", prt)));
+        }
+        if let Some(expression) = self.expression.as_ref().and_then(|e| e.to_bpf()) {
+            primitives.push(expression);
+        }
+
+        if primitives.is_empty() {
+            return None;
+        }
+        return Some(primitives.join(" and "));
+    }
+}
+
+/// It checks if the given packet_info satisfies the filter (true) or needs to be filtered out (false).
+pub fn check_filter(filter: &Filter, packet_info: &PacketInfo) -> bool {
+    if filter.get_ip_srg().is_some() &&
+        packet_info.get_ip_sorgente() != filter.get_ip_srg() {
+        return false;
+    }
+    if filter.get_ip_dest().is_some() &&
+        packet_info.get_ip_destinazione() != filter.get_ip_dest() {
+        return false;
+    }
+    if filter.get_prt_srg().is_some() &&
+        packet_info.get_porta_sorgente() != filter.get_prt_srg().unwrap() {
+        return false;
+    }
+    if filter.get_prt_dest().is_some() &&
+        packet_info.get_porta_destinazione() != filter.get_prt_dest().unwrap() {
+        return false;
+    }
+    if filter.get_protocol() != Protocol::None &&
+        !packet_info.carries(filter.get_protocol()) {
+        return false;
+    }
+    if let Some(vlan_id) = filter.get_vlan_id() {
+        if !packet_info.get_vlan_ids().contains(&vlan_id) {
+            return false;
+        }
+    }
+    if let Some(expression) = filter.get_expression() {
+        return expression.matches(packet_info);
+    }
+    true
 }
 
 /*
@@ -381,34 +992,58 @@ impl Filter {
 *
 */
 /// Checks whether the packet carried by the Transport Layer Packet ('packet') is a DNS packet or not
-fn handle_dns_packet(packet: &[u8], new_packet_info: &mut PacketInfo, filter: &Filter) {
-    match dns_parser::Packet::parse(packet) {
-        Ok(_) => {
-            PacketInfo::set_protocol(new_packet_info, Protocol::Dns);
-            if filter.protocol == Protocol::Dns {
-                new_packet_info.set_printed(true);
+fn handle_dns_packet(packet: &[u8], new_packet_info: &mut PacketInfo) {
+    if dns_parser::Packet::parse(packet).is_ok() {
+        PacketInfo::set_protocol(new_packet_info, Protocol::Dns);
+    }
+}
+/// Function to handle the ordered bytes of a TCP stream, recognising the application layer messages (TLS records, or DNS messages on port 53)
+/// even if they are split across several segments. The packet completing a message is marked with its protocol.
+/// It returns the number of bytes consumed: the bytes of an incomplete message are kept waiting for the following segments,
+/// while the bytes that do not belong to a known protocol are all consumed.
+fn handle_tcp_stream(stream: &[u8], dns_port: bool, new_packet_info: &mut PacketInfo) -> usize {
+    let mut consumed = 0;
+    loop {
+        let rest = &stream[consumed..];
+        // DNS messages over TCP are preceded by their length (2 bytes), TLS records have a 5 bytes header
+        let header_len = if dns_port { 2 } else { 5 };
+        if rest.len() < header_len {
+            return consumed;
+        }
+        let message_len = if dns_port {
+            2 + u16::from_be_bytes([rest[0], rest[1]]) as usize
+        } else {
+            // Content type (change_cipher_spec, alert, handshake, application_data, heartbeat) and major version
+            if !(20..=24).contains(&rest[0]) || rest[1] != 3 {
+                return stream.len();
             }
+            5 + u16::from_be_bytes([rest[3], rest[4]]) as usize
+        };
+        if rest.len() < message_len {
+            return consumed;
+        }
 
+        if dns_port {
+            handle_dns_packet(&rest[2..message_len], new_packet_info);
+        } else {
+            handle_tls_packet(&rest[..message_len], new_packet_info);
         }
-        Err(_) => {}
+        consumed += message_len;
     }
 }
 /// Checks whether the packet carried by the Transport Layer Packet ('packet') is a TLS packet or not
-fn handle_tls_packet(packet: &[u8], new_packet_info: &mut PacketInfo, filter: &Filter) {
+fn handle_tls_packet(packet: &[u8], new_packet_info: &mut PacketInfo) {
 
     if tls_parser::parse_tls_plaintext(packet).is_ok() || tls_parser::parse_tls_encrypted(packet).is_ok()
     {
             PacketInfo::set_protocol(new_packet_info, Protocol::Tls);
             //println!("TLS plaintext {:?}", tls_packet.1);
-            if filter.protocol == Protocol::Tls {
-                new_packet_info.set_printed(true);
-            }
         }
 
 }
 
 /// Function to handle an UDP packet parsing it accordingly
-fn handle_udp_packet(packet: &[u8], new_packet_info: &mut PacketInfo, filter: &Filter) {
+fn handle_udp_packet(packet: &[u8], new_packet_info: &mut PacketInfo) {
     let udp = UdpPacket::new(packet);
 
     if let Some(udp) = udp {
@@ -417,33 +1052,23 @@ fn handle_udp_packet(packet: &[u8], new_packet_info: &mut PacketInfo, filter: &F
         let prt_dest = udp.get_destination();
 
         // Save them in the PacketInfo structure
-
-        if filter.protocol == Protocol::Udp {
-            new_packet_info.set_printed(true);
-        }
-
-
         PacketInfo::set_porta_sorgente(new_packet_info, prt_srg);
         PacketInfo::set_porta_destinazione(new_packet_info, prt_dest);
         PacketInfo::set_protocol(new_packet_info, Protocol::Udp);
 
-        handle_dns_packet(udp.payload(), new_packet_info, filter);
+        handle_dns_packet(udp.payload(), new_packet_info);
     }
         //  else {
    //     println!("Malformed UDP Packet");
    // }
 }
 /// Function to handle an ICMPv4 packet parsing it accordingly
-fn handle_icmp_packet( packet: &[u8], new_packet_info: &mut PacketInfo, filter: &Filter) {
+fn handle_icmp_packet( packet: &[u8], new_packet_info: &mut PacketInfo) {
     let icmp_packet = IcmpPacket::new(packet);
 
-    if let Some(_) = icmp_packet {
+    if icmp_packet.is_some() {
         // Save the protocol type in the PacketInfo structure
         PacketInfo::set_protocol(new_packet_info, Protocol::IcmpV4);
-        if filter.protocol == Protocol::IcmpV4 {
-            new_packet_info.set_printed(true);
-        }
-
     }
    //  else {
    //     println!("Malformed ICMP Packet");
@@ -451,53 +1076,44 @@ fn handle_icmp_packet( packet: &[u8], new_packet_info: &mut PacketInfo, filter:
 }
 
 /// Function to handle an ICMPv6 packet parsing it accordingly
-fn handle_icmpv6_packet( packet: &[u8], new_packet_info: &mut PacketInfo, filter: &Filter) {
+fn handle_icmpv6_packet( packet: &[u8], new_packet_info: &mut PacketInfo) {
     let icmpv6_packet = Icmpv6Packet::new(packet);
 
-    if let Some(_) = icmpv6_packet {
+    if icmpv6_packet.is_some() {
         // Save the protocol type in the PacketInfo structure
         PacketInfo::set_protocol(new_packet_info, Protocol::IcmpV6);
-        if filter.protocol == Protocol::IcmpV6 {
-            new_packet_info.set_printed(true);
-        }
     }
    // else {
    //     println!("Malformed ICMPv6 Packet");
    // }
 }
-/// Function to handle an TCP packet parsing it accordingly
-fn handle_tcp_packet( packet: &[u8], new_packet_info: &mut PacketInfo, filter: &Filter) {
+/// Function to handle an TCP packet parsing it accordingly.
+/// Its payload is added to the reassembled stream, so that the application layer messages split across several segments are recognised.
+fn handle_tcp_packet( packet: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
     let tcp = TcpPacket::new(packet);
     if let Some(tcp) = tcp {
         // Extract the source and destination ports
         let prt_srg = tcp.get_source();
         let prt_dest = tcp.get_destination();
 
-        if filter.protocol == Protocol::Tcp {
-            new_packet_info.set_printed(true);
-        }
-
         // Save them in the PacketInfo structure
         PacketInfo::set_porta_sorgente(new_packet_info, prt_srg);
         PacketInfo::set_porta_destinazione(new_packet_info, prt_dest);
         PacketInfo::set_protocol(new_packet_info, Protocol::Tcp);
+        PacketInfo::set_tcp_flags(new_packet_info, tcp.get_flags());
+        PacketInfo::set_tcp_segment(new_packet_info, tcp.get_sequence(), tcp.get_acknowledgement(), tcp.get_window(), tcp.payload().len());
 
-        // Check if the protocol carried is TLS or DNS
-        handle_tls_packet(tcp.payload(), new_packet_info, filter);
-        handle_dns_packet(tcp.payload(), new_packet_info, filter);
+        // Check if the protocol carried is TLS or DNS, reassembling the stream
+        let key = ConversationKey::new_key(new_packet_info.ip_sorg.unwrap(), new_packet_info.ip_dest.unwrap(), prt_srg, prt_dest, Protocol::Tcp);
+        let dns_port = prt_srg == 53 || prt_dest == 53;
+        reassembler.tcp_segment(key, tcp.get_flags(), tcp.get_sequence(), tcp.payload(), new_packet_info.get_timestamp().unwrap(),
+                                |stream| handle_tcp_stream(stream, dns_port, new_packet_info));
 
         // Check if the application protocol is HTTP or HTTPS
         if new_packet_info.prt_dest == 80 ||  new_packet_info.prt_sorg == 80{
-
-                if filter.protocol == Protocol::Http {
-                    new_packet_info.set_printed(true);
-                }
                 PacketInfo::set_protocol(new_packet_info, Protocol::Http);
             }
         else if new_packet_info.prt_dest == 443 ||  new_packet_info.prt_sorg == 443 {
-                if filter.protocol == Protocol::Https {
-                    new_packet_info.set_printed(true);
-                }
                 PacketInfo::set_protocol(new_packet_info, Protocol::Https);
             }
 
@@ -507,21 +1123,21 @@ fn handle_tcp_packet( packet: &[u8], new_packet_info: &mut PacketInfo, filter: &
     }
 }
 /// Function to handle a generic Transport Layer packet. Based on the type of protocol used it calls specific functions to handle it accordingly
-fn handle_transport_protocol(protocol: IpNextHeaderProtocol, packet: &[u8], new_packet_info: &mut PacketInfo, filter: &Filter) {
+fn handle_transport_protocol(protocol: IpNextHeaderProtocol, packet: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
     match protocol {
         IpNextHeaderProtocols::Udp => {
-             handle_udp_packet( packet, new_packet_info, filter)
+             handle_udp_packet( packet, new_packet_info)
         }
         IpNextHeaderProtocols::Tcp => {
-              handle_tcp_packet(packet, new_packet_info, filter)
+              handle_tcp_packet(packet, new_packet_info, reassembler)
         }
         IpNextHeaderProtocols::Icmp => {
 
-            handle_icmp_packet(packet, new_packet_info, filter);
+            handle_icmp_packet(packet, new_packet_info);
         }
         IpNextHeaderProtocols::Icmpv6 => {
 
-            handle_icmpv6_packet( packet, new_packet_info, filter);
+            handle_icmpv6_packet( packet, new_packet_info);
         }
 
         _ => {
@@ -531,8 +1147,8 @@ fn handle_transport_protocol(protocol: IpNextHeaderProtocol, packet: &[u8], new_
     }
 }
 /// Function to handle an IPV4 packet parsing it accordingly.
-fn handle_ipv4_packet(ethernet: &EthernetPacket, new_packet_info: &mut PacketInfo, filter: &Filter) {
-    let header = Ipv4Packet::new(ethernet.payload());
+fn handle_ipv4_packet(packet: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    let header = Ipv4Packet::new(packet);
 
     if let Some(header) = header {
         //la dimensione dell'header ip è di 5 -> Ipv4Packet::get_header_length(&header)
@@ -545,81 +1161,135 @@ fn handle_ipv4_packet(ethernet: &EthernetPacket, new_packet_info: &mut PacketInf
         let ip_dest = IpAddr::V4(header.get_destination());
 
         // Save them in the Packet Info structure
-
-        // If there is a filter on the protocol and it is IPv4
-        if filter.protocol == Protocol::IpV4 {
-            new_packet_info.set_printed(true);
-        }
-
-
         PacketInfo::set_ip_sorgente(new_packet_info, ip_sorg);
         PacketInfo::set_ip_destinazione(new_packet_info, ip_dest);
         PacketInfo::set_protocol(new_packet_info, Protocol::IpV4);
 
+        // Fragments are reassembled before parsing the transport layer (only the first fragment contains its header)
+        let more_fragments = header.get_flags() & Ipv4Flags::MoreFragments != 0;
+        let fragment_offset = header.get_fragment_offset() as usize * 8;
+        if more_fragments || fragment_offset > 0 {
+            let datagram = reassembler.ip_fragment(Fragment {
+                source: ip_sorg,
+                destination: ip_dest,
+                identification: header.get_identification() as u32,
+                protocol: header.get_next_level_protocol().0,
+                offset: fragment_offset,
+                more_fragments,
+                payload: header.payload(),
+            }, new_packet_info.get_timestamp().unwrap());
+            PacketInfo::set_discarded_datagrams(new_packet_info, reassembler.take_discarded_datagrams());
+
+            if let Some(datagram) = datagram {
+                PacketInfo::set_reassembled(new_packet_info, true);
+                handle_transport_protocol(header.get_next_level_protocol(), &datagram, new_packet_info, reassembler);
+            }
+            return;
+        }
+
         handle_transport_protocol(
 
             header.get_next_level_protocol(),
             header.payload(),
             new_packet_info,
-            filter,
+            reassembler,
         );
     } //else {
        // println!("Malformed IPv4 Packet");
     //}
 }
 /// Function to handle an ipv6 packet parsing it accordingly
-fn handle_ipv6_packet(ethernet: &EthernetPacket, new_packet_info: &mut PacketInfo, filter: &Filter) {
-    let header = Ipv6Packet::new(ethernet.payload());
+fn handle_ipv6_packet(packet: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    let header = Ipv6Packet::new(packet);
 
     if let Some(header) = header {
         // Extract the source and destination ip address
         let ip_sorg = IpAddr::V6(header.get_source());
         let ip_dest = IpAddr::V6(header.get_destination());
 
-        // Save them in the Packet Info structure
-
-        // If there is a filter on the protocol and it is IPv6
-        if filter.protocol == Protocol::IpV6 {
-            new_packet_info.set_printed(true);
-        }
-
-        if filter.ip_srg.is_some() && filter.ip_srg.unwrap() != ip_sorg {
-            new_packet_info.set_printed(true);
-        }
-        if filter.ip_dest.is_some() && filter.ip_dest.unwrap() != ip_dest {
-            new_packet_info.set_printed(true);
-        }
-
         // Save them in the Packet Info structure
         PacketInfo::set_ip_sorgente(new_packet_info, ip_sorg);
         PacketInfo::set_ip_destinazione(new_packet_info, ip_dest);
         PacketInfo::set_protocol(new_packet_info, Protocol::IpV6);
 
-        handle_transport_protocol(
-
+        handle_ipv6_extensions(
             header.get_next_header(),
             header.payload(),
             new_packet_info,
-            filter,
+            reassembler,
         );
     } //else {
         //println!("Malformed IPv6 Packet");
     //}
 }
+/// Function to walk the chain of the IPv6 extension headers until the transport layer, which is then handled accordingly.
+/// The fragments are reassembled first: the extension headers following the Fragment one are walked in the reassembled datagram.
+fn handle_ipv6_extensions(next_header: IpNextHeaderProtocol, packet: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    let mut next_header = next_header;
+    let mut packet = packet;
+
+    loop {
+        let (extension, header_len) = match next_header {
+            IpNextHeaderProtocols::Hopopt | IpNextHeaderProtocols::Ipv6Route | IpNextHeaderProtocols::Ipv6Opts => {
+                let extension = match next_header {
+                    IpNextHeaderProtocols::Hopopt => Ipv6Extension::HopByHop,
+                    IpNextHeaderProtocols::Ipv6Route => Ipv6Extension::Routing,
+                    _ => Ipv6Extension::DestinationOptions,
+                };
+                // Length in 8 bytes units, not including the first 8 bytes
+                (extension, packet.get(1).map(|len| (*len as usize + 1) * 8))
+            }
+            // Length in 4 bytes units, not including the first 8 bytes
+            IpNextHeaderProtocols::Ah => (Ipv6Extension::Authentication, packet.get(1).map(|len| (*len as usize + 2) * 4)),
+            IpNextHeaderProtocols::Ipv6Frag => (Ipv6Extension::Fragment, Some(8)),
+            _ => break,
+        };
+        let header_len = match header_len {
+            Some(header_len) if header_len <= packet.len() => header_len,
+            _ => return, // Malformed extension header
+        };
+        PacketInfo::set_ipv6_extension(new_packet_info, extension);
+
+        if extension == Ipv6Extension::Fragment {
+            let fragment = match FragmentPacket::new(packet) {
+                Some(fragment) => fragment,
+                None => return, // Truncated fragment header
+            };
+            let datagram = reassembler.ip_fragment(Fragment {
+                source: new_packet_info.ip_sorg.unwrap(),
+                destination: new_packet_info.ip_dest.unwrap(),
+                identification: fragment.get_id(),
+                protocol: fragment.get_next_header().0,
+                offset: fragment.get_fragment_offset() as usize,
+                more_fragments: !fragment.is_last_fragment(),
+                payload: &packet[header_len..],
+            }, new_packet_info.get_timestamp().unwrap());
+            PacketInfo::set_discarded_datagrams(new_packet_info, reassembler.take_discarded_datagrams());
+
+            if let Some(datagram) = datagram {
+                PacketInfo::set_reassembled(new_packet_info, true);
+                handle_ipv6_extensions(fragment.get_next_header(), &datagram, new_packet_info, reassembler);
+            }
+            return;
+        }
+
+        next_header = match ExtensionPacket::new(packet) {
+            Some(extension_header) => extension_header.get_next_header(),
+            None => return, // Truncated extension header
+        };
+        packet = &packet[header_len..];
+    }
+
+    handle_transport_protocol(next_header, packet, new_packet_info, reassembler);
+}
 /// Function to handle an ARP packet parsing it accordingly
-fn handle_arp_packet(ethernet: &EthernetPacket, new_packet_info: &mut PacketInfo, filter: &Filter) {
-    let header = ArpPacket::new(ethernet.payload());
+fn handle_arp_packet(packet: &[u8], new_packet_info: &mut PacketInfo) {
+    let header = ArpPacket::new(packet);
 
     if let Some(header) = header {
         let ip_sorg = IpAddr::V4(header.get_sender_proto_addr());
         let ip_dest = IpAddr::V4(header.get_target_proto_addr());
 
-        // If there is a filter on the protocol and it is IPv4
-        if filter.protocol == Protocol::Arp {
-            new_packet_info.set_printed(true);
-        }
-
-
         PacketInfo::set_ip_sorgente(new_packet_info, ip_sorg);
         PacketInfo::set_ip_destinazione(new_packet_info, ip_dest);
         PacketInfo::set_protocol(new_packet_info, Protocol::Arp);
@@ -629,50 +1299,382 @@ fn handle_arp_packet(ethernet: &EthernetPacket, new_packet_info: &mut PacketInfo
     //}
 }
 
-/// Function to handle an ethernet packet parsing it accordingly
-pub fn handle_ethernet_frame(ethernet: &EthernetPacket, new_packet_info: &mut PacketInfo, filter: &Filter) {
-    PacketInfo::set_dim(new_packet_info, ethernet.packet().len());
-
-    // If there is no filter on the protocol, packet is set printed
-    if filter.protocol == Protocol::None {
-        new_packet_info.set_printed(true);
-    }
+/// Function to handle a generic Network Layer packet. Based on the EtherType it calls specific functions to handle it accordingly.
+/// The 802.1Q (VLAN) and 802.1ad (QinQ) tags are stripped, saving their VLAN IDs, and the encapsulated packet is handled.
+fn handle_network_protocol(ethertype: EtherType, packet: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    PacketInfo::set_ethertype(new_packet_info, ethertype);
 
-    match ethernet.get_ethertype() {
-        EtherTypes::Ipv4 => handle_ipv4_packet(ethernet, new_packet_info, filter),
-        EtherTypes::Ipv6 => handle_ipv6_packet(ethernet, new_packet_info, filter),
-        EtherTypes::Arp => handle_arp_packet(ethernet, new_packet_info, filter),
+    match ethertype {
+        EtherTypes::Vlan | EtherTypes::PBridge | EtherTypes::QinQ => {
+            // Tag Control Information (priority, drop eligible, VLAN ID) and EtherType of the encapsulated packet
+            if packet.len() < 4 {
+                return;
+            }
+            let vlan_id = u16::from_be_bytes([packet[0], packet[1]]) & 0x0fff;
+            PacketInfo::set_vlan_id(new_packet_info, vlan_id);
+            let ethertype = EtherType(u16::from_be_bytes([packet[2], packet[3]]));
+            handle_network_protocol(ethertype, &packet[4..], new_packet_info, reassembler);
+        }
+        EtherTypes::Ipv4 => handle_ipv4_packet(packet, new_packet_info, reassembler),
+        EtherTypes::Ipv6 => handle_ipv6_packet(packet, new_packet_info, reassembler),
+        EtherTypes::Arp => handle_arp_packet(packet, new_packet_info),
         _ => {
-            new_packet_info.set_printed(false);
             //println!("unknown lvl 3 protocol");
         }
     }
 }
 
-/// Function to handle particular interfaces pointed out by the creators of 'Libpnet'
-pub fn handle_particular_interfaces(interface: &Device, packet: &[u8], new_packet_info: &mut PacketInfo, filter: &Filter) -> bool {
-    let mut buf: [u8; 1600] = [0u8; 1600]; //il frame ethernet è di 1518 byte -> sovradimensionato a 1600
-    let mut new_ethernet_frame = MutableEthernetPacket::new(&mut buf[..]).unwrap();
+/// Link type of the Linux "cooked" captures v2 (interface 'any'), not defined by the pcap crate
+const LINKTYPE_LINUX_SLL2: Linktype = Linktype(276);
+/// Link types used for the captures of raw IP packets: DLT_RAW (12, or 14 on OpenBSD) as returned by libpcap and LINKTYPE_RAW (101)
+const RAW_LINKTYPES: [Linktype; 3] = [Linktype(12), Linktype(14), Linktype::RAW];
+
+/// Function to handle a frame got from a capture. Based on the link type of the capture it calls specific functions to handle it accordingly.
+/// The frames of the link types not supported are counted, but not parsed.
+/// Once all the layers have been parsed, the packet is checked against the filter to decide if it needs to be printed
+/// (also the frames not carrying an IP packet, which are counted apart in the report).
+pub fn handle_frame(linktype: Linktype, frame: &[u8], new_packet_info: &mut PacketInfo, filter: &Filter, reassembler: &mut Reassembler) {
+    PacketInfo::set_dim(new_packet_info, frame.len());
+
+    match linktype {
+        Linktype::ETHERNET => handle_ethernet_frame(frame, new_packet_info, reassembler),
+        Linktype::LINUX_SLL => handle_linux_sll_frame(frame, new_packet_info, reassembler),
+        LINKTYPE_LINUX_SLL2 => handle_linux_sll2_frame(frame, new_packet_info, reassembler),
+        Linktype::NULL => handle_null_frame(frame, false, new_packet_info, reassembler),
+        Linktype::LOOP => handle_null_frame(frame, true, new_packet_info, reassembler),
+        Linktype::IPV4 | Linktype::IPV6 => handle_raw_ip_packet(frame, new_packet_info, reassembler),
+        Linktype::IEEE802_11_RADIOTAP => handle_radiotap_frame(frame, new_packet_info, reassembler),
+        _ if RAW_LINKTYPES.contains(&linktype) => handle_raw_ip_packet(frame, new_packet_info, reassembler),
+        _ => {}
+    }
 
-    if interface.flags.is_loopback(){
-        let payload_offset = 4;
+    let printed = check_filter(filter, new_packet_info);
+    new_packet_info.set_printed(printed);
+}
 
-        if packet.len() > payload_offset {
-            let _version = Ipv4Packet::new(&packet[payload_offset..]);
-            let version = _version.unwrap().get_version();
-            if version == 4 {
-                new_ethernet_frame.set_ethertype(EtherTypes::Ipv4);
-                new_ethernet_frame.set_payload(&packet[payload_offset..]);
-                handle_ethernet_frame(&new_ethernet_frame.to_immutable(), new_packet_info, &filter);
-                return true;
-            } else if version == 6 {
-                new_ethernet_frame.set_ethertype(EtherTypes::Ipv6);
-                new_ethernet_frame.set_payload(&packet[payload_offset..]);
-                handle_ethernet_frame(&new_ethernet_frame.to_immutable(), new_packet_info, &filter);
-                return true;
-            }
+/// Function to handle an ethernet frame (DLT_EN10MB) parsing it accordingly.
+fn handle_ethernet_frame(frame: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    if let Some(ethernet) = EthernetPacket::new(frame) {
+        PacketInfo::set_protocol(new_packet_info, Protocol::Ethernet);
+        PacketInfo::set_mac_sorgente(new_packet_info, ethernet.get_source());
+        PacketInfo::set_mac_destinazione(new_packet_info, ethernet.get_destination());
+        handle_network_protocol(ethernet.get_ethertype(), ethernet.payload(), new_packet_info, reassembler);
+    }
+}
+
+/// Function to handle a Linux "cooked" frame (DLT_LINUX_SLL), captured on the interface 'any' or on interfaces without a link layer header.
+/// The 16-byte pseudo header contains the link layer address of the source (only the source one) and ends with the EtherType of the packet.
+fn handle_linux_sll_frame(frame: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    if frame.len() >= 16 {
+        let address_len = u16::from_be_bytes([frame[4], frame[5]]);
+        let address_type = u16::from_be_bytes([frame[2], frame[3]]);
+        set_sll_source(address_type, address_len as usize, &frame[6..14], new_packet_info);
+        let ethertype = EtherType(u16::from_be_bytes([frame[14], frame[15]]));
+        handle_network_protocol(ethertype, &frame[16..], new_packet_info, reassembler);
+    }
+}
+
+/// Function to handle a Linux "cooked" frame v2 (DLT_LINUX_SLL2), captured on the interface 'any'.
+/// The 20-byte pseudo header starts with the EtherType of the packet and contains the link layer address of the source (only the source one).
+fn handle_linux_sll2_frame(frame: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    if frame.len() >= 20 {
+        let address_type = u16::from_be_bytes([frame[8], frame[9]]);
+        set_sll_source(address_type, frame[11] as usize, &frame[12..20], new_packet_info);
+        let ethertype = EtherType(u16::from_be_bytes([frame[0], frame[1]]));
+        handle_network_protocol(ethertype, &frame[20..], new_packet_info, reassembler);
+    }
+}
+
+/// It sets the source MAC address of a Linux "cooked" frame, if its link layer address is an Ethernet one (ARPHRD_ETHER).
+fn set_sll_source(address_type: u16, address_len: usize, address: &[u8], new_packet_info: &mut PacketInfo) {
+    if address_type == 1 && address_len == 6 {
+        PacketInfo::set_mac_sorgente(new_packet_info, MacAddr::new(address[0], address[1], address[2], address[3], address[4], address[5]));
+    }
+}
+
+/// Function to handle a loopback frame (DLT_NULL or DLT_LOOP).
+/// The 4-byte header contains the address family of the packet: in the byte order of the capturing host for DLT_NULL, in network byte order for DLT_LOOP.
+/// The value of AF_INET6 depends on the operating system of the capturing host, so all of them are accepted.
+fn handle_null_frame(frame: &[u8], network_order: bool, new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    if frame.len() < 4 {
+        return;
+    }
+    let header = [frame[0], frame[1], frame[2], frame[3]];
+    // The families are small numbers: in little endian order the first byte is the meaningful one
+    let family = if network_order || header[0] == 0 { u32::from_be_bytes(header) } else { u32::from_le_bytes(header) };
+
+    match family {
+        2 => handle_network_protocol(EtherTypes::Ipv4, &frame[4..], new_packet_info, reassembler),
+        // Linux, Windows, NetBSD/OpenBSD, FreeBSD, macOS
+        10 | 23 | 24 | 28 | 30 => handle_network_protocol(EtherTypes::Ipv6, &frame[4..], new_packet_info, reassembler),
+        _ => {}
+    }
+}
+
+/// Function to handle a raw IP packet (DLT_RAW, DLT_IPV4, DLT_IPV6), without any link layer header.
+/// The IP version is taken from the first nibble of the packet.
+fn handle_raw_ip_packet(packet: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    match packet.first().map(|byte| byte >> 4) {
+        Some(4) => handle_network_protocol(EtherTypes::Ipv4, packet, new_packet_info, reassembler),
+        Some(6) => handle_network_protocol(EtherTypes::Ipv6, packet, new_packet_info, reassembler),
+        _ => {}
+    }
+}
+
+/// Function to handle an 802.11 frame preceded by the radiotap header (DLT_IEEE802_11_RADIO), captured by wireless interfaces in monitor mode.
+/// The radiotap header is skipped, removing the FCS at the end of the frame if the radiotap flags report it.
+/// Frames with a bad FCS are not parsed.
+fn handle_radiotap_frame(frame: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    if frame.len() < 8 {
+        return;
+    }
+    let radiotap_len = u16::from_le_bytes([frame[2], frame[3]]) as usize;
+    if frame.len() < radiotap_len {
+        return;
+    }
+
+    // Skip the (possibly extended) present bitmaps to reach the fields. Only the first bitmap is needed:
+    // bit 0 is the TSFT field (8 bytes, aligned to 8), bit 1 the flags field (1 byte)
+    let present = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]);
+    let mut offset = 4;
+    while offset + 4 <= radiotap_len && frame[offset + 3] & 0x80 != 0 {
+        offset += 4;
+    }
+    offset += 4;
+
+    let mut has_fcs = false;
+    if present & 0b10 != 0 {
+        if present & 0b01 != 0 {
+            offset = offset.div_ceil(8) * 8 + 8;
+        }
+        if offset >= radiotap_len {
+            return;
+        }
+        let flags = frame[offset];
+        if flags & 0x40 != 0 {
+            // Bad FCS
+            return;
         }
+        has_fcs = flags & 0x10 != 0;
     }
 
-    return false;
+    let end = if has_fcs { frame.len().saturating_sub(4).max(radiotap_len) } else { frame.len() };
+    handle_ieee80211_frame(&frame[radiotap_len..end], new_packet_info, reassembler);
+}
+
+/// Function to handle an 802.11 frame parsing it accordingly.
+/// Only the data frames not protected (or already decrypted) carry a packet that can be parsed: an LLC/SNAP header followed by the EtherType of the packet.
+/// Management and control frames, protected frames and aggregated MSDUs are counted, but not parsed.
+fn handle_ieee80211_frame(frame: &[u8], new_packet_info: &mut PacketInfo, reassembler: &mut Reassembler) {
+    if frame.len() < 24 {
+        return;
+    }
+    let frame_type = (frame[0] >> 2) & 0b11;
+    let subtype = frame[0] >> 4;
+    let flags = frame[1];
+    // Data frames only, without the null data ones (no payload)
+    if frame_type != 2 || subtype & 0b0100 != 0 || flags & 0x40 != 0 {
+        return;
+    }
+
+    // Address 4 is present when the frame goes from a distribution system to another one
+    let mut header_len = if flags & 0b11 == 0b11 { 30 } else { 24 };
+    if frame.len() < header_len {
+        return;
+    }
+    // The addresses of the source and the destination depend on the direction of the frame (to/from the distribution system)
+    let address = |start: usize| MacAddr::new(frame[start], frame[start + 1], frame[start + 2], frame[start + 3], frame[start + 4], frame[start + 5]);
+    let (destination, source) = match flags & 0b11 {
+        0b00 => (address(4), address(10)),
+        0b01 => (address(16), address(10)),
+        0b10 => (address(4), address(16)),
+        _ => (address(16), address(24)),
+    };
+    PacketInfo::set_mac_sorgente(new_packet_info, source);
+    PacketInfo::set_mac_destinazione(new_packet_info, destination);
+
+    if subtype & 0b1000 != 0 {
+        // QoS data: QoS control field (bit 7 of its first byte set for the aggregated MSDUs), then the HT control field if the order flag is set
+        if frame.len() < header_len + 2 || frame[header_len] & 0x80 != 0 {
+            return;
+        }
+        header_len += if flags & 0x80 != 0 { 6 } else { 2 };
+    }
+
+    let payload = &frame[header_len.min(frame.len())..];
+    if payload.len() >= 8 && payload[..6] == [0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00] {
+        let ethertype = EtherType(u16::from_be_bytes([payload[6], payload[7]]));
+        handle_network_protocol(ethertype, &payload[8..], new_packet_info, reassembler);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use super::*;
+    use crate::reassembly::{DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP};
+
+    /// It creates the information of an IPv6 packet, whose extension headers are still to be handled
+    fn ipv6_packet_info() -> PacketInfo {
+        let mut packet_info = PacketInfo::new();
+        packet_info.set_ip_sorgente(IpAddr::V6(Ipv6Addr::LOCALHOST));
+        packet_info.set_ip_destinazione(IpAddr::V6(Ipv6Addr::LOCALHOST));
+        packet_info.set_protocol(Protocol::IpV6);
+        packet_info.set_timestamp(SystemTime::UNIX_EPOCH);
+        return packet_info;
+    }
+
+    /// It returns a Fragment extension header (carrying UDP) followed by the fragment
+    /// - *offset*: position in bytes of the fragment in the datagram, multiple of 8
+    fn ipv6_fragment(offset: u16, more_fragments: bool, fragment: &[u8]) -> Vec<u8> {
+        let offset_flags = offset | more_fragments as u16;
+        let mut packet = vec![IpNextHeaderProtocols::Udp.0, 0];
+        packet.extend(offset_flags.to_be_bytes());
+        packet.extend(7u32.to_be_bytes());
+        packet.extend(fragment);
+        return packet;
+    }
+
+    #[test]
+    fn truncated_ipv6_extension_headers_are_skipped() {
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        let fragment = ipv6_fragment(0, true, &[]);
+        for len in 0..fragment.len() {
+            let mut packet_info = ipv6_packet_info();
+            handle_ipv6_extensions(IpNextHeaderProtocols::Ipv6Frag, &fragment[..len], &mut packet_info, &mut reassembler);
+            assert_eq!(packet_info.get_protocol(), Protocol::IpV6);
+            assert!(packet_info.get_ipv6_extensions().is_empty());
+        }
+
+        let mut packet_info = ipv6_packet_info();
+        handle_ipv6_extensions(IpNextHeaderProtocols::Hopopt, &[IpNextHeaderProtocols::Udp.0, 1, 0, 0], &mut packet_info, &mut reassembler);
+        assert!(packet_info.get_ipv6_extensions().is_empty());
+    }
+
+    const SOURCE_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+
+    /// It returns an IPv4 packet 10.0.0.1 -> 10.0.0.2 carrying a UDP datagram (ports 1000 -> 2000) with 4 bytes of payload
+    fn ipv4_udp_packet() -> Vec<u8> {
+        let mut packet = vec![0x45, 0, 0, 32, 0, 1, 0, 0, 64, IpNextHeaderProtocols::Udp.0, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2];
+        packet.extend([0x03, 0xe8, 0x07, 0xd0, 0, 12, 0, 0, 1, 2, 3, 4]);
+        return packet;
+    }
+
+    /// It parses the frame of the link type, returning the information of the packet
+    fn parse(linktype: Linktype, frame: &[u8]) -> PacketInfo {
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        let mut packet_info = PacketInfo::new();
+        packet_info.set_timestamp(SystemTime::UNIX_EPOCH);
+        handle_frame(linktype, frame, &mut packet_info, &Filter::default(), &mut reassembler);
+        return packet_info;
+    }
+
+    /// It checks that the packet parsed is the one of ipv4_udp_packet()
+    fn assert_ipv4_udp(packet_info: &PacketInfo) {
+        assert_eq!(packet_info.get_protocol(), Protocol::Udp);
+        assert_eq!(packet_info.get_ip_sorgente(), Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+        assert_eq!((packet_info.get_porta_sorgente(), packet_info.get_porta_destinazione()), (1000, 2000));
+    }
+
+    #[test]
+    fn linux_sll_frames_are_parsed() {
+        // Packet type, ARPHRD_ETHER, address length, address (padded to 8 bytes), EtherType
+        let mut frame = vec![0, 0, 0, 1, 0, 6];
+        frame.extend(SOURCE_MAC);
+        frame.extend([0, 0, 0x08, 0x00]);
+        frame.extend(ipv4_udp_packet());
+        let packet_info = parse(Linktype::LINUX_SLL, &frame);
+        assert_ipv4_udp(&packet_info);
+        assert_eq!(packet_info.get_mac_sorgente(), Some(MacAddr::from(SOURCE_MAC)));
+    }
+
+    #[test]
+    fn linux_sll2_frames_are_parsed() {
+        // EtherType, reserved, interface index, ARPHRD_ETHER, packet type, address length, address (padded to 8 bytes)
+        let mut frame = vec![0x08, 0x00, 0, 0, 0, 0, 0, 2, 0, 1, 0, 6];
+        frame.extend(SOURCE_MAC);
+        frame.extend([0, 0]);
+        frame.extend(ipv4_udp_packet());
+        let packet_info = parse(LINKTYPE_LINUX_SLL2, &frame);
+        assert_ipv4_udp(&packet_info);
+        assert_eq!(packet_info.get_mac_sorgente(), Some(MacAddr::from(SOURCE_MAC)));
+    }
+
+    #[test]
+    fn null_frames_are_parsed_in_both_byte_orders() {
+        for header in [[2, 0, 0, 0], [0, 0, 0, 2]] {
+            let mut frame = header.to_vec();
+            frame.extend(ipv4_udp_packet());
+            assert_ipv4_udp(&parse(Linktype::NULL, &frame));
+        }
+        let mut frame = vec![0, 0, 0, 2];
+        frame.extend(ipv4_udp_packet());
+        assert_ipv4_udp(&parse(Linktype::LOOP, &frame));
+
+        // Unknown address family
+        let mut frame = vec![99, 0, 0, 0];
+        frame.extend(ipv4_udp_packet());
+        assert_eq!(parse(Linktype::NULL, &frame).get_ip_sorgente(), None);
+    }
+
+    /// It returns a radiotap frame with the TSFT and the flags fields, carrying an 802.11 data frame (from the distribution system)
+    /// - *flags*: value of the radiotap flags field
+    /// - *body*: body of the 802.11 frame, followed by the FCS
+    fn radiotap_frame(flags: u8, body: &[u8]) -> Vec<u8> {
+        // Two present bitmaps: the TSFT field starts at offset 12, aligned to 16
+        let mut frame = vec![0, 0, 25, 0, 0b11, 0, 0, 0x80, 0, 0, 0, 0];
+        frame.extend([0; 4]);
+        frame.extend([0; 8]);
+        frame.push(flags);
+        // Frame control, duration, destination, BSSID, source, sequence control
+        frame.extend([0x08, 0x02, 0, 0]);
+        frame.extend([0xff; 6]);
+        frame.extend([0x02, 0, 0, 0, 0, 0x02]);
+        frame.extend(SOURCE_MAC);
+        frame.extend([0, 0]);
+        frame.extend(body);
+        frame.extend([0xde, 0xad, 0xbe, 0xef]);
+        return frame;
+    }
+
+    #[test]
+    fn radiotap_frames_are_parsed_without_the_fcs() {
+        let mut body = vec![0xaa, 0xaa, 0x03, 0, 0, 0, 0x08, 0x00];
+        body.extend(ipv4_udp_packet());
+        let packet_info = parse(Linktype::IEEE802_11_RADIOTAP, &radiotap_frame(0x10, &body));
+        assert_ipv4_udp(&packet_info);
+        assert_eq!(packet_info.get_mac_sorgente(), Some(MacAddr::from(SOURCE_MAC)));
+        assert_eq!(packet_info.get_mac_destinazione(), Some(MacAddr::broadcast()));
+
+        // Frames with a bad FCS are not parsed
+        let packet_info = parse(Linktype::IEEE802_11_RADIOTAP, &radiotap_frame(0x50, &body));
+        assert_eq!(packet_info.get_mac_sorgente(), None);
+
+        // A frame 2 bytes shorter: once its FCS is removed the 802.11 header is truncated
+        let frame = radiotap_frame(0x10, &[]);
+        let packet_info = parse(Linktype::IEEE802_11_RADIOTAP, &frame[..frame.len() - 2]);
+        assert_eq!(packet_info.get_mac_sorgente(), None);
+        let frame = radiotap_frame(0x00, &[]);
+        let packet_info = parse(Linktype::IEEE802_11_RADIOTAP, &frame[..frame.len() - 2]);
+        assert_eq!(packet_info.get_mac_sorgente(), Some(MacAddr::from(SOURCE_MAC)));
+    }
+
+    #[test]
+    fn fragmented_ipv6_datagrams_are_reassembled() {
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        // UDP header (ports 1000 -> 2000, length 16) and 8 bytes of payload
+        let datagram = [0x03, 0xe8, 0x07, 0xd0, 0, 16, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8];
+
+        let mut packet_info = ipv6_packet_info();
+        handle_ipv6_extensions(IpNextHeaderProtocols::Ipv6Frag, &ipv6_fragment(8, false, &datagram[8..]), &mut packet_info, &mut reassembler);
+        assert!(packet_info.get_ipv6_extensions().contains(Ipv6Extension::Fragment));
+        assert!(!packet_info.get_reassembled());
+        assert_eq!(packet_info.get_protocol(), Protocol::IpV6);
+
+        let mut packet_info = ipv6_packet_info();
+        handle_ipv6_extensions(IpNextHeaderProtocols::Ipv6Frag, &ipv6_fragment(0, true, &datagram[..8]), &mut packet_info, &mut reassembler);
+        assert!(packet_info.get_reassembled());
+        assert_eq!(packet_info.get_protocol(), Protocol::Udp);
+        assert_eq!((packet_info.get_porta_sorgente(), packet_info.get_porta_destinazione()), (1000, 2000));
+    }
 }
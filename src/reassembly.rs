@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+use pnet::packet::tcp::TcpFlags;
+use crate::packet_handle::ConversationKey;
+
+/// Default maximum number of bytes buffered for a single TCP stream
+pub const DEFAULT_FLOW_CAP: usize = 64 * 1024;
+/// Default maximum number of bytes buffered for all the TCP streams
+pub const DEFAULT_TOTAL_CAP: usize = 16 * 1024 * 1024;
+
+/// Maximum time waited for the missing fragments of a datagram, since its first fragment
+pub const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Maximum number of fragmented datagrams reassembled at the same time
+const MAX_FRAGMENTED_DATAGRAMS: usize = 1024;
+/// Maximum size of the payload of a reassembled datagram
+const MAX_DATAGRAM_LEN: usize = 65535;
+/// Time after its last segment an idle TCP stream is discarded (e.g. its FIN or RST got lost)
+pub const STREAM_TIMEOUT: Duration = Duration::from_secs(120);
+/// Minimum time between two scans of the TCP streams looking for the idle ones
+const STREAM_EVICTION_PERIOD: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Eq, Hash, PartialEq, Copy, Clone)]
+/// Object identifying the fragments of the same datagram.
+/// - *source*, *destination*: ip addresses of the source and the destination
+/// - *identification*: identification of the datagram
+/// - *protocol*: protocol carried by the datagram
+struct FragmentKey {
+    source: IpAddr,
+    destination: IpAddr,
+    identification: u32,
+    protocol: u8,
+}
+
+/// Fragment of an IPv4 or IPv6 datagram, as parsed from its header.
+/// - *source*, *destination*: ip addresses of the source and the destination of the fragment
+/// - *identification*: identification of the datagram
+/// - *protocol*: protocol carried by the datagram
+/// - *offset*: position in bytes of the fragment in the payload of the datagram
+/// - *more_fragments*: whether other fragments follow this one
+/// - *payload*: payload of the fragment
+pub struct Fragment<'a> {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    pub identification: u32,
+    pub protocol: u8,
+    pub offset: usize,
+    pub more_fragments: bool,
+    pub payload: &'a [u8],
+}
+
+/// Object used to reassemble a fragmented datagram.
+/// - *first_arrival*: capture time of the first fragment received
+/// - *data*: payload reassembled so far
+/// - *received*: sorted and disjoint ranges of bytes of the payload received
+/// - *total_len*: size of the payload, known once the last fragment is received
+struct Datagram {
+    first_arrival: SystemTime,
+    data: Vec<u8>,
+    received: Vec<(usize, usize)>,
+    total_len: Option<usize>,
+}
+
+impl Datagram {
+    /// Add a fragment to the datagram.
+    /// Overlapping fragments only fill the bytes not received yet: the bytes received first are kept.
+    fn add(&mut self, offset: usize, payload: &[u8], last: bool) {
+        let end = offset + payload.len();
+        if last && self.total_len.is_none() {
+            self.total_len = Some(end);
+        }
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+
+        // Copy the bytes falling in the holes between the ranges already received
+        let mut start = offset;
+        for &(received_start, received_end) in self.received.iter() {
+            if received_end <= start { continue; }
+            if received_start >= end { break; }
+            if received_start > start {
+                self.data[start..received_start].copy_from_slice(&payload[start - offset..received_start - offset]);
+            }
+            start = start.max(received_end);
+        }
+        if start < end {
+            self.data[start..end].copy_from_slice(&payload[start - offset..]);
+        }
+
+        // Merge the new range with the ones received
+        self.received.push((offset, end));
+        self.received.sort();
+        let mut merged: Vec<(usize, usize)> = vec![];
+        for &(range_start, range_end) in self.received.iter() {
+            match merged.last_mut() {
+                Some(last_range) if range_start <= last_range.1 => last_range.1 = last_range.1.max(range_end),
+                _ => merged.push((range_start, range_end)),
+            }
+        }
+        self.received = merged;
+    }
+
+    /// It returns whether all the fragments of the datagram have been received
+    fn is_complete(&self) -> bool {
+        return match self.total_len {
+            Some(total_len) => matches!(self.received.first(), Some(&(0, received_end)) if received_end >= total_len),
+            None => false,
+        };
+    }
+}
+
+/// Object used to reassemble one direction of a TCP connection.
+/// - *next_sequence*: sequence number of the next byte expected
+/// - *pending*: (sequence number, payload) of the segments arrived before the ones preceding them
+/// - *data*: ordered bytes not consumed yet by the application layer parsers
+/// - *last_seen*: capture time of the last segment of the stream
+struct TcpStream {
+    next_sequence: u32,
+    pending: Vec<(u32, Vec<u8>)>,
+    data: Vec<u8>,
+    last_seen: SystemTime,
+}
+
+impl TcpStream {
+    /// It returns the number of bytes buffered for the stream
+    fn buffered(&self) -> usize {
+        return self.data.len() + self.pending.iter().map(|(_, payload)| payload.len()).sum::<usize>();
+    }
+}
+
+/// Reassembler object. It puts back together the fragmented IP datagrams, so that their transport layer can be parsed,
+/// and it puts back in order the payloads of the TCP segments of each stream (a direction of a connection),
+/// so that the application layer messages split across several segments can be parsed.
+/// The memory used is bounded: when a stream would buffer more than 'flow_cap' bytes, or all the streams more than
+/// 'total_cap' bytes, the bytes buffered for the stream are dropped and the reassembly restarts after the current segment.
+/// The fragmented datagrams are discarded if not completed within FRAGMENT_TIMEOUT, or if too many of them are being reassembled,
+/// and the streams are discarded (releasing their bytes) if no segment arrives within STREAM_TIMEOUT.
+/// - *fragments*: datagrams being reassembled
+/// - *discarded_datagrams*: number of fragmented datagrams discarded since the last time it was taken
+/// - *tcp_streams*: streams being reassembled, identified by the ConversationKey of their direction
+/// - *flow_cap*: maximum number of bytes buffered for a single stream
+/// - *total_cap*: maximum number of bytes buffered for all the streams
+/// - *total_bytes*: number of bytes currently buffered for all the streams
+/// - *last_stream_eviction*: capture time of the segment on which the streams have been last scanned looking for the idle ones
+pub struct Reassembler {
+    fragments: HashMap<FragmentKey, Datagram>,
+    discarded_datagrams: usize,
+    tcp_streams: HashMap<ConversationKey, TcpStream>,
+    flow_cap: usize,
+    total_cap: usize,
+    total_bytes: usize,
+    last_stream_eviction: Option<SystemTime>,
+}
+
+impl Reassembler {
+    /// Create a new Reassembler object instance
+    /// - *flow_cap*: maximum number of bytes buffered for a single TCP stream
+    /// - *total_cap*: maximum number of bytes buffered for all the TCP streams
+    pub fn new(flow_cap: usize, total_cap: usize) -> Self {
+        return Reassembler {
+            fragments: HashMap::new(),
+            discarded_datagrams: 0,
+            tcp_streams: HashMap::new(),
+            flow_cap,
+            total_cap,
+            total_bytes: 0,
+            last_stream_eviction: None,
+        };
+    }
+
+    /// Add a fragment to its datagram. It returns the payload of the datagram if the fragment completes it.
+    /// The datagrams not completed within FRAGMENT_TIMEOUT are discarded.
+    /// - *fragment*: the fragment and the identifiers of its datagram
+    /// - *timestamp*: capture time of the fragment
+    pub fn ip_fragment(&mut self, fragment: Fragment<'_>, timestamp: SystemTime) -> Option<Vec<u8>> {
+        // Discard the datagrams whose missing fragments did not arrive in time
+        let n_datagrams = self.fragments.len();
+        self.fragments.retain(|_, datagram|
+            timestamp.duration_since(datagram.first_arrival).unwrap_or(Duration::ZERO) < FRAGMENT_TIMEOUT);
+        self.discarded_datagrams += n_datagrams - self.fragments.len();
+
+        let key = FragmentKey {
+            source: fragment.source,
+            destination: fragment.destination,
+            identification: fragment.identification,
+            protocol: fragment.protocol,
+        };
+        if fragment.offset + fragment.payload.len() > MAX_DATAGRAM_LEN {
+            // Malformed datagram
+            if self.fragments.remove(&key).is_some() {
+                self.discarded_datagrams += 1;
+            }
+            return None;
+        }
+
+        if !self.fragments.contains_key(&key) && self.fragments.len() >= MAX_FRAGMENTED_DATAGRAMS {
+            // Too many datagrams being reassembled: discard the oldest one
+            let oldest = self.fragments.iter()
+                .min_by_key(|(_, datagram)| datagram.first_arrival)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.fragments.remove(&oldest);
+                self.discarded_datagrams += 1;
+            }
+        }
+
+        let datagram = self.fragments.entry(key).or_insert_with(|| Datagram {
+            first_arrival: timestamp,
+            data: vec![],
+            received: vec![],
+            total_len: None,
+        });
+        datagram.add(fragment.offset, fragment.payload, !fragment.more_fragments);
+
+        if datagram.is_complete() {
+            let mut datagram = self.fragments.remove(&key).unwrap();
+            datagram.data.truncate(datagram.total_len.unwrap());
+            return Some(datagram.data);
+        }
+        return None;
+    }
+
+    /// It returns the number of fragmented datagrams discarded (not completed in time) since the last call
+    pub fn take_discarded_datagrams(&mut self) -> usize {
+        return std::mem::take(&mut self.discarded_datagrams);
+    }
+
+    /// Add a TCP segment to its stream. The ordered bytes not consumed yet are given to 'parse',
+    /// which returns how many of them it consumed (the remaining ones are kept waiting for the following segments).
+    /// The stream is removed when the FIN or the RST is sent, and the streams idle since more than STREAM_TIMEOUT are discarded.
+    /// - *key*: ConversationKey of the direction of the connection (protocol TCP)
+    /// - *flags*: TCP flags of the segment
+    /// - *sequence*: sequence number of the segment
+    /// - *payload*: payload of the segment
+    /// - *timestamp*: capture time of the segment
+    /// - *parse*: application layer parser
+    pub fn tcp_segment<F>(&mut self, key: ConversationKey, flags: u16, sequence: u32, payload: &[u8], timestamp: SystemTime, parse: F)
+        where F: FnOnce(&[u8]) -> usize
+    {
+        let scan_due = match self.last_stream_eviction {
+            Some(last_stream_eviction) => timestamp.duration_since(last_stream_eviction).unwrap_or(Duration::ZERO) >= STREAM_EVICTION_PERIOD,
+            None => true,
+        };
+        if scan_due {
+            self.evict_streams(timestamp);
+        }
+
+        let closing = flags & (TcpFlags::FIN | TcpFlags::RST) != 0;
+        let mut sequence = sequence;
+        let mut payload = payload;
+
+        if flags & TcpFlags::SYN != 0 {
+            // A new connection begins: the SYN consumes a sequence number before the data
+            sequence = sequence.wrapping_add(1);
+            let stream = TcpStream { next_sequence: sequence, pending: vec![], data: vec![], last_seen: timestamp };
+            if let Some(old_stream) = self.tcp_streams.insert(key, stream) {
+                self.total_bytes -= old_stream.buffered();
+            }
+        }
+
+        if !payload.is_empty() {
+            let stream = self.tcp_streams.entry(key).or_insert_with(|| TcpStream {
+                next_sequence: sequence,
+                pending: vec![],
+                data: vec![],
+                last_seen: timestamp,
+            });
+            stream.last_seen = timestamp;
+
+            // Skip the bytes already received
+            let already_received = stream.next_sequence.wrapping_sub(sequence) as i32;
+            if already_received > 0 {
+                payload = &payload[(already_received as usize).min(payload.len())..];
+                sequence = stream.next_sequence;
+            }
+
+            if !payload.is_empty() {
+                let buffered = stream.buffered();
+                if buffered + payload.len() > self.flow_cap || self.total_bytes + payload.len() > self.total_cap {
+                    // Over the memory caps: drop the stream and restart after this segment
+                    self.total_bytes -= buffered;
+                    stream.pending.clear();
+                    stream.data.clear();
+                    stream.next_sequence = sequence.wrapping_add(payload.len() as u32);
+                } else if sequence != stream.next_sequence {
+                    // A previous segment is missing: keep this one until it arrives
+                    stream.pending.push((sequence, payload.to_vec()));
+                    self.total_bytes += payload.len();
+                } else {
+                    stream.data.extend_from_slice(payload);
+                    stream.next_sequence = sequence.wrapping_add(payload.len() as u32);
+                    self.total_bytes += payload.len();
+
+                    // Append the pending segments that are now in order
+                    while let Some(pos) = stream.pending.iter()
+                        .position(|(seq, _)| (stream.next_sequence.wrapping_sub(*seq) as i32) >= 0) {
+                        let (seq, segment) = stream.pending.swap_remove(pos);
+                        let skip = (stream.next_sequence.wrapping_sub(seq) as usize).min(segment.len());
+                        stream.data.extend_from_slice(&segment[skip..]);
+                        stream.next_sequence = stream.next_sequence.wrapping_add((segment.len() - skip) as u32);
+                        self.total_bytes -= skip;
+                    }
+
+                    let consumed = parse(&stream.data).min(stream.data.len());
+                    stream.data.drain(..consumed);
+                    self.total_bytes -= consumed;
+                }
+            }
+        }
+
+        if closing {
+            if let Some(stream) = self.tcp_streams.remove(&key) {
+                self.total_bytes -= stream.buffered();
+            }
+        }
+    }
+
+    /// Discard the streams whose last segment arrived more than STREAM_TIMEOUT before 'now', releasing their bytes
+    fn evict_streams(&mut self, now: SystemTime) {
+        self.last_stream_eviction = Some(now);
+        let total_bytes = &mut self.total_bytes;
+        self.tcp_streams.retain(|_, stream| {
+            if now.duration_since(stream.last_seen).unwrap_or(Duration::ZERO) > STREAM_TIMEOUT {
+                *total_bytes -= stream.buffered();
+                return false;
+            }
+            return true;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use crate::Protocol;
+
+    /// It returns the key of a direction of a TCP connection, identified by the source port
+    fn stream_key(source_port: u16) -> ConversationKey {
+        return ConversationKey::new_key(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                                        source_port, 443, Protocol::Tcp);
+    }
+
+    /// It returns the capture time 'secs' seconds after the UNIX epoch
+    fn at(secs: u64) -> SystemTime {
+        return SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+    }
+
+    /// It returns the fragment of the test datagram (identification 1, UDP) at 'offset'
+    fn fragment(offset: usize, more_fragments: bool, payload: &[u8]) -> Fragment<'_> {
+        return Fragment {
+            source: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            destination: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            identification: 1,
+            protocol: 17,
+            offset,
+            more_fragments,
+            payload,
+        };
+    }
+
+    /// It returns the test payload of 24 bytes: 0, 1, 2, ...
+    fn payload() -> Vec<u8> {
+        return (0..24).collect();
+    }
+
+    #[test]
+    fn datagram_keeps_the_bytes_received_first() {
+        let mut datagram = Datagram { first_arrival: at(0), data: vec![], received: vec![], total_len: None };
+        datagram.add(8, &[1; 8], false);
+        datagram.add(4, &[2; 8], false);
+        datagram.add(0, &[3; 24], true);
+        assert!(datagram.is_complete());
+        assert_eq!(datagram.received, vec![(0, 24)]);
+        let mut expected = vec![3; 4];
+        expected.extend([2; 4]);
+        expected.extend([1; 8]);
+        expected.extend([3; 8]);
+        assert_eq!(datagram.data, expected);
+    }
+
+    #[test]
+    fn datagram_is_complete_only_without_holes() {
+        let mut datagram = Datagram { first_arrival: at(0), data: vec![], received: vec![], total_len: None };
+        datagram.add(0, &[0; 8], false);
+        datagram.add(16, &[0; 8], true);
+        assert_eq!(datagram.total_len, Some(24));
+        assert!(!datagram.is_complete());
+        datagram.add(8, &[0; 8], false);
+        assert!(datagram.is_complete());
+    }
+
+    #[test]
+    fn fragments_in_order_are_reassembled() {
+        let payload = payload();
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        assert_eq!(reassembler.ip_fragment(fragment(0, true, &payload[..8]), at(0)), None);
+        assert_eq!(reassembler.ip_fragment(fragment(8, true, &payload[8..16]), at(0)), None);
+        assert_eq!(reassembler.ip_fragment(fragment(16, false, &payload[16..]), at(0)), Some(payload));
+        assert!(reassembler.fragments.is_empty());
+        assert_eq!(reassembler.take_discarded_datagrams(), 0);
+    }
+
+    #[test]
+    fn fragments_in_reverse_order_are_reassembled() {
+        let payload = payload();
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        assert_eq!(reassembler.ip_fragment(fragment(16, false, &payload[16..]), at(0)), None);
+        assert_eq!(reassembler.ip_fragment(fragment(8, true, &payload[8..16]), at(0)), None);
+        assert_eq!(reassembler.ip_fragment(fragment(0, true, &payload[..8]), at(0)), Some(payload));
+    }
+
+    #[test]
+    fn overlapping_and_duplicate_fragments_are_reassembled() {
+        let payload = payload();
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        assert_eq!(reassembler.ip_fragment(fragment(0, true, &payload[..16]), at(0)), None);
+        assert_eq!(reassembler.ip_fragment(fragment(0, true, &payload[..16]), at(0)), None);
+        // The overlapping bytes received first are kept
+        assert_eq!(reassembler.ip_fragment(fragment(8, false, &[0xff; 16]), at(0)), Some([&payload[..16], &[0xff; 8][..]].concat()));
+    }
+
+    #[test]
+    fn fragments_of_different_datagrams_are_not_mixed() {
+        let payload = payload();
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        let mut other = fragment(16, false, &payload[16..]);
+        other.identification = 2;
+        assert_eq!(reassembler.ip_fragment(fragment(0, true, &payload[..16]), at(0)), None);
+        assert_eq!(reassembler.ip_fragment(other, at(0)), None);
+        assert_eq!(reassembler.fragments.len(), 2);
+    }
+
+    #[test]
+    fn incomplete_datagrams_are_discarded_after_the_timeout() {
+        let payload = payload();
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        assert_eq!(reassembler.ip_fragment(fragment(0, true, &payload[..8]), at(0)), None);
+        // The missing fragments arrive too late: the first one has been discarded
+        let late = at(FRAGMENT_TIMEOUT.as_secs());
+        assert_eq!(reassembler.ip_fragment(fragment(8, false, &payload[8..]), late), None);
+        assert_eq!(reassembler.take_discarded_datagrams(), 1);
+        assert_eq!(reassembler.take_discarded_datagrams(), 0);
+        assert_eq!(reassembler.ip_fragment(fragment(0, true, &payload[..8]), late), Some(payload));
+    }
+
+    #[test]
+    fn oversized_datagrams_are_discarded() {
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        assert_eq!(reassembler.ip_fragment(fragment(0, true, &[0; 8]), at(0)), None);
+        assert_eq!(reassembler.ip_fragment(fragment(MAX_DATAGRAM_LEN, false, &[0; 8]), at(0)), None);
+        assert!(reassembler.fragments.is_empty());
+        assert_eq!(reassembler.take_discarded_datagrams(), 1);
+    }
+
+    #[test]
+    fn idle_tcp_streams_are_evicted_and_release_their_bytes() {
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        // Nothing is consumed by the parser, so the bytes stay buffered
+        reassembler.tcp_segment(stream_key(1000), TcpFlags::ACK, 1, &[1; 100], at(0), |_| 0);
+        reassembler.tcp_segment(stream_key(2000), TcpFlags::ACK, 1, &[2; 50], at(0), |_| 0);
+        assert_eq!(reassembler.total_bytes, 150);
+
+        reassembler.tcp_segment(stream_key(2000), TcpFlags::ACK, 51, &[2; 50], at(STREAM_TIMEOUT.as_secs()), |_| 0);
+        assert_eq!(reassembler.tcp_streams.len(), 2);
+        assert_eq!(reassembler.total_bytes, 200);
+
+        // The first stream has been idle for more than STREAM_TIMEOUT, the second one has not
+        let now = STREAM_TIMEOUT.as_secs() + STREAM_EVICTION_PERIOD.as_secs() + 1;
+        reassembler.tcp_segment(stream_key(3000), TcpFlags::ACK, 1, &[3; 10], at(now), |_| 0);
+        assert_eq!(reassembler.tcp_streams.len(), 2);
+        assert!(!reassembler.tcp_streams.contains_key(&stream_key(1000)));
+        assert_eq!(reassembler.total_bytes, 110);
+    }
+
+    #[test]
+    fn closed_tcp_streams_release_their_bytes() {
+        let mut reassembler = Reassembler::new(DEFAULT_FLOW_CAP, DEFAULT_TOTAL_CAP);
+        reassembler.tcp_segment(stream_key(1000), TcpFlags::ACK, 1, &[1; 100], at(0), |_| 0);
+        reassembler.tcp_segment(stream_key(1000), TcpFlags::ACK | TcpFlags::FIN, 101, &[1; 10], at(1), |_| 0);
+        assert!(reassembler.tcp_streams.is_empty());
+        assert_eq!(reassembler.total_bytes, 0);
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+use crate::events::{FinalSummary, IntervalSummary};
+use crate::Filter;
+use crate::csv_sink::CsvSink;
+use crate::json_sink::{JsonSink, NdjsonSink};
+use crate::table_sink::TableSink;
+
+/// Destination of the reports. The 'Reporter' calls 'begin()' once before the first packet is handled,
+/// 'write_interval()' every time a time interval is over (the report is updated) and 'write_final()' once at the end of the sniffing.
+/// The sinks are driven by the Reporter thread, so they need to be 'Send'.
+/// An error returned by a sink is printed by the Reporter, which keeps driving the other sinks.
+pub trait ReportSink: Send {
+    /// It is called once before the first packet is handled, with the parameters of the session
+    fn begin(&mut self, _session: &SessionInfo) -> io::Result<()> {
+        return Ok(());
+    }
+    /// It writes the summary of a time interval
+    fn write_interval(&mut self, summary: &IntervalSummary) -> io::Result<()>;
+    /// It writes the summary of the whole sniffing
+    fn write_final(&mut self, summary: &FinalSummary) -> io::Result<()>;
+}
+
+/// Parameters of the sniffing session, given to the sinks before the first packet.
+/// - *interfaces*: names of the network interfaces sniffed (empty when reading a capture file)
+/// - *capture_file*: capture file read (None when sniffing the network interfaces)
+/// - *filter*: filter of the packets shown in the report
+/// - *time_interval*: number of seconds between two updates of the report
+/// - *bidirectional*: whether the packets A->B and B->A are aggregated in the same conversation
+/// - *vlan_conversations*: whether the same endpoints talking on different VLANs form different conversations
+/// - *start_time*: when the analysis began
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    interfaces: Vec<String>,
+    capture_file: Option<String>,
+    filter: Filter,
+    time_interval: usize,
+    bidirectional: bool,
+    vlan_conversations: bool,
+    start_time: SystemTime,
+}
+
+impl SessionInfo {
+    /// Create a new SessionInfo object instance
+    pub(crate) fn new(interfaces: Vec<String>,
+                      capture_file: Option<String>,
+                      filter: Filter,
+                      time_interval: usize,
+                      bidirectional: bool,
+                      vlan_conversations: bool,
+                      start_time: SystemTime,
+    ) -> Self {
+        return SessionInfo { interfaces, capture_file, filter, time_interval, bidirectional, vlan_conversations, start_time };
+    }
+    /// Get the names of the network interfaces sniffed
+    pub fn get_interfaces(&self) -> &[String] { return &self.interfaces }
+    /// Get the capture file read
+    pub fn get_capture_file(&self) -> Option<&str> { return self.capture_file.as_deref() }
+    /// Get the filter of the packets shown in the report
+    pub fn get_filter(&self) -> &Filter { return &self.filter }
+    /// Get the number of seconds between two updates of the report
+    pub fn get_time_interval(&self) -> usize { return self.time_interval }
+    /// Get whether the packets A->B and B->A are aggregated in the same conversation
+    pub fn is_bidirectional(&self) -> bool { return self.bidirectional }
+    /// Get whether the same endpoints talking on different VLANs form different conversations
+    pub fn get_vlan_conversations(&self) -> bool { return self.vlan_conversations }
+    /// Get when the analysis began
+    pub fn get_start_time(&self) -> SystemTime { return self.start_time }
+}
+
+/// Formats of the built-in report sinks. Each of them writes the report and the final report in files with its extension.
+/// - *Table*: ASCII tables (.txt)
+/// - *Csv*: comma separated values, one row for each conversation (.csv)
+/// - *Json*: JSON document, with an array of the time intervals in the report (.json)
+/// - *Ndjson*: newline delimited JSON, one line for each time interval (.ndjson)
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ReportFormat {
+    Table,
+    Csv,
+    Json,
+    Ndjson,
+}
+
+/// All the formats of the built-in report sinks
+pub const REPORT_FORMATS: [ReportFormat; 4] = [ReportFormat::Table, ReportFormat::Csv, ReportFormat::Json, ReportFormat::Ndjson];
+
+impl ReportFormat {
+    /// It returns the extension of the files written in this format
+    pub fn extension(&self) -> &'static str {
+        return match self {
+            ReportFormat::Table => "txt",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+            ReportFormat::Ndjson => "ndjson",
+        };
+    }
+
+    /// It returns the format of the files with the given extension, if any
+    pub fn from_extension(extension: &str) -> Option<ReportFormat> {
+        return REPORT_FORMATS.into_iter().find(|format| format.extension() == extension);
+    }
+
+    /// It creates the sink writing the report and the final report in this format.
+    /// The files are named as 'filename' and 'final_filename', with the extension of the format.
+    /// It returns an error if the report cannot be created.
+    pub fn create_sink(&self, filename: &str, final_filename: &str) -> io::Result<Box<dyn ReportSink>> {
+        let filename = with_extension(filename, self.extension());
+        let final_filename = with_extension(final_filename, self.extension());
+        return Ok(match self {
+            ReportFormat::Table => Box::new(TableSink::new(filename, final_filename)?),
+            ReportFormat::Csv => Box::new(CsvSink::new(filename, final_filename)?),
+            ReportFormat::Json => Box::new(JsonSink::new(filename, final_filename)?),
+            ReportFormat::Ndjson => Box::new(NdjsonSink::new(filename, final_filename)?),
+        });
+    }
+}
+
+impl Display for ReportFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ReportFormat::Table => "table",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+            ReportFormat::Ndjson => "ndjson",
+        })
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    /// It parses the name of the format (case insensitive): "table", "csv", "json", "ndjson"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match REPORT_FORMATS.into_iter().find(|format| format.to_string().eq_ignore_ascii_case(s.trim())) {
+            Some(format) => Ok(format),
+            None => Err(format!("unknown report format '{}' (expected one of: table, csv, json, ndjson)", s)),
+        };
+    }
+}
+
+/// It returns the path with its extension replaced (or added) by the given one
+pub fn with_extension(path: &str, extension: &str) -> String {
+    return Path::new(path).with_extension(extension).to_string_lossy().to_string();
+}
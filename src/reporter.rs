@@ -1,92 +1,94 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use std::fs::File;
-use std::{io, thread};
+use std::thread;
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
 use std::time::{Duration, SystemTime};
-use crate::packet_handle::{ConversationKey, ConversationStats, PacketInfo};
-use crate::{Filter, Protocol, Status, StatusValue};
-use tabled::{Table, Tabled, Style, Width, Modify, Disable, Alignment, Extract};
-use tabled::object::{ Columns,  Object, Segment};
-use std::io::Write;
-use tabled::formatting_settings::TrimStrategy;
-
-#[derive(Tabled)]
-struct ConvTabled{
-    time: String,
-    ip_srg: String,
-    prt_srg: String,
-    ip_dest: String,
-    prt_dest: String,
-    protocol: String,
-    tot_bytes: String,
-    starting_time: String,
-    ending_time: String,
-    tot_packets: String,
-}
-impl ConvTabled{
-    fn new( time: String,
-            ip_srg: String,
-            prt_srg: String,
-            ip_dest: String,
-            prt_dest: String,
-            protocol: String,
-            tot_bytes: String,
-            starting_time: String,
-            ending_time: String,
-            tot_packets: String)
-        -> ConvTabled{
-        ConvTabled{
-            time, ip_srg, prt_srg, ip_dest, prt_dest, protocol, tot_bytes, starting_time, ending_time, tot_packets
-        }
-    }
-}
+use crate::packet_handle::{ConversationKey, ConversationStats, MacConversationKey, MacConversationStats, PacketInfo};
+use crate::{Status, StatusValue};
+use crate::tcp_tracker::{TcpEvents, TcpTracker};
+use crate::events::{AnalyserEvent, Conversation, FinalSummary, IntervalSummary, NonIpCount, Subscriber};
+use crate::report_sink::{ReportSink, SessionInfo};
+use crate::metrics::Metrics;
+use pnet::packet::ethernet::EtherType;
+
+/// Maximum time the Reporter waits for a packet before checking the status of the application and the timer
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Frames not carrying an IP packet, counted by EtherType (None if the link layer does not carry it): (frames, bytes)
+type NonIpFrames = HashMap<Option<EtherType>, (usize, usize)>;
+
 /// Reporter object. It gets 'PacketInfo's from the 'Sniffer' object through the 'receiver_channel'.
-/// Every 'time_interval' seconds it prints on the 'filename' file the report of the conversations happened in the last time interval.
+/// Every 'time_interval' seconds it gives to the report sinks the summary of the conversations happened in the last time interval.
+/// The packets are assigned to a time interval based on their capture timestamp.
 /// In 'pause' mode stops taking packets from the channel and stops updating the report.
-/// In 'exit' mode writes on the report the last update and creates a final report with all the conversations happened.
-/// - *filename*: name of the report file (.txt)
-/// - *final_filename*: name of the final report file (.txt)
+/// In 'exit' mode gives to the sinks the last update and the final summary with all the conversations happened.
+/// - *sinks*: destinations of the reports (e.g. the ASCII tables of the report files)
+/// - *session*: parameters of the sniffing session, given to the sinks before the first packet
 /// - *time_interval*: number of seconds before updating the report
 /// - *status_sniffing*: status of the application ['Running', 'Exit', 'Pause']
 /// - *receiver_channel*: receiver end of the channel shared with the Sniffer thread
 /// - *status_writing*: status shared with the Timer thread. When set to 'True' the reporter needs to update the report
-/// - *initial_time*: when the application began sniffing
-/// - *filter*: information on which packets the user is interested on see in the report
+/// - *initial_time*: when the application began sniffing (offline analysis: the timestamp of the first packet recorded in the capture file, None until it is received)
+/// - *offline*: whether the packets are read from a capture file. In this case the report of a time interval is written as soon as a packet of the following one is read, instead of waiting for the Timer
+/// - *interfaces*: names of the network interfaces sniffed (empty when reading a capture file). They are used to name the interface of each conversation
+/// - *current_interval*: index of the time interval the conversations in 'convs_summaries' belong to
+/// - *bidirectional*: whether the packets A->B and B->A are aggregated in the same conversation (client -> server)
+/// - *vlan_conversations*: whether the same endpoints talking on different VLANs form different conversations
+/// - *interval_reassembled*, *interval_discarded*: number of fragmented datagrams reassembled and discarded (incomplete) in the current time interval
+/// - *tot_reassembled*, *tot_discarded*: number of fragmented datagrams reassembled and discarded (incomplete) during the whole sniffing
+/// - *mac_convs_final*: layer 2 conversations (between two MAC addresses) happened during the whole sniffing, of all the frames accepted by the filter
+/// - *interval_non_ip*, *tot_non_ip*: frames accepted by the filter not carrying an IP packet, in the current time interval and during the whole sniffing
+/// - *tcp_tracker*: state and sequence analysis of the TCP connections, used to report how each of them ended and its retransmissions, out-of-order segments, duplicate ACKs and zero windows
+/// - *subscribers*: subscribers to which the parsed packets (if they asked for them) and the summaries are sent as 'AnalyserEvent's. The subscribers that dropped their receiver are removed
+/// - *metrics*: if set, metrics of the metrics endpoint, updated for each packet got from the channel
 pub struct Reporter {
-    filename: String,
-    final_filename: String,
+    sinks: Vec<Box<dyn ReportSink>>,
+    session: SessionInfo,
     time_interval: usize,
     status_sniffing: Arc<Status>,
     convs_summaries: HashMap<ConversationKey, ConversationStats>,
     convs_final: HashMap<ConversationKey, ConversationStats>,
     receiver_channel: Receiver<PacketInfo>,
     status_writing: Arc<Mutex<bool>>,
-    initial_time: SystemTime,
-    filter: Filter,
+    initial_time: Option<SystemTime>,
+    offline: bool,
+    interfaces: Vec<String>,
+    current_interval: Option<u64>,
+    bidirectional: bool,
+    vlan_conversations: bool,
+    interval_reassembled: usize,
+    interval_discarded: usize,
+    tot_reassembled: usize,
+    tot_discarded: usize,
+    mac_convs_final: HashMap<MacConversationKey, MacConversationStats>,
+    interval_non_ip: NonIpFrames,
+    tot_non_ip: NonIpFrames,
+    tcp_tracker: TcpTracker,
+    subscribers: Vec<Subscriber>,
+    metrics: Option<Arc<Metrics>>,
 }
 
 impl Reporter {
     /// Initialize the Reporter object
-    /// - *filename*: name of the report file (.txt)
-    /// - *final_filename*: name of the final report file (.txt)
-    /// - *time_interval*: number of seconds before updating the report
+    /// - *sinks*: destinations of the reports
+    /// - *session*: parameters of the sniffing session (time interval, filter, network interfaces or capture file, aggregation of the conversations)
     /// - *status_sniffing*: status of the application ['Running', 'Quit', 'Pause']
     /// - *receiver_channel*: receiver end of the channel shared with the Sniffer thread
-    /// - *initial_time*: when the application began sniffing
-    /// - *filter*: information on which packets the user is interested on see in the report
-    pub fn new(filename: String,
-               final_filename: String,
-               time_interval: usize,
+    /// - *initial_time*: when the application began sniffing (None when reading a capture file: the time of its first packet is used)
+    /// - *subscribers*: subscribers to the events
+    /// - *metrics*: if set, metrics of the metrics endpoint
+    pub fn new(sinks: Vec<Box<dyn ReportSink>>,
+               session: SessionInfo,
                status_sniffing: Arc<Status>,
                receiver_channel: Receiver<PacketInfo>,
-               initial_time: SystemTime,
-               filter: Filter,
+               initial_time: Option<SystemTime>,
+               subscribers: Vec<Subscriber>,
+               metrics: Option<Arc<Metrics>>,
     ) -> Self {
         Self {
-            filename,
-            final_filename,
-            time_interval,
+            sinks,
+            time_interval: session.get_time_interval(),
             status_sniffing,
             convs_summaries: HashMap::new(),
             convs_final: HashMap::new(),
@@ -94,16 +96,36 @@ impl Reporter {
 
             status_writing:Arc::new(Mutex::new(false)),
             initial_time,
-            filter
+            offline: session.get_capture_file().is_some(),
+            interfaces: session.get_interfaces().to_vec(),
+            current_interval: None,
+            bidirectional: session.is_bidirectional(),
+            vlan_conversations: session.get_vlan_conversations(),
+            session,
+            interval_reassembled: 0,
+            interval_discarded: 0,
+            tot_reassembled: 0,
+            tot_discarded: 0,
+            mac_convs_final: HashMap::new(),
+            interval_non_ip: HashMap::new(),
+            tot_non_ip: HashMap::new(),
+            tcp_tracker: TcpTracker::new(),
+            subscribers,
+            metrics,
         }
     }
     /// Function used to perform the reporting.
     /// It can be called only once. It returns when the status goes to 'Quit'.
     pub fn reporting(mut self) {
         let mut status;
-        let mut file = open_file(&self.filename).unwrap();
         let mut n_packets = 0;
-        let mut write_titles = true;
+
+        // Give the parameters of the session to the sinks
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.begin(&self.session) {
+                println!("> [Error]: cannot write the report: {}", err);
+            }
+        }
 
         // Create the thread Timer
 
@@ -111,7 +133,7 @@ impl Reporter {
         let (snd_timer, rcv_timer) = channel();
 
         // - Clone time interval to pass it to the timer
-        let time_interval = self.time_interval.clone();
+        let time_interval = self.time_interval;
         // - Clone the writing status (shared by reporter and timer)
         let status_writing = self.status_writing.clone();
         // - Run the thread
@@ -126,29 +148,32 @@ impl Reporter {
         });
 
 
+        // Clone the status of the application (the lock is held while the conversations get updated)
+        let status_sniffing = self.status_sniffing.clone();
+
         loop {
 
-            if !self.convs_summaries.is_empty() // If there are conversation to write
+            if !self.offline && (!self.convs_summaries.is_empty() || !self.interval_non_ip.is_empty()) // If there are conversation to write (offline the report is updated only based on the packets time)
             {   // Get the lock and check if its time to update the report (status set to true)
                 let mut status_writing_value = self.status_writing.lock().unwrap();
 
-                if *status_writing_value == true {
-                    println!("> Updating the report with {} conversations happened in the last time interval...", self.convs_summaries.len());
-                    // Set to false the status value
-                    *status_writing_value = false;
-                    // Perform the update
-                    write_summaries(&mut file, &self.convs_summaries, &self.initial_time, &self.time_interval, write_titles);
-                    // Write titles only the first time.
-                    if write_titles {
-                        write_titles = false;
+                if *status_writing_value {
+                    // Perform the update if the current time interval is over. The status is set back to false only
+                    // when the update is performed, so that a timer tick arrived just before the end of the time interval is not lost
+                    if let Some(current) = self.current_interval {
+                        let interval_end = Duration::from_secs((current + 1) * self.time_interval as u64);
+                        if self.initial_time.unwrap().elapsed().unwrap_or(Duration::ZERO) >= interval_end {
+                            *status_writing_value = false;
+                            drop(status_writing_value);
+                            self.update_report(current * self.time_interval as u64);
+                            self.current_interval = None;
+                        }
                     }
-                    // Clear out the hash map
-                    self.convs_summaries.clear();
                 }
             }
 
             {  // Check the sniffing value getting the lock
-                let mut status_sniffing_value = self.status_sniffing.mutex.lock().unwrap();
+                let mut status_sniffing_value = status_sniffing.mutex.lock().unwrap();
 
                 match *status_sniffing_value {
                     StatusValue::Running =>  status = StatusValue::Running,
@@ -157,7 +182,7 @@ impl Reporter {
                     StatusValue::Paused => {
 
                         // Conditional waiting until the status get back to "running" or is set to "exit"
-                        status_sniffing_value = self.status_sniffing.cvar.wait_while(status_sniffing_value, |s| is_paused(&*s)).unwrap();
+                        status_sniffing_value = status_sniffing.cvar.wait_while(status_sniffing_value, |s| is_paused(&*s)).unwrap();
 
                         status = *status_sniffing_value;
                         // Here the status is either running or exit
@@ -169,19 +194,29 @@ impl Reporter {
                         // status running
                     }
                     StatusValue::Exit => {
-                        if !self.convs_summaries.is_empty() {// Before exit update the report one last time and produces final report
-                            println!("> Update report...");
-                            write_summaries(&mut file, &self.convs_summaries, &self.initial_time, &self.time_interval, false);
+                        drop(status_sniffing_value);
+                        // Get the packets still in the channel (read from the capture file, or sniffed before reaching the duration or the packet count)
+                        while let Ok(new_packet_info) = self.receiver_channel.try_recv() {
+                            if self.receive_packet_info(new_packet_info) {
+                                n_packets += 1;
+                            }
                         }
 
+                        // Before exit update the report one last time and produces final report
+                        if !self.convs_summaries.is_empty() || !self.interval_non_ip.is_empty() {
+                            println!("> Update report...");
+                            self.update_report(self.current_interval.unwrap_or(0) * self.time_interval as u64);
+                        }
 
                         // Writes all conversations in final report
-                        println!("> Writing final report in {} ...", self.final_filename);
-                        let mut final_file = open_file(&self.final_filename).unwrap();
-                        write_final_report(
-                            &mut final_file,
-                            &self.convs_final
-                        );
+                        let summary = self.final_summary(n_packets);
+                        for sink in self.sinks.iter_mut() {
+                            if let Err(err) = sink.write_final(&summary) {
+                                println!("> [Error]: cannot write the final report: {}", err);
+                            }
+                        }
+                        self.emit(AnalyserEvent::Final(summary));
+
                         // Alert the timer thread
                         snd_timer.send(()).unwrap();
                         // Wait the conclusion of the timer handle
@@ -197,282 +232,276 @@ impl Reporter {
             // Code reached only in running mode
             assert_eq!(status, StatusValue::Running);
 
-            // Get a new packet_info from the channel (if its there)
-            while let Ok(new_packet_info) = self.receiver_channel.try_recv(){
-                // If the packet does not need to be filtered out add it in the hashmap
-                if new_packet_info.get_printed() && check_filter(self.filter, new_packet_info.clone()){
-                    n_packets += 1;
-                    // Create the key of the packet considering (ip_sorg, ip_dest, port_sorg, port_dest, prot)
-                    let key = ConversationKey::new_key(new_packet_info.get_ip_sorgente().unwrap(),
-                                                       new_packet_info.get_ip_destinazione().unwrap(),
-                                                       new_packet_info.get_porta_sorgente(),
-                                                       new_packet_info.get_porta_destinazione(),
-                                                       new_packet_info.get_protocol());
-                    // If the packet belongs to a conversation already present in the map, update the stats, otherwise add a new record
-                    self.convs_summaries.entry(key)
-                        .and_modify(|entry| {
-                            entry.set_tot_bytes(new_packet_info.get_dim());
-                            entry.set_ending_time(new_packet_info.get_time().unwrap());
-                            entry.set_tot_packets(1);
-                        })
-                        .or_insert(ConversationStats::new(
-                            new_packet_info.get_dim(),
-                            new_packet_info.get_time().unwrap(),
-                            new_packet_info.get_time().unwrap(),
-                        1));
-
-                    // Updates also convs_final
-                    self.convs_final.entry(key)
-                        .and_modify(|entry| {
-                            entry.set_tot_bytes(new_packet_info.get_dim());
-                            entry.set_ending_time(new_packet_info.get_time().unwrap());
-                            entry.set_tot_packets(1);
-                        })
-                        .or_insert(ConversationStats::new(
-                            new_packet_info.get_dim(),
-                            new_packet_info.get_time().unwrap(),
-                            new_packet_info.get_time().unwrap(),
-                            1));
+            // Wait a new packet_info from the channel (at most POLL_TIMEOUT, so that the status and the timer get checked anyway),
+            // then get the ones already there
+            match self.receiver_channel.recv_timeout(POLL_TIMEOUT) {
+                Ok(new_packet_info) => {
+                    if self.receive_packet_info(new_packet_info) {
+                        n_packets += 1;
+                    }
+                    while let Ok(new_packet_info) = self.receiver_channel.try_recv() {
+                        if self.receive_packet_info(new_packet_info) {
+                            n_packets += 1;
+                        }
+                    }
                 }
+                Err(RecvTimeoutError::Timeout) => {}
+                // All the Sniffers returned: the application is going in 'Exit' state
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(POLL_TIMEOUT),
             }
         }
     }
-}
-/// Timer function. At each iteration it waits at most 'time interval' seconds. If a packet is received before the timeout it means that the status got to 'Quit'
-/// so also the timer need to return.
-/// Otherwise 'time_interval' seconds passed so the timer sets the 'status_writing_value' to 'true' to notify the reporter to write the report.
-fn timer(rcv_timer: Receiver<()>, time_interval: usize, status_writing: Arc<Mutex<bool>>)
-{
-    loop {
-         match rcv_timer.recv_timeout(Duration::from_secs(time_interval as u64)) {
-            Ok(_) => {
-                break;
-            },
-            Err(err) =>
-                if err == RecvTimeoutError::Timeout {
-                    let mut status_writing_value = status_writing.lock().unwrap();
-                    *status_writing_value = true;
-                }
-        }
-    }
 
-}
-/// It checks if the given packet_info needs to be filtered.
-fn check_filter(filter: Filter, packet_info: PacketInfo) -> bool {
-    if filter.get_ip_srg().is_some() &&
-        packet_info.get_ip_sorgente().unwrap() != filter.get_ip_srg().unwrap() {
-        return false;
-    }
-    if filter.get_ip_dest().is_some() &&
-        packet_info.get_ip_destinazione().unwrap() != filter.get_ip_dest().unwrap() {
-        return false;
-    }
-    if filter.get_prt_srg().is_some() &&
-        packet_info.get_porta_sorgente() != filter.get_prt_srg().unwrap() {
-        return false;
-    }
-    if filter.get_prt_dest().is_some() &&
-        packet_info.get_porta_destinazione() != filter.get_prt_dest().unwrap() {
-        return false;
+    /// It handles a packet_info got from the channel, counting it in the metrics (if any).
+    /// It returns true if the packet has been added to the conversations.
+    fn receive_packet_info(&mut self, new_packet_info: PacketInfo) -> bool {
+        let metrics = match self.metrics.clone() {
+            Some(metrics) => metrics,
+            None => return self.handle_packet_info(new_packet_info),
+        };
+        metrics.packet_received();
+        let protocol = new_packet_info.get_protocol().to_string();
+        let interface = new_packet_info.get_interface()
+            .or(self.session.get_capture_file())
+            .unwrap_or_default()
+            .to_string();
+        let bytes = new_packet_info.get_dim();
+        let accepted = self.handle_packet_info(new_packet_info);
+        metrics.add_packet(&protocol, &interface, bytes, accepted, self.convs_summaries.len(), self.tcp_tracker.get_open_connections());
+        return accepted;
     }
-    true
-}
-
 
-/*
-*  PRINT on FiLE FUNCTIONS
-*
-*/
-/// Given a file name returns the handle of the opened file.
-fn open_file(filename: &String) -> io::Result<File> {
-    //TODO: GESTIRE ERRORE APERTURA FILE ???
-    return File::options().write(true).truncate(true).create(true).open(filename);
-}
-
-
-/// It writes all the conversations contained in the HashMap in the file appending at the end of the file.
-/// The conversations are organised in a table with rows: [time | ip_srg | prt_srg | ip_dest | prt_dest | protocol | tot_bytes | starting_time | ending_time | tot_packets ]
-/// sorted by starting_time.
-fn write_summaries(file: &mut File, convs_summaries: &HashMap<ConversationKey, ConversationStats>, time: &SystemTime, time_interval: &usize, write_titles: bool) {
+    /// It adds the packet_info to the conversations, unless it needs to be filtered out.
+    /// If the packet belongs to a new time interval, the report is first updated with the conversations of the previous one.
+    /// It returns true if the packet has been added to the conversations.
+    fn handle_packet_info(&mut self, mut new_packet_info: PacketInfo) -> bool {
+        // Arrival time of the packet, derived from its capture timestamp. Packets captured by the kernel right before the start are considered as arrived at time 0
+        let timestamp = new_packet_info.get_timestamp().unwrap();
+        let initial_time = *self.initial_time.get_or_insert(timestamp);
+        new_packet_info.set_time(timestamp.duration_since(initial_time).unwrap_or(Duration::ZERO));
+
+        // Count the fragmented datagrams on all the packets
+        if new_packet_info.get_reassembled() {
+            self.interval_reassembled += 1;
+            self.tot_reassembled += 1;
+        }
+        self.interval_discarded += new_packet_info.get_discarded_datagrams();
+        self.tot_discarded += new_packet_info.get_discarded_datagrams();
 
-    // Retrieves closest value of time interval since time elapsed
-    let secs : u64 = time.elapsed().unwrap().as_secs()
-        .div_euclid(*time_interval as u64)*(*time_interval as u64);
-    let secs_str : String = secs.to_string();
+        // Follow the TCP connections on all the packets, so that the state is correct even if one direction is filtered out
+        let tcp_events = self.tcp_tracker.update(&new_packet_info);
 
-    // values for print the report table
-    let style = Style::ascii();
-    let column_dim = 30;
-    let mut convs_printed = vec![];
+        // If the packet does not need to be filtered out add it in the hashmap (the filter has been checked by the Sniffer)
+        if !new_packet_info.get_printed() {
+            self.emit_packet(&new_packet_info, false);
+            return false;
+        }
 
-    // Creo un vettore in cui inserisco le conversazioni come tupla (Key, Stats)
-    let mut sorted_conv: Vec<(ConversationKey, ConversationStats)> = Vec::new();
-    for(key, elem) in convs_summaries {
-        sorted_conv.push((*key, *elem));
-    }
-    // ordino per starting_time
-    sorted_conv.sort_by(|a,b|
-        a.1.get_starting_time().cmp(&b.1.get_starting_time()));
-
-    for conv in sorted_conv {
-        let start = conv.1.get_starting_time().unwrap();
-        let end = conv.1.get_ending_time().unwrap();
-        let start_format = format!("{}.{} secs", start.as_secs(), start.as_millis());
-        let end_format = format!("{}.{} secs", end.as_secs(), end.as_millis());
-
-        // handle default values => replace with "-"
-        let prt_src;
-        let normalized_prt_src = match conv.0.get_prt_srg() {
-            0 => "-",
-            _ => {
-                prt_src = conv.0.get_prt_srg().to_string();
-                &prt_src
+        // Time interval of the packet, based on its capture timestamp
+        let interval = new_packet_info.get_time().unwrap().as_secs().div_euclid(self.time_interval as u64);
+        match self.current_interval {
+            Some(current) if current != interval => {
+                self.update_report(current * self.time_interval as u64);
             }
-        };
-        let prt_dst;
-        let normalized_prt_dst = match conv.0.get_prt_dest() {
-            0 => "-",
+            _ => {}
+        }
+        self.current_interval = Some(interval);
+        self.emit_packet(&new_packet_info, true);
+
+        // Layer 2 conversation, if the link layer carries both the MAC addresses
+        if let (Some(mac_srg), Some(mac_dest)) = (new_packet_info.get_mac_sorgente(), new_packet_info.get_mac_destinazione()) {
+            let mut key = MacConversationKey::new_key(mac_srg, mac_dest);
+            if self.bidirectional {
+                key = key.normalized();
+            }
+            self.mac_convs_final.entry(key)
+                .or_default()
+                .add_frame(new_packet_info.get_dim(), new_packet_info.get_ethertype());
+        }
+
+        // The frames not carrying an IP packet (unknown lvl 3 protocol or malformed) are only counted
+        let (ip_sorg, ip_dest) = match (new_packet_info.get_ip_sorgente(), new_packet_info.get_ip_destinazione()) {
+            (Some(ip_sorg), Some(ip_dest)) => (ip_sorg, ip_dest),
             _ => {
-                prt_dst = conv.0.get_prt_dest().to_string();
-                &prt_dst
+                for non_ip in [&mut self.interval_non_ip, &mut self.tot_non_ip] {
+                    let count = non_ip.entry(new_packet_info.get_ethertype()).or_insert((0, 0));
+                    count.0 += 1;
+                    count.1 += new_packet_info.get_dim();
+                }
+                return true;
             }
         };
 
-        let prtcl = match conv.0.get_protocol() {
-            Protocol::None => "-".to_string(),
-            _ => conv.0.get_protocol().to_string(),
-        };
+        // Create the key of the packet considering (ip_sorg, ip_dest, port_sorg, port_dest, prot)
+        let mut key = ConversationKey::new_key(ip_sorg,
+                                               ip_dest,
+                                               new_packet_info.get_porta_sorgente(),
+                                               new_packet_info.get_porta_destinazione(),
+                                               new_packet_info.get_protocol());
+        // Aggregating both directions the key is (client, server): the packet is forward if it is sent by the client
+        if self.bidirectional {
+            key = key.normalized();
+        }
+        // The same endpoints on different VLANs form different conversations
+        if self.vlan_conversations {
+            key = key.with_vlan_ids(new_packet_info.get_vlan_ids());
+        }
+        // The same endpoints seen on different network interfaces form different conversations
+        if let Some(interface) = new_packet_info.get_interface() {
+            let index = self.interfaces.iter().position(|name| name == interface).unwrap_or(0);
+            key = key.with_interface(index);
+        }
 
-        let conv = ConvTabled::new(
-            secs_str.clone(),
-            conv.0.get_ip_srg().to_string(),
-            normalized_prt_src.to_string(),
-            conv.0.get_ip_dest().to_string(),
-            normalized_prt_dst.to_string(),
-            prtcl,
-            conv.1.get_tot_bytes().to_string(),
-            start_format,
-            end_format,
-            conv.1.get_tot_packets().to_string()
-        );
-
-        convs_printed.push(conv);
-    }
+        // If the packet belongs to a conversation already present in the map, update the stats, otherwise add a new record
+        update_conversation(&mut self.convs_summaries, key, &new_packet_info, &tcp_events);
+        // Updates also convs_final
+        update_conversation(&mut self.convs_final, key, &new_packet_info, &tcp_events);
 
-    let mut table = Table::new(convs_printed);
+        return true;
+    }
 
-    //write the header only the first time
-    if write_titles == false{
-        table = table.with(Disable::Row(0..1))
+    /// It gives to the sinks (and to the subscribers) the summary of the time interval starting at 'interval_start' seconds, then it clears out its conversations.
+    fn update_report(&mut self, interval_start: u64) {
+        if self.convs_summaries.is_empty() && self.interval_non_ip.is_empty() {
+            return;
+        }
+        println!("> Updating the report with {} conversations happened in the last time interval...", self.convs_summaries.len());
+        let summary = self.interval_summary(interval_start);
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.write_interval(&summary) {
+                println!("> [Error]: cannot write the report: {}", err);
+            }
+        }
+        self.emit(AnalyserEvent::Interval(summary));
+        self.tcp_tracker.clear_expired();
+        self.interval_reassembled = 0;
+        self.interval_discarded = 0;
+        // Clear out the hash maps
+        self.convs_summaries.clear();
+        self.interval_non_ip.clear();
+        if let Some(metrics) = &self.metrics {
+            metrics.set_interval_conversations(0);
+        }
     }
 
-    //set the style
-    table = table.with(style.clone())
+    /// It creates the summary of the current time interval, starting at 'interval_start' seconds
+    fn interval_summary(&self, interval_start: u64) -> IntervalSummary {
+        return IntervalSummary::new(self.initial_time,
+                                    interval_start,
+                                    self.conversations(&self.convs_summaries),
+                                    self.interval_reassembled,
+                                    self.interval_discarded,
+                                    non_ip_counts(&self.interval_non_ip));
+    }
 
-        //set the minimum dimension of all the columns
-        .with(Width::justify(column_dim/2))
+    /// It creates the summary of the whole sniffing
+    /// - *packets*: number of packets accepted by the filter
+    fn final_summary(&self, packets: usize) -> FinalSummary {
+        let mut mac_conversations: Vec<(MacConversationKey, MacConversationStats)> = self.mac_convs_final.iter()
+            .map(|(key, stats)| (*key, stats.clone()))
+            .collect();
+        mac_conversations.sort_by_key(|(_, stats)| Reverse(stats.get_tot_bytes()));
+        return FinalSummary::new(self.initial_time,
+                                 packets,
+                                 self.conversations(&self.convs_final),
+                                 self.tot_reassembled,
+                                 self.tot_discarded,
+                                 non_ip_counts(&self.tot_non_ip),
+                                 mac_conversations);
+    }
 
-        //except the address ip ones
-        .with(Modify::new(Columns::single(1).and(Columns::single(3))).with(Width::increase(column_dim)))
+    /// It returns the conversations of the map sorted by starting time, with the name of their network interface and the state of their TCP connection
+    fn conversations(&self, convs: &HashMap<ConversationKey, ConversationStats>) -> Vec<Conversation> {
+        let mut conversations: Vec<Conversation> = convs.iter()
+            .map(|(key, stats)| {
+                let tcp_state = if stats.is_tcp() { self.tcp_tracker.get_state(key) } else { None };
+                Conversation::new(*key, *stats, self.interfaces.get(key.get_interface()).cloned(), tcp_state)
+            })
+            .collect();
+        conversations.sort_by_key(|conversation| conversation.get_stats().get_starting_time());
+        return conversations;
+    }
 
-        //align at the middle
-        .with(
-            Modify::new(Segment::all())
-                .with(Alignment::center())
-                .with(TrimStrategy::Horizontal)
-    );
+    /// It sends the parsed packet to the subscribers that asked for the packets (if any)
+    /// - *accepted*: whether the packet passed the filter
+    fn emit_packet(&mut self, packet_info: &PacketInfo, accepted: bool) {
+        if self.subscribers.iter().any(Subscriber::wants_packets) {
+            self.emit(AnalyserEvent::Packet { info: packet_info.clone(), accepted });
+        }
+    }
 
-    //scrivo il report
-    write!(file, "{}\n", table.to_string()).expect("Error during the writing of the report");
+    /// It sends the event to all the subscribers (if any), removing the ones that dropped their receiver
+    fn emit(&mut self, event: AnalyserEvent) {
+        self.subscribers.retain_mut(|subscriber| subscriber.send(&event));
+    }
+}
 
+/// It returns the counts of the frames not carrying an IP packet, sorted by number of frames (descending)
+fn non_ip_counts(non_ip: &NonIpFrames) -> Vec<NonIpCount> {
+    let mut counts: Vec<NonIpCount> = non_ip.iter()
+        .map(|(ethertype, (frames, bytes))| NonIpCount::new(*ethertype, *frames, *bytes))
+        .collect();
+    counts.sort_by_key(|count| Reverse(count.get_frames()));
+    return counts;
 }
-/// Write all the conversations sniffed by the analyser in the final report.
-/// The conversations are organised in a table with rows: [time | ip_srg | prt_srg | ip_dest | prt_dest | protocol | tot_bytes | starting_time | ending_time | tot_packets ]
-/// sorted by starting_time.
-fn write_final_report(file: &mut File, convs_final: &HashMap<ConversationKey, ConversationStats>) {
-
-    let style = Style::rounded();
-    //let column_dim = 15;
-    let mut convs_printed = vec![];
-
-    //if !convs_final.is_empty()
-    {
-
-        // Creo un vettore in cui inserisco le conversazioni come tupla (Key, Stats)
-        let mut sorted_conv: Vec<(ConversationKey, ConversationStats)> = Vec::new();
-        for(key, elem) in convs_final {
-            sorted_conv.push((*key, *elem));
-        }
-        // ordino per starting_time
-        sorted_conv.sort_by(|a,b|
-            a.1.get_starting_time().cmp(&b.1.get_starting_time()));
-
-        for conv in sorted_conv {
-            let start = conv.1.get_starting_time().unwrap();
-            let end = conv.1.get_ending_time().unwrap();
-            let start_format = format!("{}.{} secs", start.as_secs(), start.as_millis());
-            let end_format = format!("{}.{} secs", end.as_secs(), end.as_millis());
-
-            // handle default values => replace with "-"
-            let prt_src;
-            let normalized_prt_src = match conv.0.get_prt_srg() {
-                0 => "-",
-                _ => {
-                    prt_src = conv.0.get_prt_srg().to_string();
-                    &prt_src
-                }
-            };
-            let prt_dst;
-            let normalized_prt_dst = match conv.0.get_prt_dest() {
-                0 => "-",
-                _ => {
-                    prt_dst = conv.0.get_prt_dest().to_string();
-                    &prt_dst
+/// If the packet belongs to a conversation already present in the map, it updates the stats, otherwise it adds a new record.
+/// The packet is sent in the forward direction if its source is the source of the key.
+/// - *tcp_events*: events detected on the packet by the TCP sequence analysis
+fn update_conversation(convs: &mut HashMap<ConversationKey, ConversationStats>, key: ConversationKey, packet_info: &PacketInfo, tcp_events: &TcpEvents) {
+    let ip_sorg = packet_info.get_ip_sorgente().unwrap();
+    let prt_sorg = packet_info.get_porta_sorgente();
+    let forward = key.get_ip_srg() == ip_sorg && key.get_prt_srg() == prt_sorg;
+
+    convs.entry(key)
+        .and_modify(|entry| {
+            entry.set_tot_bytes(packet_info.get_dim());
+            entry.set_ending_time(packet_info.get_time().unwrap());
+            entry.set_tot_packets(1);
+            entry.set_direction_stats(forward, packet_info.get_dim(), 1);
+            entry.set_ipv6_extensions(packet_info.get_ipv6_extensions());
+            if let Some(flags) = packet_info.get_tcp_flags() {
+                entry.set_tcp_flags(flags);
+                entry.set_tcp_events(tcp_events);
+            }
+        })
+        .or_insert_with(|| {
+            let mut stats = ConversationStats::new(
+                0,
+                packet_info.get_time().unwrap(),
+                packet_info.get_time().unwrap(),
+                0);
+            stats.set_tot_bytes(packet_info.get_dim());
+            stats.set_tot_packets(1);
+            stats.set_direction_stats(forward, packet_info.get_dim(), 1);
+            stats.set_initiator(ip_sorg, prt_sorg);
+            stats.set_ipv6_extensions(packet_info.get_ipv6_extensions());
+            if let Some(flags) = packet_info.get_tcp_flags() {
+                stats.set_tcp_flags(flags);
+                stats.set_tcp_events(tcp_events);
+            }
+            stats
+        });
+}
+
+/// Timer function. At each iteration it waits at most 'time interval' seconds. If a packet is received before the timeout it means that the status got to 'Quit'
+/// so also the timer need to return.
+/// Otherwise 'time_interval' seconds passed so the timer sets the 'status_writing_value' to 'true' to notify the reporter to write the report.
+fn timer(rcv_timer: Receiver<()>, time_interval: usize, status_writing: Arc<Mutex<bool>>)
+{
+    loop {
+         match rcv_timer.recv_timeout(Duration::from_secs(time_interval as u64)) {
+            Ok(_) => {
+                break;
+            },
+            Err(err) =>
+                if err == RecvTimeoutError::Timeout {
+                    let mut status_writing_value = status_writing.lock().unwrap();
+                    *status_writing_value = true;
                 }
-            };
-            let prtcl = match conv.0.get_protocol() {
-                Protocol::None => "-".to_string(),
-                _ => conv.0.get_protocol().to_string(),
-            };
-
-            let conv = ConvTabled::new(
-                "".to_string(),
-                conv.0.get_ip_srg().to_string(),
-                normalized_prt_src.to_string(),
-                conv.0.get_ip_dest().to_string(),
-                normalized_prt_dst.to_string(),
-                prtcl,
-                conv.1.get_tot_bytes().to_string(),
-                start_format,
-                end_format,
-                conv.1.get_tot_packets().to_string()
-            );
-
-            convs_printed.push(conv);
         }
-        let mut table = Table::new(convs_printed);
-        //let dim = table.shape();
-
-        //extract all the table except the first column
-        table = table.with(Disable::Column(0..1))
-            .with(Extract::segment(.., ..))
-
-            //set the style
-            .with(style)
-
-            //align at the center
-            .with(
-                Modify::new(Segment::all())
-                .with(Alignment::center())
-                .with(TrimStrategy::Horizontal)
-        );
-
-        //scrivo il report
-        write!(file, "{}\n", table.to_string()).expect("Error during the writing of the final report");
-
     }
-}
 
+}
 /// Check if the status is 'Pause'
 fn is_paused(state: &StatusValue) -> bool {
     return match state {
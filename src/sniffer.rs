@@ -1,81 +1,170 @@
 use std::sync::{Arc};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
-use pnet::packet::ethernet::{EthernetPacket};
-use std::time::{SystemTime};
-use pcap::{Active, Capture, Device};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use pcap::{Activated, Capture, Device, PacketHeader, Precision};
+use crate::dumper::Dumper;
+use crate::metrics::Metrics;
 use crate::packet_handle::PacketInfo;
+use crate::reassembly::Reassembler;
 use crate::{Filter, packet_handle, Status, StatusValue};
 
 /// Sniffer object.
-/// It gets raw packets from the 'Network Interface', handle them accordingly.
+/// It gets raw packets from a 'Network Interface' (or from a capture file), handle them accordingly.
+/// When more interfaces are sniffed there is a Sniffer for each of them, all sending to the same 'Reporter'.
 /// The most meaningful information extracted by each packet are saved in a 'PacketInfo' structure, then send to
 /// the 'Reporter' to update the report.
-///     - *interface*: Network interface,
+///     - *interface*: Network interface. None if the packets are read from a capture file,
 ///     - *filter*: filter selected by the user. The sniffing process needs to take it in consideration.
 ///     - *sender_channel*: sender end of the channel shared with the reporter. The sniffer sends a 'PacketInfo' for each packet that gets from the interface
-///     - *receiver_channel*: receiver end of the channel shared with the network interface. From this channel the Sniffer gets raw packets.
+///     - *receiver_channel*: receiver end of the channel shared with the network interface (or the capture file). From this channel the Sniffer gets raw packets.
 ///     - *status*: status of the application ['Running', 'Exit', 'Pause']
-///     - *time*: time on which the application started
+///     - *precision*: timestamp precision in effect on the capture
+///     - *dumper*: if set, it saves the packets got in a pcap savefile
+///     - *reassembler*: it reassembles the TCP streams, so that the application layer messages split across several segments are recognised
+///     - *duration*: if set, the sniffing ends after this time (of capture time, since the first packet, when reading a capture file)
+///     - *remaining_packets*: if set, number of packets still to be sniffed, shared by all the Sniffers. The sniffing ends when it reaches 0
+///     - *metrics*: if set, metrics of the metrics endpoint. The Sniffer counts the packets sent to the Reporter and, once per second, the packets dropped by the kernel
+///
+/// The time of each packet is taken from the header that the kernel/pcap associate to it (nanosecond precision, or microsecond if the device does not support it), not from the moment it gets handled.
 pub struct Sniffer {
-    interface: Device,
+    interface: Option<Device>,
     filter: Filter,
     //sender channel to send packet_infos to the reporter
     sender_channel: Sender<PacketInfo>,
 
-    //receiver channel to receive raw_packet from the lvl2 interface
-    receiver_channel: Capture<Active>,
+    //receiver channel to receive raw_packet from the lvl2 interface or from the capture file
+    receiver_channel: Capture<dyn Activated>,
 
     status: Arc<Status>,
-    time: SystemTime,
+    precision: Precision,
+    dumper: Option<Dumper>,
+    reassembler: Reassembler,
+    duration: Option<Duration>,
+    remaining_packets: Option<Arc<AtomicUsize>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+/// Options of a Sniffer, beyond its input and its output.
+///     - *precision*: timestamp precision in effect on the capture (microseconds if the device does not support nanoseconds)
+///     - *dumper*: if set, it saves the packets got in a pcap savefile
+///     - *reassembler*: it reassembles the TCP streams
+///     - *duration*: if set, the sniffing ends after this time
+///     - *remaining_packets*: if set, number of packets still to be sniffed, shared by all the Sniffers
+///     - *metrics*: if set, metrics of the metrics endpoint
+pub struct SnifferOptions {
+    pub precision: Precision,
+    pub dumper: Option<Dumper>,
+    pub reassembler: Reassembler,
+    pub duration: Option<Duration>,
+    pub remaining_packets: Option<Arc<AtomicUsize>>,
+    pub metrics: Option<Arc<Metrics>>,
 }
 
 impl Sniffer {
     /// Create a new instance of the Sniffer object
-    ///     - *interface*: Network Interface,
+    ///     - *interface*: Network Interface. None if the packets are read from a capture file,
     ///     - *filter*: filter selected by the user. The sniffing process needs to take it in consideration.
     ///     - *sender_channel*: sender end of the channel shared with the reporter. The sniffer sends a 'PacketInfo' for each packet that gets from the interface
-    ///     - *receiver_channel*: receiver end of the channel shared with the network interface. From this channel the Sniffer gets raw packets.
+    ///     - *receiver_channel*: receiver end of the channel shared with the network interface (or the capture file). From this channel the Sniffer gets raw packets.
     ///     - *status*: status of the application ['Running', 'Exit', 'Pause']
-    ///     - *time*: time on which the application started
-    pub fn new(interface: Device, filter: Filter, sender_channel: Sender<PacketInfo>, receiver_channel: Capture<Active>, status: Arc<Status>, time: SystemTime) -> Self {
-        Self { interface, filter, sender_channel, receiver_channel, status, time }
+    ///     - *options*: timestamp precision, savefile, TCP reassembly, limits of the sniffing and metrics
+    pub fn new(interface: Option<Device>,
+               filter: Filter,
+               sender_channel: Sender<PacketInfo>,
+               receiver_channel: Capture<dyn Activated>,
+               status: Arc<Status>,
+               options: SnifferOptions,
+    ) -> Self {
+        let SnifferOptions { precision, dumper, reassembler, duration, remaining_packets, metrics } = options;
+        Self { interface, filter, sender_channel, receiver_channel, status, precision, dumper, reassembler, duration, remaining_packets, metrics }
     }
 
     /// Sniffing function.
     /// It can be called only once.
     /// At each iteration the Sniffer gets a packet from the Network Interface.
     /// In 'Running' state it handles it parsing it and extracting the information needed to create a PacketInfo object. Then it sends it through the channel to the Reporter.
-    /// In 'Pause' state it discards all the packets that it gets
+    /// In 'Pause' state it discards all the packets that it gets (when reading a capture file it waits until the process is resumed instead)
     /// In 'Exit' state it returns
+    /// When all the packets of the capture file have been read, or when the duration or the packet count is reached, it puts the application in 'Exit' state and returns
     pub fn sniffing(mut self) {
         let mut status;
+        // Start of the sniffing: wall clock time for the network interfaces, timestamp of the first packet for the capture files
+        let start = Instant::now();
+        let mut first_timestamp: Option<SystemTime> = None;
+
+        // The frames are parsed according to the link type of the capture (Ethernet, Linux cooked, raw IP, loopback, radiotap)
+        let linktype = self.receiver_channel.get_datalink();
+        // Last time the packets dropped by the kernel have been read for the metrics
+        let mut last_stats = start;
 
         loop {
+            self.update_dropped(&mut last_stats);
+
             // Get a packet from the interface
             match self.receiver_channel.next_packet() {
                 Ok(packet) => {
                     {  // Check the status of the application
-                        let status_value = self.status.mutex.lock().unwrap();
+                        let mut status_value = self.status.mutex.lock().unwrap();
+                        if self.interface.is_none() {
+                            // Reading a capture file nothing gets lost while paused: wait until the user resumes (or quits)
+                            status_value = self.status.cvar.wait_while(status_value, |s| *s == StatusValue::Paused).unwrap();
+                        }
                         status = *status_value;
                     }
 
                     match status {
                         StatusValue::Running => {
-                            // Packet arrival time
-                            let initial_time = SystemTime::elapsed(&self.time).expect("TIME ERROR");
+                            let timestamp = header_timestamp(packet.header, self.precision);
+
+                            // Check the limits of the sniffing
+                            if let Some(duration) = self.duration {
+                                let elapsed = match self.interface {
+                                    Some(_) => start.elapsed(),
+                                    None => timestamp.duration_since(*first_timestamp.get_or_insert(timestamp)).unwrap_or(Duration::ZERO),
+                                };
+                                if elapsed >= duration {
+                                    self.status.exit();
+                                    return;
+                                }
+                            }
+                            let last_packet = match &self.remaining_packets {
+                                Some(remaining) => match remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)) {
+                                    Ok(previous) => previous == 1,
+                                    // Another Sniffer got the last packet
+                                    Err(_) => return,
+                                },
+                                None => false,
+                            };
 
                             // Create a data structure to host the information got from the packet
                             let mut new_packet_info = PacketInfo::new();
 
-                            // Set arrival packet time
-                            PacketInfo::set_time(&mut new_packet_info, initial_time);
+                            // Set the packet timestamp, taken from the pcap header
+                            PacketInfo::set_timestamp(&mut new_packet_info, timestamp);
+                            // Set the interface the packet was sniffed on
+                            if let Some(interface) = &self.interface {
+                                PacketInfo::set_interface(&mut new_packet_info, interface.name.clone());
+                            }
 
-                            // Handle particular interfaces
-                            if !packet_handle::handle_particular_interfaces(&self.interface, packet.data, &mut new_packet_info, &self.filter) {
-                                packet_handle::handle_ethernet_frame(&EthernetPacket::new(packet.data).unwrap(), &mut new_packet_info, &self.filter);
+                            // Parse the frame
+                            packet_handle::handle_frame(linktype, packet.data, &mut new_packet_info, &self.filter, &mut self.reassembler);
+                            // Save the raw packet in the savefile (all of them or only the ones accepted by the filter)
+                            if let Some(dumper) = self.dumper.as_mut() {
+                                if !dumper.get_only_filtered() || new_packet_info.get_printed() {
+                                    dumper.dump(&packet, timestamp);
+                                }
                             }
                             // Send the packet info to the Sniffer
+                            if let Some(metrics) = &self.metrics {
+                                metrics.packet_sent();
+                            }
                             self.sender_channel.send(new_packet_info).unwrap();
+
+                            if last_packet {
+                                self.status.exit();
+                                return;
+                            }
                         }
                         StatusValue::Paused => {
                             // Discard all the packets got. (If the reporter gets actually paused the packets that arrive in the meanwhile would be put in the channel buffer, but what we want
@@ -88,7 +177,24 @@ impl Sniffer {
                     }
 
                 }
+                Err(pcap::Error::NoMorePackets) => {
+                    // End of the capture file: put the application in 'Exit' state so that the Reporter writes the final report
+                    self.status.exit();
+                    return;
+                }
+                Err(err) if self.interface.is_none() => {
+                    // The capture file cannot be read any further (e.g. it is truncated): end the sniffing, reporting the packets read so far
+                    println!("> [Error]: cannot read the capture file: {}", err);
+                    self.status.exit();
+                    return;
+                }
                 Err(_) => {
+                    // No packet arrived: the duration may be over anyway
+                    if self.duration.is_some_and(|duration| start.elapsed() >= duration) {
+                        self.status.exit();
+                        return;
+                    }
+
                     {  // Check the status of the application
                         let status_value = self.status.mutex.lock().unwrap();
                         status = *status_value;
@@ -104,4 +210,51 @@ impl Sniffer {
            }
         }
     }
-}
\ No newline at end of file
+
+    /// It updates the metrics with the number of packets dropped by the kernel and by the network interface, at most once per second.
+    /// The capture files have no such statistics.
+    fn update_dropped(&mut self, last_stats: &mut Instant) {
+        if let (Some(metrics), Some(interface)) = (&self.metrics, &self.interface) {
+            if last_stats.elapsed() >= Duration::from_secs(1) {
+                *last_stats = Instant::now();
+                if let Ok(stats) = self.receiver_channel.stats() {
+                    metrics.set_dropped(&interface.name, stats.dropped as u64 + stats.if_dropped as u64);
+                }
+            }
+        }
+    }
+}
+
+/// Convert the timestamp of a pcap packet header in a SystemTime.
+/// The 'tv_usec' field of the header contains nanoseconds when the capture has nanosecond precision, microseconds otherwise.
+fn header_timestamp(header: &PacketHeader, precision: Precision) -> SystemTime {
+    let fraction = match precision {
+        Precision::Nano => header.ts.tv_usec as u32,
+        Precision::Micro => header.ts.tv_usec as u32 * 1000,
+    };
+    return UNIX_EPOCH + Duration::new(header.ts.tv_sec as u64, fraction);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// It returns a packet header with the timestamp 'secs' seconds and 'fraction' micro or nanoseconds after the UNIX epoch
+    fn header(secs: i64, fraction: i64) -> PacketHeader {
+        // SAFETY: the header is plain data, valid when zeroed
+        let mut header: PacketHeader = unsafe { std::mem::zeroed() };
+        header.ts.tv_sec = secs as _;
+        header.ts.tv_usec = fraction as _;
+        return header;
+    }
+
+    #[test]
+    fn header_timestamp_follows_the_precision_of_the_capture() {
+        let nano = header_timestamp(&header(10, 500), Precision::Nano);
+        assert_eq!(nano, UNIX_EPOCH + Duration::new(10, 500));
+        let micro = header_timestamp(&header(10, 500), Precision::Micro);
+        assert_eq!(micro, UNIX_EPOCH + Duration::new(10, 500_000));
+        let micro = header_timestamp(&header(10, 999_999), Precision::Micro);
+        assert_eq!(micro, UNIX_EPOCH + Duration::new(10, 999_999_000));
+    }
+}
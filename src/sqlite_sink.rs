@@ -0,0 +1,304 @@
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rusqlite::{params, Connection};
+use crate::events::{Conversation, FinalSummary, IntervalSummary};
+use crate::Protocol;
+use crate::report_sink::{ReportSink, SessionInfo};
+
+/// Tables of the database, created if they do not exist yet.
+/// - *sessions*: a row for each sniffing session. 'end_time', 'initial_time' and 'packets' are set at the end of the session
+/// - *conversations*: a row for each conversation of each time interval of a session
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    start_time REAL NOT NULL,
+    end_time REAL,
+    initial_time REAL,
+    interfaces TEXT,
+    capture_file TEXT,
+    filter TEXT NOT NULL,
+    time_interval INTEGER NOT NULL,
+    bidirectional INTEGER NOT NULL,
+    vlan_conversations INTEGER NOT NULL,
+    packets INTEGER
+);
+CREATE TABLE IF NOT EXISTS conversations (
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    interval_start REAL,
+    interval_offset INTEGER NOT NULL,
+    ip_srg TEXT NOT NULL,
+    prt_srg INTEGER NOT NULL,
+    ip_dest TEXT NOT NULL,
+    prt_dest INTEGER NOT NULL,
+    protocol TEXT,
+    interface TEXT,
+    outer_vlan INTEGER NOT NULL,
+    inner_vlan INTEGER NOT NULL,
+    tot_bytes INTEGER NOT NULL,
+    tot_packets INTEGER NOT NULL,
+    starting_time REAL,
+    ending_time REAL,
+    duration REAL NOT NULL,
+    fwd_bytes INTEGER NOT NULL,
+    fwd_packets INTEGER NOT NULL,
+    bwd_bytes INTEGER NOT NULL,
+    bwd_packets INTEGER NOT NULL,
+    initiator_ip TEXT,
+    initiator_prt INTEGER,
+    ipv6_extensions TEXT,
+    tcp INTEGER NOT NULL,
+    syn_packets INTEGER NOT NULL,
+    fin_packets INTEGER NOT NULL,
+    rst_packets INTEGER NOT NULL,
+    retransmissions INTEGER NOT NULL,
+    out_of_order INTEGER NOT NULL,
+    dup_acks INTEGER NOT NULL,
+    zero_windows INTEGER NOT NULL,
+    tcp_end TEXT,
+    handshake_rtt REAL,
+    rtt_min REAL,
+    rtt_avg REAL,
+    rtt_max REAL
+);
+CREATE INDEX IF NOT EXISTS conversations_session ON conversations(session_id, interval_offset);
+";
+
+/// SqliteSink object. It stores the conversations of every time interval in a SQLite database, so that the captures can be queried with SQL.
+/// The database is appended across runs: each run adds a row in the 'sessions' table (network interfaces or capture file, filter, start time, ...)
+/// and its conversations reference it, so that more sessions can be compared.
+/// The times are stored as seconds (with fractional part) since the UNIX epoch, e.g. 'datetime(starting_time, 'unixepoch')' converts them.
+/// - *connection*: connection to the database
+/// - *session_id*: id of the row of the current session (None until 'begin()' is called)
+pub struct SqliteSink {
+    connection: Connection,
+    session_id: Option<i64>,
+}
+
+impl SqliteSink {
+    /// Open (or create) the database, creating its tables if they do not exist.
+    /// It returns an error if the database cannot be opened.
+    /// - *path*: path of the database file
+    pub fn open(path: &str) -> io::Result<Self> {
+        let connection = Connection::open(path).map_err(to_io_error)?;
+        connection.execute_batch(SCHEMA).map_err(to_io_error)?;
+        return Ok(SqliteSink { connection, session_id: None });
+    }
+}
+
+impl ReportSink for SqliteSink {
+    fn begin(&mut self, session: &SessionInfo) -> io::Result<()> {
+        self.connection.execute(
+            "INSERT INTO sessions (start_time, interfaces, capture_file, filter, time_interval, bidirectional, vlan_conversations)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                epoch_secs(session.get_start_time()),
+                if session.get_interfaces().is_empty() { None } else { Some(session.get_interfaces().join(",")) },
+                session.get_capture_file(),
+                session.get_filter().to_string(),
+                session.get_time_interval() as i64,
+                session.is_bidirectional(),
+                session.get_vlan_conversations(),
+            ],
+        ).map_err(to_io_error)?;
+        self.session_id = Some(self.connection.last_insert_rowid());
+        return Ok(());
+    }
+
+    fn write_interval(&mut self, summary: &IntervalSummary) -> io::Result<()> {
+        let session_id = match self.session_id {
+            Some(session_id) => session_id,
+            None => return Err(io::Error::other("the session has not begun")),
+        };
+        let interval_start = summary.get_initial_time()
+            .map(|time| epoch_secs(time + Duration::from_secs(summary.get_interval_start())));
+
+        // All the conversations of the time interval are inserted in a single transaction
+        let transaction = self.connection.transaction().map_err(to_io_error)?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO conversations VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19,
+                                                  ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32, ?33, ?34, ?35, ?36)"
+            ).map_err(to_io_error)?;
+            for conversation in summary.get_conversations() {
+                insert_conversation(&mut statement, session_id, interval_start, summary.get_interval_start(), summary.get_initial_time(), conversation)
+                    .map_err(to_io_error)?;
+            }
+        }
+        return transaction.commit().map_err(to_io_error);
+    }
+
+    fn write_final(&mut self, summary: &FinalSummary) -> io::Result<()> {
+        if let Some(session_id) = self.session_id {
+            self.connection.execute(
+                "UPDATE sessions SET end_time = ?1, initial_time = ?2, packets = ?3 WHERE id = ?4",
+                params![
+                    epoch_secs(SystemTime::now()),
+                    summary.get_initial_time().map(epoch_secs),
+                    summary.get_packets() as i64,
+                    session_id,
+                ],
+            ).map_err(to_io_error)?;
+        }
+        return Ok(());
+    }
+}
+
+/// It inserts the row of a conversation of a time interval.
+/// The starting and ending times are absolute, computed from the initial time of the sniffing.
+fn insert_conversation(statement: &mut rusqlite::CachedStatement,
+                       session_id: i64,
+                       interval_start: Option<f64>,
+                       interval_offset: u64,
+                       initial_time: Option<SystemTime>,
+                       conversation: &Conversation,
+) -> rusqlite::Result<usize> {
+    let key = conversation.get_key();
+    let stats = conversation.get_stats();
+    let [outer_vlan, inner_vlan] = key.get_vlan_ids();
+    let absolute = |time: Option<Duration>| match (initial_time, time) {
+        (Some(initial_time), Some(time)) => Some(epoch_secs(initial_time + time)),
+        _ => None,
+    };
+    let secs = |time: Option<Duration>| time.map(|time| time.as_secs_f64());
+    return statement.execute(params![
+        session_id,
+        interval_start,
+        interval_offset as i64,
+        key.get_ip_srg().to_string(),
+        key.get_prt_srg(),
+        key.get_ip_dest().to_string(),
+        key.get_prt_dest(),
+        match key.get_protocol() {
+            Protocol::None => None,
+            protocol => Some(protocol.to_string()),
+        },
+        conversation.get_interface(),
+        outer_vlan,
+        inner_vlan,
+        stats.get_tot_bytes() as i64,
+        stats.get_tot_packets() as i64,
+        absolute(stats.get_starting_time()),
+        absolute(stats.get_ending_time()),
+        stats.get_duration().unwrap_or_default().as_secs_f64(),
+        stats.get_fwd_bytes() as i64,
+        stats.get_fwd_packets() as i64,
+        stats.get_bwd_bytes() as i64,
+        stats.get_bwd_packets() as i64,
+        stats.get_initiator().map(|(ip, _)| ip.to_string()),
+        stats.get_initiator().map(|(_, prt)| prt),
+        if stats.get_ipv6_extensions().is_empty() { None } else { Some(stats.get_ipv6_extensions().to_string()) },
+        stats.is_tcp(),
+        stats.get_syn_packets() as i64,
+        stats.get_fin_packets() as i64,
+        stats.get_rst_packets() as i64,
+        stats.get_retransmissions() as i64,
+        stats.get_out_of_order() as i64,
+        stats.get_dup_acks() as i64,
+        stats.get_zero_windows() as i64,
+        conversation.get_tcp_state().map(|state| state.connection_end()),
+        secs(stats.get_handshake_rtt()),
+        secs(stats.get_rtt_min()),
+        secs(stats.get_rtt_avg()),
+        secs(stats.get_rtt_max()),
+    ]);
+}
+
+/// It returns the number of seconds (with fractional part) since the UNIX epoch
+fn epoch_secs(time: SystemTime) -> f64 {
+    return time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+}
+
+/// It converts an error of the database in an io::Error, the error type of the report sinks
+fn to_io_error(err: rusqlite::Error) -> io::Error {
+    return io::Error::other(err);
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::fs;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::path::PathBuf;
+    use super::*;
+    use crate::{ConversationKey, Filter};
+    use crate::packet_handle::ConversationStats;
+
+    /// Row of the 'sessions' table: id, interfaces, capture_file, filter, time_interval, bidirectional, vlan_conversations,
+    /// start_time, initial_time, packets
+    type SessionRow = (i64, Option<String>, Option<String>, String, i64, bool, bool, f64, Option<f64>, Option<i64>);
+
+    /// It returns a path in the temporary directory, unique for the test process
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("traffic_analyser_{}_{}", std::process::id(), name));
+    }
+
+    /// It returns the summary of a time interval with a single TCP conversation
+    fn interval(initial_time: SystemTime, interval_start: u64) -> IntervalSummary {
+        let key = ConversationKey::new_key(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                                           40000, 443, Protocol::Tcp);
+        let stats = ConversationStats::new(100, Duration::from_secs(interval_start), Duration::from_secs(interval_start + 1), 2);
+        let conversation = Conversation::new(key, stats, Some("eth0".to_string()), None);
+        return IntervalSummary::new(Some(initial_time), interval_start, vec![conversation], 0, 0, vec![]);
+    }
+
+    #[test]
+    fn every_session_is_appended_to_the_database() {
+        let path = temp_path("sessions.db");
+        let _ = fs::remove_file(&path);
+        let initial_time = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        let session = SessionInfo::new(vec!["eth0".to_string(), "eth1".to_string()], None, Filter::new(), 5, true, false, initial_time);
+        let mut sink = SqliteSink::open(path.to_str().unwrap()).unwrap();
+        sink.begin(&session).unwrap();
+        sink.write_interval(&interval(initial_time, 0)).unwrap();
+        sink.write_interval(&interval(initial_time, 5)).unwrap();
+        sink.write_final(&FinalSummary::new(Some(initial_time), 4, vec![], 0, 0, vec![], vec![])).unwrap();
+        drop(sink);
+
+        let session = SessionInfo::new(vec![], Some("capture.pcap".to_string()), Filter::new(), 10, false, true, initial_time);
+        let mut sink = SqliteSink::open(path.to_str().unwrap()).unwrap();
+        sink.begin(&session).unwrap();
+        sink.write_interval(&interval(initial_time, 0)).unwrap();
+        drop(sink);
+
+        let connection = Connection::open(&path).unwrap();
+        let columns: i64 = connection.query_row("SELECT COUNT(*) FROM pragma_table_info('conversations')", [], |row| row.get(0)).unwrap();
+        assert_eq!(columns, 36);
+
+        let mut statement = connection.prepare(
+            "SELECT id, interfaces, capture_file, filter, time_interval, bidirectional, vlan_conversations, start_time, initial_time, packets
+             FROM sessions ORDER BY id"
+        ).unwrap();
+        let sessions: Vec<SessionRow> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?,
+                                     row.get(7)?, row.get(8)?, row.get(9)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].1.as_deref(), Some("eth0,eth1"));
+        assert_eq!(sessions[0].2, None);
+        assert_eq!(sessions[0].3, Filter::new().to_string());
+        assert_eq!((sessions[0].4, sessions[0].5, sessions[0].6), (5, true, false));
+        assert_eq!(sessions[0].7, 1_000_000.0);
+        assert_eq!((sessions[0].8, sessions[0].9), (Some(1_000_000.0), Some(4)));
+        assert_eq!((sessions[1].1.as_deref(), sessions[1].2.as_deref()), (None, Some("capture.pcap")));
+        assert_eq!((sessions[1].4, sessions[1].5, sessions[1].6), (10, false, true));
+        // The second session has not ended
+        assert_eq!((sessions[1].8, sessions[1].9), (None, None));
+
+        let conversations: Vec<(i64, i64, f64, String)> = connection
+            .prepare("SELECT session_id, interval_offset, starting_time, protocol FROM conversations ORDER BY session_id, interval_offset").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(conversations, [
+            (sessions[0].0, 0, 1_000_000.0, "TCP".to_string()),
+            (sessions[0].0, 5, 1_000_005.0, "TCP".to_string()),
+            (sessions[1].0, 0, 1_000_000.0, "TCP".to_string()),
+        ]);
+        drop(statement);
+        drop(connection);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,377 @@
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+use tabled::{Table, Tabled, Style, Width, Modify, Disable, Alignment, Extract};
+use tabled::object::{ Columns,  Object, Segment};
+use tabled::formatting_settings::TrimStrategy;
+use crate::events::{Conversation, FinalSummary, IntervalSummary, NonIpCount};
+use crate::packet_handle::{format_ethertype, MacConversationKey, MacConversationStats};
+use crate::Protocol;
+use crate::report_sink::{ReportSink, SessionInfo};
+
+#[derive(Tabled)]
+struct ConvTabled{
+    time: String,
+    ip_srg: String,
+    prt_srg: String,
+    ip_dest: String,
+    prt_dest: String,
+    protocol: String,
+    interface: String,
+    vlan: String,
+    tot_bytes: String,
+    starting_time: String,
+    ending_time: String,
+    tot_packets: String,
+    duration: String,
+    ipv6_extensions: String,
+    syn_packets: String,
+    fin_packets: String,
+    rst_packets: String,
+    retransmissions: String,
+    out_of_order: String,
+    dup_acks: String,
+    zero_windows: String,
+    tcp_end: String,
+    handshake_rtt: String,
+    rtt_min: String,
+    rtt_avg: String,
+    rtt_max: String,
+    initiator: String,
+    fwd_bytes: String,
+    bwd_bytes: String,
+    fwd_packets: String,
+    bwd_packets: String,
+}
+/// Index of the column shown only if more network interfaces are sniffed
+const INTERFACE_COLUMN: usize = 6;
+/// Index of the column shown only if the conversations are distinguished by VLAN
+const VLAN_COLUMN: usize = 7;
+/// Index of the first column shown only in the final report (round trip times)
+const RTT_COLUMNS: usize = 22;
+/// Index of the first column shown only if the conversations are aggregated in both directions
+const BIDIRECTIONAL_COLUMNS: usize = 26;
+
+impl ConvTabled{
+    /// Create the row of the table describing a conversation
+    fn new(time: String, conversation: &Conversation) -> ConvTabled{
+        let key = conversation.get_key();
+        let stats = conversation.get_stats();
+        // TCP columns are meaningful only for TCP conversations
+        let tcp_count = |count: usize| if stats.is_tcp() { count.to_string() } else { "-".to_string() };
+        ConvTabled{
+            time,
+            ip_srg: key.get_ip_srg().to_string(),
+            prt_srg: format_port(key.get_prt_srg()),
+            ip_dest: key.get_ip_dest().to_string(),
+            prt_dest: format_port(key.get_prt_dest()),
+            protocol: match key.get_protocol() {
+                Protocol::None => "-".to_string(),
+                _ => key.get_protocol().to_string(),
+            },
+            interface: conversation.get_interface().unwrap_or("-").to_string(),
+            vlan: match key.get_vlan_ids() {
+                [0, 0] => "-".to_string(),
+                [outer, 0] => outer.to_string(),
+                [outer, inner] => format!("{}.{}", outer, inner),
+            },
+            tot_bytes: stats.get_tot_bytes().to_string(),
+            starting_time: format_secs(stats.get_starting_time().unwrap()),
+            ending_time: format_secs(stats.get_ending_time().unwrap()),
+            tot_packets: stats.get_tot_packets().to_string(),
+            duration: format_secs(stats.get_duration().unwrap()),
+            ipv6_extensions: match stats.get_ipv6_extensions() {
+                extensions if extensions.is_empty() => "-".to_string(),
+                extensions => extensions.to_string(),
+            },
+            syn_packets: tcp_count(stats.get_syn_packets()),
+            fin_packets: tcp_count(stats.get_fin_packets()),
+            rst_packets: tcp_count(stats.get_rst_packets()),
+            retransmissions: tcp_count(stats.get_retransmissions()),
+            out_of_order: tcp_count(stats.get_out_of_order()),
+            dup_acks: tcp_count(stats.get_dup_acks()),
+            zero_windows: tcp_count(stats.get_zero_windows()),
+            tcp_end: match conversation.get_tcp_state() {
+                Some(state) => state.connection_end().to_string(),
+                None => "-".to_string(),
+            },
+            handshake_rtt: format_rtt(stats.get_handshake_rtt()),
+            rtt_min: format_rtt(stats.get_rtt_min()),
+            rtt_avg: format_rtt(stats.get_rtt_avg()),
+            rtt_max: format_rtt(stats.get_rtt_max()),
+            initiator: match stats.get_initiator() {
+                Some((ip, prt)) => format!("{}:{}", ip, format_port(prt)),
+                None => "-".to_string(),
+            },
+            fwd_bytes: stats.get_fwd_bytes().to_string(),
+            bwd_bytes: stats.get_bwd_bytes().to_string(),
+            fwd_packets: stats.get_fwd_packets().to_string(),
+            bwd_packets: stats.get_bwd_packets().to_string(),
+        }
+    }
+}
+
+#[derive(Tabled)]
+struct MacConvTabled{
+    mac_srg: String,
+    mac_dest: String,
+    ethertypes: String,
+    tot_bytes: String,
+    tot_packets: String,
+}
+
+impl MacConvTabled{
+    /// Create the row of the table describing a layer 2 conversation
+    fn new(key: &MacConversationKey, stats: &MacConversationStats) -> MacConvTabled{
+        MacConvTabled{
+            mac_srg: key.get_mac_srg().to_string(),
+            mac_dest: key.get_mac_dest().to_string(),
+            ethertypes: match stats.get_ethertypes() {
+                [] => "-".to_string(),
+                ethertypes => ethertypes.iter().map(|ethertype| format_ethertype(*ethertype)).collect::<Vec<String>>().join(", "),
+            },
+            tot_bytes: stats.get_tot_bytes().to_string(),
+            tot_packets: stats.get_tot_packets().to_string(),
+        }
+    }
+}
+
+/// Port shown in the report: default values (0) are replaced with "-"
+fn format_port(prt: u16) -> String {
+    return match prt {
+        0 => "-".to_string(),
+        _ => prt.to_string(),
+    };
+}
+
+/// Round trip time shown in the report, in milliseconds with microseconds ("-" if not measured)
+fn format_rtt(rtt: Option<Duration>) -> String {
+    return match rtt {
+        Some(rtt) => format!("{}.{:03} ms", rtt.as_millis(), rtt.subsec_micros() % 1000),
+        None => "-".to_string(),
+    };
+}
+
+/// Time shown in the report, in seconds with milliseconds
+fn format_secs(time: Duration) -> String {
+    return format!("{}.{:03} secs", time.as_secs(), time.subsec_millis());
+}
+
+/// TableSink object. It writes the reports as ASCII tables: every time interval is appended to the report file,
+/// while the final report file is written at the end of the sniffing.
+/// - *file*: report file, created (truncated) when the sink is created
+/// - *final_filename*: name of the final report file (.txt)
+/// - *write_titles*: whether the titles of the report table still need to be written
+/// - *show_interfaces*: whether the network interface of each conversation is shown (more of them are sniffed)
+/// - *bidirectional*: whether the bytes and packets in each direction and the initiator of each conversation are shown
+/// - *vlan_conversations*: whether the VLAN IDs of each conversation are shown
+pub struct TableSink {
+    file: File,
+    final_filename: String,
+    write_titles: bool,
+    show_interfaces: bool,
+    bidirectional: bool,
+    vlan_conversations: bool,
+}
+
+impl TableSink {
+    /// Create a new TableSink object instance, creating the report file.
+    /// It returns an error if the report file cannot be created.
+    /// - *filename*: name of the report file (.txt)
+    /// - *final_filename*: name of the final report file (.txt)
+    pub fn new(filename: String, final_filename: String) -> io::Result<Self> {
+        return Ok(TableSink {
+            file: open_file(&filename)?,
+            final_filename,
+            write_titles: true,
+            show_interfaces: false,
+            bidirectional: false,
+            vlan_conversations: false,
+        });
+    }
+}
+
+impl ReportSink for TableSink {
+    fn begin(&mut self, session: &SessionInfo) -> io::Result<()> {
+        self.show_interfaces = session.get_interfaces().len() > 1;
+        self.bidirectional = session.is_bidirectional();
+        self.vlan_conversations = session.get_vlan_conversations();
+        return Ok(());
+    }
+
+    fn write_interval(&mut self, summary: &IntervalSummary) -> io::Result<()> {
+        if !summary.get_conversations().is_empty() {
+            write_summaries(&mut self.file, summary.get_conversations(), summary.get_interval_start(), self.write_titles, self.show_interfaces, self.bidirectional, self.vlan_conversations)?;
+            // Write titles only the first time.
+            self.write_titles = false;
+        }
+        write_fragments(&mut self.file, summary.get_reassembled(), summary.get_discarded())?;
+        return write_non_ip(&mut self.file, summary.get_non_ip());
+    }
+
+    fn write_final(&mut self, summary: &FinalSummary) -> io::Result<()> {
+        println!("> Writing final report in {} ...", self.final_filename);
+        let mut final_file = open_file(&self.final_filename)?;
+        write_final_report(&mut final_file, summary.get_conversations(), self.show_interfaces, self.bidirectional, self.vlan_conversations)?;
+        write_fragments(&mut final_file, summary.get_reassembled(), summary.get_discarded())?;
+        write_non_ip(&mut final_file, summary.get_non_ip())?;
+        return write_mac_conversations(&mut final_file, summary.get_mac_conversations(), self.bidirectional);
+    }
+}
+
+/// Given a file name returns the handle of the opened file.
+fn open_file(filename: &String) -> io::Result<File> {
+    return File::options().write(true).truncate(true).create(true).open(filename);
+}
+
+
+/// It writes the conversations of a time interval (sorted by starting_time) in the file appending at the end of the file.
+/// The conversations are organised in a table with rows: [time | ip_srg | prt_srg | ip_dest | prt_dest | protocol | (interface) | (vlan) | tot_bytes | starting_time | ending_time | tot_packets | duration | ipv6_extensions | syn_packets | fin_packets | rst_packets | retransmissions | out_of_order | dup_acks | zero_windows | tcp_end ]
+/// The 'tcp_end' column shows how the TCP connection ended (at the moment of the writing). The 'time' column contains 'interval_start', the second on which the time interval began.
+/// If the conversations are aggregated in both directions the rows contain also [initiator | fwd_bytes | bwd_bytes | fwd_packets | bwd_packets].
+fn write_summaries(file: &mut File, conversations: &[Conversation], interval_start: u64, write_titles: bool, show_interfaces: bool, bidirectional: bool, vlan_conversations: bool) -> io::Result<()> {
+
+    let secs_str : String = interval_start.to_string();
+
+    // values for print the report table
+    let style = Style::ascii();
+    let column_dim = 30;
+
+    let convs_printed: Vec<ConvTabled> = conversations.iter()
+        .map(|conversation| ConvTabled::new(secs_str.clone(), conversation))
+        .collect();
+
+    let mut table = Table::new(convs_printed);
+
+    //show the stats of each direction only if the conversations are bidirectional
+    if !bidirectional {
+        table = table.with(Disable::Column(BIDIRECTIONAL_COLUMNS..))
+    }
+    //the round trip times are shown only in the final report
+    table = table.with(Disable::Column(RTT_COLUMNS..BIDIRECTIONAL_COLUMNS));
+    //show the VLAN IDs only if the conversations are distinguished by VLAN
+    if !vlan_conversations {
+        table = table.with(Disable::Column(VLAN_COLUMN..VLAN_COLUMN + 1))
+    }
+    //show the network interfaces only if more of them are sniffed
+    if !show_interfaces {
+        table = table.with(Disable::Column(INTERFACE_COLUMN..INTERFACE_COLUMN + 1))
+    }
+
+    //write the header only the first time
+    if !write_titles {
+        table = table.with(Disable::Row(0..1))
+    }
+
+    //set the style
+    table = table.with(style.clone())
+
+        //set the minimum dimension of all the columns
+        .with(Width::justify(column_dim/2))
+
+        //except the address ip ones
+        .with(Modify::new(Columns::single(1).and(Columns::single(3))).with(Width::increase(column_dim)))
+
+        //align at the middle
+        .with(
+            Modify::new(Segment::all())
+                .with(Alignment::center())
+                .with(TrimStrategy::Horizontal)
+    );
+
+    //scrivo il report
+    return writeln!(file, "{}", table);
+
+}
+/// Write all the conversations sniffed by the analyser (sorted by starting_time) in the final report.
+/// The conversations are organised in a table with rows: [ip_srg | prt_srg | ip_dest | prt_dest | protocol | (interface) | (vlan) | tot_bytes | starting_time | ending_time | tot_packets | duration | ipv6_extensions | syn_packets | fin_packets | rst_packets | retransmissions | out_of_order | dup_acks | zero_windows | tcp_end | handshake_rtt | rtt_min | rtt_avg | rtt_max ]
+/// The 'tcp_end' column shows how the TCP connection ended.
+/// If the conversations are aggregated in both directions the rows contain also [initiator | fwd_bytes | bwd_bytes | fwd_packets | bwd_packets].
+fn write_final_report(file: &mut File, conversations: &[Conversation], show_interfaces: bool, bidirectional: bool, vlan_conversations: bool) -> io::Result<()> {
+
+    let style = Style::rounded();
+
+    let convs_printed: Vec<ConvTabled> = conversations.iter()
+        .map(|conversation| ConvTabled::new("".to_string(), conversation))
+        .collect();
+    let mut table = Table::new(convs_printed);
+
+    //show the stats of each direction only if the conversations are bidirectional
+    if !bidirectional {
+        table = table.with(Disable::Column(BIDIRECTIONAL_COLUMNS..))
+    }
+    //show the VLAN IDs only if the conversations are distinguished by VLAN
+    if !vlan_conversations {
+        table = table.with(Disable::Column(VLAN_COLUMN..VLAN_COLUMN + 1))
+    }
+    //show the network interfaces only if more of them are sniffed
+    if !show_interfaces {
+        table = table.with(Disable::Column(INTERFACE_COLUMN..INTERFACE_COLUMN + 1))
+    }
+
+    //extract all the table except the first column
+    table = table.with(Disable::Column(0..1))
+        .with(Extract::segment(.., ..))
+
+        //set the style
+        .with(style)
+
+        //align at the center
+        .with(
+            Modify::new(Segment::all())
+            .with(Alignment::center())
+            .with(TrimStrategy::Horizontal)
+    );
+
+    //scrivo il report
+    return writeln!(file, "{}", table);
+}
+
+/// It writes the number of fragmented datagrams reassembled and discarded (incompletely reassembled) after the table of the conversations.
+/// Nothing is written if no datagram was fragmented.
+fn write_fragments(file: &mut File, reassembled: usize, discarded: usize) -> io::Result<()> {
+    if reassembled == 0 && discarded == 0 {
+        return Ok(());
+    }
+    return write!(file, "Fragmented datagrams: {} reassembled, {} incomplete (discarded)\n\n", reassembled, discarded);
+}
+
+/// It writes the number of frames (and bytes) not carrying an IP packet, for each EtherType, after the table of the conversations.
+/// Nothing is written if all the frames carried an IP packet.
+fn write_non_ip(file: &mut File, non_ip: &[NonIpCount]) -> io::Result<()> {
+    if non_ip.is_empty() {
+        return Ok(());
+    }
+    let frames: usize = non_ip.iter().map(|count| count.get_frames()).sum();
+    let bytes: usize = non_ip.iter().map(|count| count.get_bytes()).sum();
+    let details: Vec<String> = non_ip.iter()
+        .map(|count| match count.get_ethertype() {
+            Some(ethertype) => format!("{}: {}", format_ethertype(ethertype), count.get_frames()),
+            None => format!("other: {}", count.get_frames()),
+        })
+        .collect();
+    return write!(file, "Non-IP frames: {} ({} bytes) [{}]\n\n", frames, bytes, details.join(", "));
+}
+
+/// It writes the table of the layer 2 conversations (between two MAC addresses) in the final report.
+/// The conversations are organised in a table with rows: [mac_srg | mac_dest | ethertypes | tot_bytes | tot_packets ].
+/// If the conversations are aggregated in both directions, the source is the lowest MAC address.
+/// Nothing is written if no frame carried the MAC addresses.
+fn write_mac_conversations(file: &mut File, mac_convs: &[(MacConversationKey, MacConversationStats)], bidirectional: bool) -> io::Result<()> {
+    if mac_convs.is_empty() {
+        return Ok(());
+    }
+    let convs_printed: Vec<MacConvTabled> = mac_convs.iter()
+        .map(|(key, stats)| MacConvTabled::new(key, stats))
+        .collect();
+    let table = Table::new(convs_printed)
+        .with(Style::rounded())
+        .with(
+            Modify::new(Segment::all())
+                .with(Alignment::center())
+                .with(TrimStrategy::Horizontal)
+        );
+
+    return write!(file, "\nEthernet conversations{}:\n{}\n", if bidirectional { " (both directions)" } else { "" }, table);
+}
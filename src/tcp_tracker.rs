@@ -0,0 +1,592 @@
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::net::IpAddr;
+use std::time::Duration;
+use pnet::packet::tcp::TcpFlags;
+use crate::packet_handle::{ConversationKey, PacketInfo, Protocol};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// State of a TCP connection, as seen from the packets sniffed.
+/// - *SynSent*: the client sent the SYN
+/// - *SynReceived*: the server answered with the SYN-ACK
+/// - *Established*: the handshake completed (or the connection was already open when the sniffing began)
+/// - *HalfClosed*: only one of the endpoints sent the FIN
+/// - *Closed*: both the endpoints sent the FIN
+/// - *Reset*: one of the endpoints sent a RST
+pub enum TcpState {
+    SynSent,
+    SynReceived,
+    Established,
+    HalfClosed,
+    Closed,
+    Reset,
+}
+
+impl TcpState {
+    /// It returns how the connection ended: "closed", "reset", "open" (still open)
+    /// or "half-open" (only one endpoint considers the connection open: the handshake did not complete or only one FIN was sent)
+    pub fn connection_end(&self) -> &'static str {
+        return match self {
+            TcpState::SynSent | TcpState::SynReceived | TcpState::HalfClosed => "half-open",
+            TcpState::Established => "open",
+            TcpState::Closed => "closed",
+            TcpState::Reset => "reset",
+        };
+    }
+}
+
+/// Time after its last packet a closed (or reset) connection is forgotten, as the TIME_WAIT state of the endpoints: the last ACKs and the retransmitted FINs still find it
+const CLOSED_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time after its last packet an idle connection is forgotten (e.g. its FIN or RST got lost)
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// Minimum time between two scans of the connections looking for the ones to forget
+const EVICTION_PERIOD: Duration = Duration::from_secs(10);
+
+/// Maximum number of segments remembered for each direction of a connection, used to tell retransmissions from out-of-order segments
+const MAX_RECENT_SEGMENTS: usize = 32;
+
+#[derive(Debug, Default, Copy, Clone)]
+/// Events detected on a TCP packet by the sequence analysis of its direction of the connection.
+/// - *retransmission*: the segment carries data already sent
+/// - *out_of_order*: the segment carries data never seen before, but it arrives after segments that follow it
+/// - *dup_ack*: the packet acknowledges again the same data, with the same window and without carrying data
+/// - *zero_window*: the packet advertises a zero window (the receiver cannot accept any more data)
+/// - *rtt*: round trip time sample, if the packet acknowledges data sent by the other endpoint (or completes the handshake)
+/// - *handshake_rtt*: round trip time of the handshake (SYN -> SYN-ACK -> ACK), if the packet completes it
+pub struct TcpEvents {
+    retransmission: bool,
+    out_of_order: bool,
+    dup_ack: bool,
+    zero_window: bool,
+    rtt: Option<Duration>,
+    handshake_rtt: Option<Duration>,
+}
+
+impl TcpEvents {
+    /// It returns whether the segment is a retransmission
+    pub fn is_retransmission(&self) -> bool { return self.retransmission }
+    /// It returns whether the segment arrived out of order
+    pub fn is_out_of_order(&self) -> bool { return self.out_of_order }
+    /// It returns whether the packet is a duplicate ACK
+    pub fn is_dup_ack(&self) -> bool { return self.dup_ack }
+    /// It returns whether the packet advertises a zero window
+    pub fn is_zero_window(&self) -> bool { return self.zero_window }
+    /// It returns the round trip time sample taken on the packet
+    pub fn get_rtt(&self) -> Option<Duration> { return self.rtt }
+    /// It returns the round trip time of the handshake, if the packet completes it
+    pub fn get_handshake_rtt(&self) -> Option<Duration> { return self.handshake_rtt }
+}
+
+/// Object used to follow the sequence numbers of one direction of a TCP connection.
+/// - *next_sequence*: sequence number following the highest one sent (None until the first segment)
+/// - *last_ack*: last (acknowledgement number, window size) sent
+/// - *recent_segments*: (sequence number, length) of the last segments sent (at most MAX_RECENT_SEGMENTS)
+/// - *unacked*: (sequence number following the segment, time) of the segments carrying data not acknowledged yet (at most MAX_RECENT_SEGMENTS).
+///   Following Karn's algorithm, the retransmitted segments are not used to take round trip time samples.
+#[derive(Default)]
+struct TcpFlow {
+    next_sequence: Option<u32>,
+    last_ack: Option<(u32, u16)>,
+    recent_segments: VecDeque<(u32, u32)>,
+    unacked: VecDeque<(u32, Duration)>,
+}
+
+impl TcpFlow {
+    /// Analyse a new packet sent in this direction, returning the events detected
+    /// - *flags*: TCP flags of the packet
+    /// - *sequence*, *acknowledgement*, *window*: sequence number, acknowledgement number and window size of the packet
+    /// - *payload_len*: size in bytes of the payload
+    /// - *time*: arrival time of the packet
+    fn update(&mut self, flags: u16, sequence: u32, acknowledgement: u32, window: u16, payload_len: usize, time: Duration) -> TcpEvents {
+        let mut events = TcpEvents::default();
+        if flags & TcpFlags::RST != 0 {
+            return events;
+        }
+
+        // SYN and FIN consume a sequence number as a byte of data
+        let mut segment_len = payload_len as u32;
+        if flags & TcpFlags::SYN != 0 { segment_len += 1; }
+        if flags & TcpFlags::FIN != 0 { segment_len += 1; }
+
+        if segment_len > 0 {
+            let segment_end = sequence.wrapping_add(segment_len);
+            match self.next_sequence {
+                Some(next) if seq_before(sequence, next) => {
+                    // Data behind the highest one sent: already seen (retransmitted) or filling a hole (out of order)
+                    let seen = self.recent_segments.iter()
+                        .any(|&(start, len)| !seq_before(sequence, start) && seq_before(sequence, start.wrapping_add(len)));
+                    if seen { events.retransmission = true; } else { events.out_of_order = true; }
+                    // The acknowledgements of the data sent again would be ambiguous
+                    self.unacked.clear();
+                }
+                _ => {
+                    self.next_sequence = Some(segment_end);
+                    if payload_len > 0 {
+                        if self.unacked.len() == MAX_RECENT_SEGMENTS {
+                            self.unacked.pop_front();
+                        }
+                        self.unacked.push_back((segment_end, time));
+                    }
+                }
+            }
+            if self.recent_segments.len() == MAX_RECENT_SEGMENTS {
+                self.recent_segments.pop_front();
+            }
+            self.recent_segments.push_back((sequence, segment_len));
+        }
+
+        if flags & TcpFlags::ACK != 0 {
+            let pure_ack = segment_len == 0;
+            if pure_ack && self.last_ack == Some((acknowledgement, window)) {
+                events.dup_ack = true;
+            }
+            self.last_ack = Some((acknowledgement, window));
+        }
+        events.zero_window = window == 0 && flags & TcpFlags::SYN == 0;
+
+        return events;
+    }
+
+    /// Remove the segments acknowledged by the other endpoint.
+    /// It returns the round trip time sample taken on the last segment acknowledged (None if no segment is acknowledged).
+    /// - *acknowledgement*: acknowledgement number sent by the other endpoint
+    /// - *time*: arrival time of the acknowledgement
+    fn acknowledge(&mut self, acknowledgement: u32, time: Duration) -> Option<Duration> {
+        let mut rtt = None;
+        while let Some(&(segment_end, sent)) = self.unacked.front() {
+            if seq_before(acknowledgement, segment_end) {
+                break;
+            }
+            rtt = Some(time.saturating_sub(sent));
+            self.unacked.pop_front();
+        }
+        return rtt;
+    }
+}
+
+/// It returns whether the sequence number 'a' comes before 'b', considering the wrap around of the sequence numbers
+fn seq_before(a: u32, b: u32) -> bool {
+    return (a.wrapping_sub(b) as i32) < 0;
+}
+
+/// Object used to follow a single TCP connection.
+/// - *client*: (ip address, port) of the endpoint that opened the connection
+/// - *state*: current state of the connection
+/// - *fin_client*, *fin_server*: whether the client (server) sent the FIN
+/// - *client_flow*, *server_flow*: sequence analysis of the packets sent by the client (server)
+/// - *syn_time*: arrival time of the SYN sent by the client
+/// - *last_seen*: arrival time of the last packet of the connection
+struct TcpConnection {
+    client: (IpAddr, u16),
+    state: TcpState,
+    syn_time: Option<Duration>,
+    last_seen: Duration,
+    fin_client: bool,
+    fin_server: bool,
+    client_flow: TcpFlow,
+    server_flow: TcpFlow,
+}
+
+impl TcpConnection {
+    /// Create a new TcpConnection object instance starting from its first packet sniffed
+    /// - *source*, *destination*: (ip address, port) of the source and the destination of the packet
+    /// - *flags*: TCP flags of the packet
+    /// - *time*: arrival time of the packet
+    fn new(source: (IpAddr, u16), destination: (IpAddr, u16), flags: u16, time: Duration) -> Self {
+        let syn = flags & TcpFlags::SYN != 0;
+        let ack = flags & TcpFlags::ACK != 0;
+
+        let (client, state) = match (syn, ack) {
+            (true, false) => (source, TcpState::SynSent),
+            // The SYN got lost (or was sent before the sniffing began): the SYN-ACK is sent by the server
+            (true, true) => (destination, TcpState::SynReceived),
+            // Connection already open when the sniffing began
+            _ => (source, TcpState::Established),
+        };
+
+        TcpConnection {
+            client,
+            state,
+            syn_time: None,
+            last_seen: time,
+            fin_client: false,
+            fin_server: false,
+            client_flow: TcpFlow::default(),
+            server_flow: TcpFlow::default(),
+        }
+    }
+
+    /// Update the connection with a new packet, returning the events detected by the sequence analysis of its direction
+    fn update(&mut self, packet_info: &PacketInfo) -> TcpEvents {
+        let source = (packet_info.get_ip_sorgente().unwrap(), packet_info.get_porta_sorgente());
+        let flags = packet_info.get_tcp_flags().unwrap();
+        let time = packet_info.get_time().unwrap();
+        let from_client = source == self.client;
+        self.last_seen = self.last_seen.max(time);
+
+        let (flow, other_flow) = if from_client {
+            (&mut self.client_flow, &mut self.server_flow)
+        } else {
+            (&mut self.server_flow, &mut self.client_flow)
+        };
+        let mut events = flow.update(flags,
+                                     packet_info.get_tcp_sequence(),
+                                     packet_info.get_tcp_acknowledgement(),
+                                     packet_info.get_tcp_window(),
+                                     packet_info.get_tcp_payload_len(),
+                                     time);
+        if flags & TcpFlags::ACK != 0 {
+            events.rtt = other_flow.acknowledge(packet_info.get_tcp_acknowledgement(), time);
+        }
+
+        // Handshake round trip time: from the SYN of the client to its ACK of the SYN-ACK
+        if from_client && flags & TcpFlags::SYN != 0 && flags & TcpFlags::ACK == 0 {
+            self.syn_time = Some(time);
+        }
+        let previous_state = self.state;
+        self.update_state(from_client, flags);
+        if previous_state == TcpState::SynReceived && self.state == TcpState::Established {
+            if let Some(syn_time) = self.syn_time {
+                let handshake_rtt = time.saturating_sub(syn_time);
+                events.handshake_rtt = Some(handshake_rtt);
+                events.rtt = Some(handshake_rtt);
+            }
+        }
+        return events;
+    }
+
+    /// It returns whether the connection has ended, closed or reset
+    fn ended(&self) -> bool {
+        return self.state == TcpState::Closed || self.state == TcpState::Reset;
+    }
+
+    /// It returns whether the connection can be forgotten at time 'now': closed since more than CLOSED_TIMEOUT or idle since more than IDLE_TIMEOUT
+    fn expired(&self, now: Duration) -> bool {
+        let idle = now.saturating_sub(self.last_seen);
+        return idle > IDLE_TIMEOUT || (self.ended() && idle > CLOSED_TIMEOUT);
+    }
+
+    /// Update the state of the connection with the flags of a new packet
+    /// - *from_client*: whether the packet is sent by the client
+    /// - *flags*: TCP flags of the packet
+    fn update_state(&mut self, from_client: bool, flags: u16) {
+        let syn = flags & TcpFlags::SYN != 0;
+        let ack = flags & TcpFlags::ACK != 0;
+
+        // Once ended, the state of the connection does not change anymore (e.g. the last ACK after the FINs)
+        if self.ended() {
+            return;
+        }
+        if flags & TcpFlags::RST != 0 {
+            self.state = TcpState::Reset;
+            return;
+        }
+        if flags & TcpFlags::FIN != 0 {
+            if from_client { self.fin_client = true; } else { self.fin_server = true; }
+        }
+
+        self.state = if self.fin_client && self.fin_server {
+            TcpState::Closed
+        } else if self.fin_client || self.fin_server {
+            TcpState::HalfClosed
+        } else {
+            match self.state {
+                TcpState::SynSent if syn && ack && !from_client => TcpState::SynReceived,
+                TcpState::SynReceived if ack && !syn && from_client => TcpState::Established,
+                state => state,
+            }
+        };
+    }
+}
+
+/// TcpTracker object. It follows the state of the TCP connections sniffed.
+/// Both the directions of a connection are tracked together, regardless of the application protocol recognised in each packet.
+/// The connections closed since more than CLOSED_TIMEOUT, or idle since more than IDLE_TIMEOUT, are forgotten so that the memory does not grow
+/// on a long sniffing: only their last state is kept, to report how they ended, until the next update of the report.
+/// - *connections*: state of each connection, identified by its normalized ConversationKey (protocol TCP)
+/// - *expired*: last state of the connections forgotten since the last update of the report
+/// - *last_eviction*: arrival time of the packet on which the connections have been last scanned looking for the ones to forget
+/// - *open_connections*: number of the connections tracked that have not ended (neither closed nor reset)
+pub struct TcpTracker {
+    connections: HashMap<ConversationKey, TcpConnection>,
+    expired: HashMap<ConversationKey, TcpState>,
+    last_eviction: Duration,
+    open_connections: usize,
+}
+
+impl TcpTracker {
+    /// Create a new TcpTracker object instance
+    pub fn new() -> Self {
+        return TcpTracker { connections: HashMap::new(), expired: HashMap::new(), last_eviction: Duration::ZERO, open_connections: 0 };
+    }
+
+    /// Update the connection the packet belongs to (if it is a TCP packet), returning the events detected on it.
+    /// A SYN received on a connection that already ended starts a new connection on the same ports.
+    pub fn update(&mut self, packet_info: &PacketInfo) -> TcpEvents {
+        let flags = match packet_info.get_tcp_flags() {
+            Some(flags) => flags,
+            None => return TcpEvents::default(),
+        };
+        let source = (packet_info.get_ip_sorgente().unwrap(), packet_info.get_porta_sorgente());
+        let destination = (packet_info.get_ip_destinazione().unwrap(), packet_info.get_porta_destinazione());
+        let key = connection_key(source.0, destination.0, source.1, destination.1);
+        let time = packet_info.get_time().unwrap();
+
+        let was_open = self.connections.get(&key).is_some_and(|connection| !connection.ended());
+        let connection = match self.connections.entry(key) {
+            Entry::Occupied(entry) => {
+                let connection = entry.into_mut();
+                if connection.ended() && flags & TcpFlags::SYN != 0 && flags & TcpFlags::ACK == 0 {
+                    *connection = TcpConnection::new(source, destination, flags, time);
+                }
+                connection
+            }
+            Entry::Vacant(entry) => entry.insert(TcpConnection::new(source, destination, flags, time)),
+        };
+        let events = connection.update(packet_info);
+        match (was_open, !connection.ended()) {
+            (false, true) => self.open_connections += 1,
+            (true, false) => self.open_connections -= 1,
+            _ => {}
+        }
+
+        if time.saturating_sub(self.last_eviction) >= EVICTION_PERIOD {
+            self.evict(time);
+        }
+        return events;
+    }
+
+    /// It returns the state of the TCP connection the conversation belongs to (None if it has not been tracked)
+    pub fn get_state(&self, key: &ConversationKey) -> Option<TcpState> {
+        let key = connection_key(key.get_ip_srg(), key.get_ip_dest(), key.get_prt_srg(), key.get_prt_dest());
+        return match self.connections.get(&key) {
+            Some(connection) => Some(connection.state),
+            None => self.expired.get(&key).copied(),
+        };
+    }
+
+    /// It returns the number of the connections tracked that have not ended (the idle ones are forgotten after IDLE_TIMEOUT)
+    pub fn get_open_connections(&self) -> usize {
+        return self.open_connections;
+    }
+
+    /// Drop the last state of the connections forgotten, once the report showing how they ended has been updated
+    pub fn clear_expired(&mut self) {
+        self.expired.clear();
+    }
+
+    /// Forget the connections expired at time 'now', keeping only their last state
+    fn evict(&mut self, now: Duration) {
+        self.last_eviction = now;
+        let expired = &mut self.expired;
+        let open_connections = &mut self.open_connections;
+        self.connections.retain(|key, connection| {
+            if connection.expired(now) {
+                if !connection.ended() {
+                    *open_connections -= 1;
+                }
+                expired.insert(*key, connection.state);
+                return false;
+            }
+            return true;
+        });
+    }
+}
+
+/// It returns the key identifying a TCP connection, the same for both its directions
+fn connection_key(ip_srg: IpAddr, ip_dest: IpAddr, prt_srg: u16, prt_dest: u16) -> ConversationKey {
+    return ConversationKey::new_key(ip_srg, ip_dest, prt_srg, prt_dest, Protocol::Tcp).normalized();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use super::*;
+
+    const CLIENT: (IpAddr, u16) = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 40000);
+    const SERVER: (IpAddr, u16) = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 80);
+
+    /// It creates the information of a TCP packet
+    fn packet(source: (IpAddr, u16), destination: (IpAddr, u16), flags: u16, sequence: u32, acknowledgement: u32, payload_len: usize, secs: u64) -> PacketInfo {
+        let mut packet_info = PacketInfo::new();
+        packet_info.set_ip_sorgente(source.0);
+        packet_info.set_porta_sorgente(source.1);
+        packet_info.set_ip_destinazione(destination.0);
+        packet_info.set_porta_destinazione(destination.1);
+        packet_info.set_protocol(Protocol::Tcp);
+        packet_info.set_tcp_flags(flags);
+        packet_info.set_tcp_segment(sequence, acknowledgement, 1000, payload_len);
+        packet_info.set_time(Duration::from_secs(secs));
+        return packet_info;
+    }
+
+    fn conversation() -> ConversationKey {
+        return ConversationKey::new_key(CLIENT.0, SERVER.0, CLIENT.1, SERVER.1, Protocol::Tcp);
+    }
+
+    #[test]
+    fn closed_connection_is_forgotten_after_the_grace_period() {
+        let mut tracker = TcpTracker::new();
+        tracker.update(&packet(CLIENT, SERVER, TcpFlags::SYN, 0, 0, 0, 0));
+        tracker.update(&packet(SERVER, CLIENT, TcpFlags::SYN | TcpFlags::ACK, 0, 1, 0, 0));
+        tracker.update(&packet(CLIENT, SERVER, TcpFlags::ACK, 1, 1, 0, 0));
+        assert_eq!(tracker.get_open_connections(), 1);
+        tracker.update(&packet(CLIENT, SERVER, TcpFlags::FIN | TcpFlags::ACK, 1, 1, 0, 1));
+        tracker.update(&packet(SERVER, CLIENT, TcpFlags::FIN | TcpFlags::ACK, 1, 2, 0, 1));
+        assert_eq!(tracker.get_state(&conversation()), Some(TcpState::Closed));
+        assert_eq!(tracker.get_open_connections(), 0);
+
+        // Still tracked within the grace period
+        tracker.update(&packet(CLIENT, SERVER, TcpFlags::ACK, 2, 2, 0, 30));
+        assert_eq!(tracker.connections.len(), 1);
+
+        // Forgotten on a packet of another connection, but its last state is kept
+        let other = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)), 50000);
+        tracker.update(&packet(other, SERVER, TcpFlags::SYN, 0, 0, 0, 30 + CLOSED_TIMEOUT.as_secs() + 1));
+        assert_eq!(tracker.connections.len(), 1);
+        assert_eq!(tracker.get_state(&conversation()), Some(TcpState::Closed));
+        assert_eq!(tracker.get_open_connections(), 1);
+    }
+
+    #[test]
+    fn idle_connection_is_forgotten() {
+        let mut tracker = TcpTracker::new();
+        tracker.update(&packet(CLIENT, SERVER, TcpFlags::ACK, 1, 1, 10, 0));
+        let other = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)), 50000);
+        tracker.update(&packet(other, SERVER, TcpFlags::ACK, 1, 1, 10, IDLE_TIMEOUT.as_secs()));
+        assert_eq!(tracker.connections.len(), 2);
+        assert_eq!(tracker.get_open_connections(), 2);
+        tracker.update(&packet(other, SERVER, TcpFlags::ACK, 11, 1, 10, IDLE_TIMEOUT.as_secs() + EVICTION_PERIOD.as_secs() + 1));
+        assert_eq!(tracker.connections.len(), 1);
+        assert_eq!(tracker.get_open_connections(), 1);
+        assert_eq!(tracker.get_state(&conversation()), Some(TcpState::Established));
+
+        // The last state is dropped once the report has been updated
+        tracker.clear_expired();
+        assert_eq!(tracker.get_state(&conversation()), None);
+
+        // A new packet starts tracking the connection again
+        tracker.update(&packet(CLIENT, SERVER, TcpFlags::ACK, 11, 1, 10, IDLE_TIMEOUT.as_secs() + EVICTION_PERIOD.as_secs() + 2));
+        assert_eq!(tracker.connections.len(), 2);
+    }
+
+    /// It sends a segment in the flow, at 'millis' milliseconds
+    fn send(flow: &mut TcpFlow, flags: u16, sequence: u32, acknowledgement: u32, window: u16, payload_len: usize, millis: u64) -> TcpEvents {
+        return flow.update(flags, sequence, acknowledgement, window, payload_len, Duration::from_millis(millis));
+    }
+
+    #[test]
+    fn in_order_segments_have_no_events() {
+        let mut flow = TcpFlow::default();
+        for i in 0..3 {
+            let events = send(&mut flow, TcpFlags::ACK | TcpFlags::PSH, 1 + i * 100, 1, 1000, 100, 0);
+            assert!(!events.is_retransmission() && !events.is_out_of_order() && !events.is_dup_ack() && !events.is_zero_window());
+        }
+        assert_eq!(flow.next_sequence, Some(301));
+    }
+
+    #[test]
+    fn data_sent_again_is_a_retransmission() {
+        let mut flow = TcpFlow::default();
+        send(&mut flow, TcpFlags::ACK, 1, 1, 1000, 100, 0);
+        send(&mut flow, TcpFlags::ACK, 101, 1, 1000, 100, 1);
+        let events = send(&mut flow, TcpFlags::ACK, 1, 1, 1000, 100, 2);
+        assert!(events.is_retransmission());
+        assert!(!events.is_out_of_order());
+        // Also a segment starting inside one already sent
+        assert!(send(&mut flow, TcpFlags::ACK, 150, 1, 1000, 10, 3).is_retransmission());
+    }
+
+    #[test]
+    fn data_filling_a_hole_is_out_of_order() {
+        let mut flow = TcpFlow::default();
+        send(&mut flow, TcpFlags::ACK, 1, 1, 1000, 100, 0);
+        // The segment 101-201 is overtaken by the following one
+        send(&mut flow, TcpFlags::ACK, 201, 1, 1000, 100, 1);
+        let events = send(&mut flow, TcpFlags::ACK, 101, 1, 1000, 100, 2);
+        assert!(events.is_out_of_order());
+        assert!(!events.is_retransmission());
+        assert_eq!(flow.next_sequence, Some(301));
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let mut flow = TcpFlow::default();
+        send(&mut flow, TcpFlags::ACK, u32::MAX - 49, 1, 1000, 100, 0);
+        assert_eq!(flow.next_sequence, Some(50));
+        assert!(!send(&mut flow, TcpFlags::ACK, 50, 1, 1000, 100, 1).is_out_of_order());
+        assert!(send(&mut flow, TcpFlags::ACK, u32::MAX - 49, 1, 1000, 100, 2).is_retransmission());
+    }
+
+    #[test]
+    fn same_pure_ack_is_a_dup_ack() {
+        let mut flow = TcpFlow::default();
+        assert!(!send(&mut flow, TcpFlags::ACK, 1, 500, 1000, 0, 0).is_dup_ack());
+        assert!(send(&mut flow, TcpFlags::ACK, 1, 500, 1000, 0, 1).is_dup_ack());
+        // A window update is not a duplicate ACK
+        assert!(!send(&mut flow, TcpFlags::ACK, 1, 500, 2000, 0, 2).is_dup_ack());
+        // A segment carrying data is not a duplicate ACK
+        assert!(!send(&mut flow, TcpFlags::ACK, 1, 500, 2000, 10, 3).is_dup_ack());
+        // A new acknowledgement is not a duplicate ACK
+        assert!(!send(&mut flow, TcpFlags::ACK, 11, 600, 2000, 0, 4).is_dup_ack());
+    }
+
+    #[test]
+    fn zero_window_is_detected_except_on_syn() {
+        let mut flow = TcpFlow::default();
+        assert!(!send(&mut flow, TcpFlags::SYN, 0, 0, 0, 0, 0).is_zero_window());
+        assert!(send(&mut flow, TcpFlags::ACK, 1, 1, 0, 0, 1).is_zero_window());
+        assert!(!send(&mut flow, TcpFlags::ACK, 1, 1, 100, 0, 2).is_zero_window());
+    }
+
+    #[test]
+    fn rst_has_no_events() {
+        let mut flow = TcpFlow::default();
+        send(&mut flow, TcpFlags::ACK, 1, 1, 0, 100, 0);
+        let events = send(&mut flow, TcpFlags::RST | TcpFlags::ACK, 1, 1, 0, 100, 1);
+        assert!(!events.is_retransmission() && !events.is_zero_window());
+    }
+
+    #[test]
+    fn rtt_is_sampled_on_the_acknowledged_segment() {
+        let mut flow = TcpFlow::default();
+        send(&mut flow, TcpFlags::ACK, 1, 1, 1000, 100, 0);
+        send(&mut flow, TcpFlags::ACK, 101, 1, 1000, 100, 10);
+        // Acknowledging only the first segment
+        assert_eq!(flow.acknowledge(101, Duration::from_millis(40)), Some(Duration::from_millis(40)));
+        // Nothing new acknowledged
+        assert_eq!(flow.acknowledge(101, Duration::from_millis(45)), None);
+        // Acknowledging the second one: the sample is taken on it
+        assert_eq!(flow.acknowledge(201, Duration::from_millis(60)), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn retransmitted_segments_are_not_sampled() {
+        // Karn's algorithm: the acknowledgement of a retransmitted segment is ambiguous
+        let mut flow = TcpFlow::default();
+        send(&mut flow, TcpFlags::ACK, 1, 1, 1000, 100, 0);
+        assert!(send(&mut flow, TcpFlags::ACK, 1, 1, 1000, 100, 200).is_retransmission());
+        assert_eq!(flow.acknowledge(101, Duration::from_millis(210)), None);
+        // The following segments are sampled again
+        send(&mut flow, TcpFlags::ACK, 101, 1, 1000, 100, 300);
+        assert_eq!(flow.acknowledge(201, Duration::from_millis(320)), Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn tracker_counts_events_of_both_directions_and_handshake_rtt() {
+        let mut tracker = TcpTracker::new();
+        tracker.update(&packet(CLIENT, SERVER, TcpFlags::SYN, 0, 0, 0, 0));
+        tracker.update(&packet(SERVER, CLIENT, TcpFlags::SYN | TcpFlags::ACK, 1000, 1, 0, 1));
+        let events = tracker.update(&packet(CLIENT, SERVER, TcpFlags::ACK, 1, 1001, 0, 3));
+        assert_eq!(events.get_handshake_rtt(), Some(Duration::from_secs(3)));
+        assert_eq!(tracker.get_state(&conversation()), Some(TcpState::Established));
+
+        tracker.update(&packet(CLIENT, SERVER, TcpFlags::ACK | TcpFlags::PSH, 1, 1001, 100, 4));
+        let events = tracker.update(&packet(SERVER, CLIENT, TcpFlags::ACK, 1001, 101, 0, 6));
+        assert_eq!(events.get_rtt(), Some(Duration::from_secs(2)));
+        assert!(tracker.update(&packet(SERVER, CLIENT, TcpFlags::ACK, 1001, 101, 0, 7)).is_dup_ack());
+        assert!(tracker.update(&packet(CLIENT, SERVER, TcpFlags::ACK | TcpFlags::PSH, 1, 1001, 100, 8)).is_retransmission());
+        assert!(tracker.update(&packet(CLIENT, SERVER, TcpFlags::RST, 101, 0, 0, 9)).get_rtt().is_none());
+        assert_eq!(tracker.get_state(&conversation()), Some(TcpState::Reset));
+    }
+}